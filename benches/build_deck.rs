@@ -0,0 +1,84 @@
+//! Benchmarks for building large decks
+//!
+//! Run with `cargo bench`. Card generation reuses each model's template
+//! requirements and compiled regexes, so note creation should scale linearly
+//! with deck size for both standard and cloze models.
+
+use criterion::{BenchmarkId, Criterion, Throughput, criterion_group, criterion_main};
+use genanki_rs_rev::{
+    Deck, Note, Package, basic_and_reversed_card_model, basic_model, cloze_model,
+};
+use std::collections::HashMap;
+use std::hint::black_box;
+
+fn build_deck(num_notes: usize) -> Deck {
+    let basic = basic_model();
+    let reversed = basic_and_reversed_card_model();
+    let mut deck = Deck::new(2059400110, "Benchmark", "");
+    for i in 0..num_notes {
+        let model = if i % 2 == 0 { &basic } else { &reversed };
+        let front = format!("Question {i}");
+        let back = format!("Answer {i}");
+        deck.add_note(Note::new(model.clone(), vec![&front, &back]).unwrap());
+    }
+    deck
+}
+
+fn build_cloze_deck(num_notes: usize) -> Deck {
+    let cloze = cloze_model();
+    let mut deck = Deck::new(2059400111, "Cloze Benchmark", "");
+    for i in 0..num_notes {
+        let text = format!("{{{{c1::Question {i}}}}} has {{{{c2::answer {i}}}}}");
+        deck.add_note(Note::new(cloze.clone(), vec![&text]).unwrap());
+    }
+    deck
+}
+
+fn bench_create_notes(c: &mut Criterion) {
+    let mut group = c.benchmark_group("create_notes");
+    for num_notes in [1_000, 10_000, 50_000] {
+        group.throughput(Throughput::Elements(num_notes as u64));
+        group.bench_with_input(
+            BenchmarkId::from_parameter(num_notes),
+            &num_notes,
+            |b, &n| b.iter(|| black_box(build_deck(n))),
+        );
+    }
+    group.finish();
+}
+
+fn bench_create_cloze_notes(c: &mut Criterion) {
+    let mut group = c.benchmark_group("create_cloze_notes");
+    for num_notes in [1_000, 10_000, 50_000] {
+        group.throughput(Throughput::Elements(num_notes as u64));
+        group.bench_with_input(
+            BenchmarkId::from_parameter(num_notes),
+            &num_notes,
+            |b, &n| b.iter(|| black_box(build_cloze_deck(n))),
+        );
+    }
+    group.finish();
+}
+
+fn bench_write_package(c: &mut Criterion) {
+    let mut group = c.benchmark_group("write_package");
+    group.sample_size(10);
+    for num_notes in [1_000, 10_000] {
+        let package = Package::new(vec![build_deck(num_notes)], HashMap::new()).unwrap();
+        group.throughput(Throughput::Elements(num_notes as u64));
+        group.bench_with_input(
+            BenchmarkId::from_parameter(num_notes),
+            &package,
+            |b, package| b.iter(|| black_box(package.to_bytes().unwrap())),
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    bench_create_notes,
+    bench_create_cloze_notes,
+    bench_write_package
+);
+criterion_main!(benches);
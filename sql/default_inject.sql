@@ -63,7 +63,7 @@ VALUES (null,
                     "delays": [
                         10
                     ],
-                    "leechAction": 0,
+                    "leechAction": 1,
                     "leechFails": 8,
                     "minInt": 1,
                     "mult": 0
@@ -0,0 +1,27 @@
+INSERT INTO col
+VALUES (1,
+        1411124400,
+        1425279151694,
+        1425279151690,
+        18,
+        0,
+        0,
+        0,
+        '',
+        '',
+        '',
+        '',
+        '');
+INSERT INTO config
+VALUES ('activeDecks', 0, 0, CAST('[1]' AS BLOB)),
+       ('addToCur', 0, 0, CAST('true' AS BLOB)),
+       ('collapseTime', 0, 0, CAST('1200' AS BLOB)),
+       ('curDeck', 0, 0, CAST('1' AS BLOB)),
+       ('dueCounts', 0, 0, CAST('true' AS BLOB)),
+       ('estTimes', 0, 0, CAST('true' AS BLOB)),
+       ('newSpread', 0, 0, CAST('0' AS BLOB)),
+       ('nextPos', 0, 0, CAST('1' AS BLOB)),
+       ('schedVer', 0, 0, CAST('2' AS BLOB)),
+       ('sortBackwards', 0, 0, CAST('false' AS BLOB)),
+       ('sortType', 0, 0, CAST('"noteFld"' AS BLOB)),
+       ('timeLim', 0, 0, CAST('0' AS BLOB));
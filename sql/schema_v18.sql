@@ -0,0 +1,142 @@
+CREATE TABLE col
+(
+    id     integer primary key,
+    crt    integer not null,
+    mod    integer not null,
+    scm    integer not null,
+    ver    integer not null,
+    dty    integer not null,
+    usn    integer not null,
+    ls     integer not null,
+    conf   text    not null,
+    models text    not null,
+    decks  text    not null,
+    dconf  text    not null,
+    tags   text    not null
+);
+CREATE TABLE notes
+(
+    id    integer primary key, /* 0 */
+    guid  text    not null, /* 1 */
+    mid   integer not null, /* 2 */
+    mod   integer not null, /* 3 */
+    usn   integer not null, /* 4 */
+    tags  text    not null, /* 5 */
+    flds  text    not null, /* 6 */
+    sfld  integer not null, /* 7 */
+    csum  integer not null, /* 8 */
+    flags integer not null, /* 9 */
+    data  text    not null /* 10 */
+);
+CREATE TABLE cards
+(
+    id     integer primary key, /* 0 */
+    nid    integer not null, /* 1 */
+    did    integer not null, /* 2 */
+    ord    integer not null, /* 3 */
+    mod    integer not null, /* 4 */
+    usn    integer not null, /* 5 */
+    type   integer not null, /* 6 */
+    queue  integer not null, /* 7 */
+    due    integer not null, /* 8 */
+    ivl    integer not null, /* 9 */
+    factor integer not null, /* 10 */
+    reps   integer not null, /* 11 */
+    lapses integer not null, /* 12 */
+    left   integer not null, /* 13 */
+    odue   integer not null, /* 14 */
+    odid   integer not null, /* 15 */
+    flags  integer not null, /* 16 */
+    data   text    not null /* 17 */
+);
+CREATE TABLE revlog
+(
+    id      integer primary key,
+    cid     integer not null,
+    usn     integer not null,
+    ease    integer not null,
+    ivl     integer not null,
+    lastIvl integer not null,
+    factor  integer not null,
+    time    integer not null,
+    type    integer not null
+);
+CREATE TABLE graves
+(
+    oid  integer not null,
+    type integer not null,
+    usn  integer not null,
+    primary key (oid, type)
+) without rowid;
+CREATE TABLE deck_config
+(
+    id         integer primary key not null,
+    name       text    not null collate unicase,
+    mtime_secs integer not null,
+    usn        integer not null,
+    config     blob    not null
+);
+CREATE TABLE config
+(
+    key        text    not null primary key,
+    usn        integer not null,
+    mtime_secs integer not null,
+    val        blob    not null
+) without rowid;
+CREATE TABLE fields
+(
+    ntid   integer not null,
+    ord    integer not null,
+    name   text    not null collate unicase,
+    config blob    not null,
+    primary key (ntid, ord)
+) without rowid;
+CREATE TABLE templates
+(
+    ntid       integer not null,
+    ord        integer not null,
+    name       text    not null collate unicase,
+    mtime_secs integer not null,
+    usn        integer not null,
+    config     blob    not null,
+    primary key (ntid, ord)
+) without rowid;
+CREATE TABLE notetypes
+(
+    id         integer not null primary key,
+    name       text    not null collate unicase,
+    mtime_secs integer not null,
+    usn        integer not null,
+    config     blob    not null
+);
+CREATE TABLE decks
+(
+    id         integer primary key not null,
+    name       text    not null collate unicase,
+    mtime_secs integer not null,
+    usn        integer not null,
+    common     blob    not null,
+    kind       blob    not null
+);
+CREATE TABLE tags
+(
+    tag       text    not null primary key collate unicase,
+    usn       integer not null,
+    collapsed boolean not null,
+    config    blob    null
+) without rowid;
+CREATE INDEX ix_notes_usn on notes (usn);
+CREATE INDEX ix_cards_usn on cards (usn);
+CREATE INDEX ix_revlog_usn on revlog (usn);
+CREATE INDEX ix_cards_nid on cards (nid);
+CREATE INDEX ix_cards_sched on cards (did, queue, due);
+CREATE INDEX ix_revlog_cid on revlog (cid);
+CREATE INDEX ix_notes_csum on notes (csum);
+CREATE INDEX idx_notes_mid on notes (mid);
+CREATE INDEX idx_cards_odid on cards (odid) where odid != 0;
+CREATE UNIQUE INDEX idx_fields_name_ntid on fields (name, ntid);
+CREATE UNIQUE INDEX idx_templates_name_ntid on templates (name, ntid);
+CREATE INDEX idx_templates_usn on templates (usn);
+CREATE UNIQUE INDEX idx_notetypes_name on notetypes (name);
+CREATE INDEX idx_notetypes_usn on notetypes (usn);
+CREATE UNIQUE INDEX idx_decks_name on decks (name);
@@ -1,6 +1,6 @@
 //! Deck builder
 
-use crate::core::{Deck, Note};
+use crate::core::{Deck, DeckOptions, Note};
 
 /// Builder for decks
 pub struct DeckBuilder {
@@ -8,6 +8,7 @@ pub struct DeckBuilder {
     name: String,
     description: String,
     notes: Vec<Note>,
+    options: Option<DeckOptions>,
 }
 
 impl DeckBuilder {
@@ -17,6 +18,7 @@ impl DeckBuilder {
             name: name.to_string(),
             description: String::new(),
             notes: Vec::new(),
+            options: None,
         }
     }
 
@@ -35,8 +37,16 @@ impl DeckBuilder {
         self
     }
 
+    pub fn options(mut self, options: DeckOptions) -> Self {
+        self.options = Some(options);
+        self
+    }
+
     pub fn build(self) -> Deck {
         let mut deck = Deck::new(self.id, &self.name, &self.description);
+        if let Some(options) = self.options {
+            deck = deck.with_options(options);
+        }
         for note in self.notes {
             deck.add_note(note);
         }
@@ -12,5 +12,5 @@ pub mod template;
 pub use deck::DeckBuilder;
 pub use field::{FieldBuilder, FieldDefaultsConstants};
 pub use model::{BasicModels, ModelBuilder};
-pub use note::NoteBuilder;
+pub use note::{NoteBuilder, TemplateRef};
 pub use template::{TemplateBuilder, TemplateDefaults};
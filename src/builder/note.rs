@@ -1,6 +1,42 @@
 //! Note builder
 
-use crate::core::{Model, Note};
+use crate::core::{Burial, Flag, GuidStrategy, Model, ModelType, Note};
+
+/// A card template, by ordinal or by name
+///
+/// Cards of cloze models can only be picked by ordinal, where ordinal `n`
+/// is the card of cloze deletion `n + 1`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TemplateRef {
+    Ord(i64),
+    Name(String),
+}
+
+impl From<i64> for TemplateRef {
+    fn from(ord: i64) -> Self {
+        TemplateRef::Ord(ord)
+    }
+}
+
+impl From<&str> for TemplateRef {
+    fn from(name: &str) -> Self {
+        TemplateRef::Name(name.to_string())
+    }
+}
+
+impl From<String> for TemplateRef {
+    fn from(name: String) -> Self {
+        TemplateRef::Name(name)
+    }
+}
+
+/// Setting applied to one card of the note
+#[derive(Debug, Clone, Copy)]
+enum CardSetting {
+    Flag(Flag),
+    Buried(Burial),
+    Deck(i64),
+}
 
 /// Builder for notes
 pub struct NoteBuilder {
@@ -8,7 +44,9 @@ pub struct NoteBuilder {
     fields: Vec<String>,
     tags: Vec<String>,
     guid: Option<String>,
-    sort_field: bool,
+    guid_strategy: GuidStrategy,
+    sort_field: Option<String>,
+    card_settings: Vec<(TemplateRef, CardSetting)>,
 }
 
 impl NoteBuilder {
@@ -18,7 +56,9 @@ impl NoteBuilder {
             fields: Vec::new(),
             tags: Vec::new(),
             guid: None,
-            sort_field: false,
+            guid_strategy: GuidStrategy::default(),
+            sort_field: None,
+            card_settings: Vec::new(),
         }
     }
 
@@ -52,11 +92,43 @@ impl NoteBuilder {
         self
     }
 
-    pub fn sort_field(mut self, sort: bool) -> Self {
-        self.sort_field = sort;
+    pub fn guid_strategy(mut self, strategy: GuidStrategy) -> Self {
+        self.guid_strategy = strategy;
+        self
+    }
+
+    /// Override the sort field value
+    pub fn sort_field_value(mut self, sort_field: &str) -> Self {
+        self.sort_field = Some(sort_field.to_string());
         self
     }
 
+    /// Flag the card of `template`
+    pub fn flag(mut self, template: impl Into<TemplateRef>, flag: Flag) -> Self {
+        self.card_settings
+            .push((template.into(), CardSetting::Flag(flag)));
+        self
+    }
+
+    /// Bury the card of `template` until the next day
+    pub fn bury(mut self, template: impl Into<TemplateRef>, burial: Burial) -> Self {
+        self.card_settings
+            .push((template.into(), CardSetting::Buried(burial)));
+        self
+    }
+
+    /// Put the card of `template` into the deck `deck_id` instead of the
+    /// note's deck, such as reverse cards into a "Reverse" subdeck
+    pub fn card_deck(mut self, template: impl Into<TemplateRef>, deck_id: i64) -> Self {
+        self.card_settings
+            .push((template.into(), CardSetting::Deck(deck_id)));
+        self
+    }
+
+    /// Build the note
+    ///
+    /// Card settings for templates that produce no card for this note, such
+    /// as an optional reverse card, are ignored.
     pub fn build(self) -> anyhow::Result<Note> {
         let model = self
             .model
@@ -66,17 +138,61 @@ impl NoteBuilder {
             return Err(anyhow::anyhow!("Fields are required"));
         }
 
-        Ok(Note::with_options(
+        let mut note = Note::with_options_and_strategy(
             model,
             self.fields.iter().map(|s| s.as_str()).collect(),
-            Some(self.sort_field),
+            self.sort_field.as_deref(),
             if self.tags.is_empty() {
                 None
             } else {
                 Some(self.tags.iter().map(|s| s.as_str()).collect())
             },
             self.guid.as_deref(),
-        )?)
+            self.guid_strategy,
+        )?;
+
+        for (template, setting) in self.card_settings {
+            let ord = template_ord(note.model(), &template)?;
+            for card in note.cards_mut().iter_mut().filter(|card| card.ord == ord) {
+                match setting {
+                    CardSetting::Flag(flag) => card.flag = Some(flag),
+                    CardSetting::Buried(burial) => card.buried = Some(burial),
+                    CardSetting::Deck(deck_id) => card.deck_id = Some(deck_id),
+                }
+            }
+        }
+
+        Ok(note)
+    }
+}
+
+/// Card ordinal of `template` in `model`
+///
+/// Ordinals of standard models must belong to one of their templates.
+fn template_ord(model: &Model, template: &TemplateRef) -> anyhow::Result<i64> {
+    match template {
+        TemplateRef::Ord(ord)
+            if model.model_type != ModelType::Cloze
+                && !(0..model.templates.len() as i64).contains(ord) =>
+        {
+            Err(anyhow::anyhow!(
+                "Model '{}' has no template with ordinal {ord}",
+                model.name
+            ))
+        }
+        TemplateRef::Ord(ord) => Ok(*ord),
+        TemplateRef::Name(_) if model.model_type == ModelType::Cloze => Err(anyhow::anyhow!(
+            "Cards of cloze model '{}' must be picked by ordinal",
+            model.name
+        )),
+        TemplateRef::Name(name) => model
+            .templates
+            .iter()
+            .position(|t| t.name == *name)
+            .map(|index| index as i64)
+            .ok_or_else(|| {
+                anyhow::anyhow!("Model '{}' has no template named '{name}'", model.name)
+            }),
     }
 }
 
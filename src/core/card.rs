@@ -3,18 +3,292 @@
 //! A card represents a single flashcard generated from a note.
 
 use crate::core::config::db;
+use crate::core::revlog::RevlogEntry;
 use serde::{Deserialize, Serialize};
+use std::time::SystemTime;
+use time::Date;
+
+/// Stage of a card in Anki's scheduler
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum CardType {
+    /// Never studied
+    #[default]
+    New,
+    /// Going through the learning steps
+    Learning,
+    /// Graduated, shown again after its interval
+    Review,
+    /// Forgotten, going through the relearning steps
+    Relearning,
+}
+
+impl CardType {
+    /// Value of the `type` column
+    pub fn to_i64(self) -> i64 {
+        match self {
+            CardType::New => db::card_type::NEW,
+            CardType::Learning => db::card_type::LEARNING,
+            CardType::Review => db::card_type::REVIEW,
+            CardType::Relearning => db::card_type::RELEARNING,
+        }
+    }
+
+    /// Card type for a value of the `type` column
+    pub fn from_i64(value: i64) -> Self {
+        match value {
+            db::card_type::LEARNING => CardType::Learning,
+            db::card_type::REVIEW => CardType::Review,
+            db::card_type::RELEARNING => CardType::Relearning,
+            _ => CardType::New,
+        }
+    }
+}
+
+/// When a card is due
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Due {
+    /// Position in the new card queue
+    Position(i64),
+    /// Time a learning step ends, for steps shorter than a day
+    Time(SystemTime),
+    /// Day a review, or a learning step of a day or more, is due
+    Day(Date),
+}
+
+/// Scheduling state of a card
+///
+/// Cards start out new. To carry over progress from another system, give
+/// them a state with [`Scheduling::learning`], [`Scheduling::review`] or
+/// [`Scheduling::relearning`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Scheduling {
+    pub card_type: CardType,
+    pub due: Due,
+    /// Current interval in days; 0 for new and learning cards
+    pub interval: u32,
+    /// Ease factor in permille, such as 2500 for 250%; 0 for new cards
+    pub ease_factor: u32,
+    /// Number of times the card was answered
+    pub reps: u32,
+    /// Number of times the card was forgotten after graduating
+    pub lapses: u32,
+    /// Learning or relearning steps left
+    pub remaining_steps: u32,
+}
+
+impl Scheduling {
+    /// A new card at `position` in the new card queue
+    pub fn new_card(position: i64) -> Self {
+        Self {
+            card_type: CardType::New,
+            due: Due::Position(position),
+            interval: 0,
+            ease_factor: 0,
+            reps: 0,
+            lapses: 0,
+            remaining_steps: 0,
+        }
+    }
+
+    /// A card in learning with `remaining_steps` steps left
+    ///
+    /// `due` is a [`Due::Time`] for steps within a day, or a [`Due::Day`].
+    pub fn learning(due: Due, remaining_steps: u32) -> Self {
+        Self {
+            card_type: CardType::Learning,
+            due,
+            remaining_steps,
+            ..Self::new_card(0)
+        }
+    }
+
+    /// A review card due on `due`, with its interval in days and ease
+    /// factor in permille
+    pub fn review(due: Date, interval: u32, ease_factor: u32) -> Self {
+        Self {
+            card_type: CardType::Review,
+            due: Due::Day(due),
+            interval,
+            ease_factor,
+            ..Self::new_card(0)
+        }
+    }
+
+    /// A forgotten card relearning with `remaining_steps` steps left, and
+    /// the interval in days and ease factor it returns to review with
+    pub fn relearning(due: Due, remaining_steps: u32, interval: u32, ease_factor: u32) -> Self {
+        Self {
+            card_type: CardType::Relearning,
+            due,
+            interval,
+            ease_factor,
+            remaining_steps,
+            ..Self::new_card(0)
+        }
+    }
+
+    /// Set the number of times the card was answered
+    pub fn with_reps(mut self, reps: u32) -> Self {
+        self.reps = reps;
+        self
+    }
+
+    /// Set the number of times the card was forgotten
+    pub fn with_lapses(mut self, lapses: u32) -> Self {
+        self.lapses = lapses;
+        self
+    }
+
+    /// Queue a card in this state is in, when not suspended
+    pub fn queue_value(&self) -> i64 {
+        match (self.card_type, self.due) {
+            (CardType::New, _) => db::queue::NEW,
+            (CardType::Review, _) => db::queue::REVIEW,
+            (_, Due::Day(_)) => db::queue::DAY_LEARNING,
+            _ => db::queue::LEARNING,
+        }
+    }
+}
+
+impl Default for Scheduling {
+    fn default() -> Self {
+        Self::new_card(0)
+    }
+}
+
+/// FSRS memory state of a card
+///
+/// Anki keeps it in the card's `data` column and uses it instead of the
+/// review history when FSRS is enabled.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct FsrsState {
+    /// Days until the recall probability drops to 90%
+    pub stability: f32,
+    /// How hard the card is, from 1 (easiest) to 10 (hardest)
+    pub difficulty: f32,
+    /// Retention the card was last scheduled for, from 0 to 1
+    pub desired_retention: Option<f32>,
+}
+
+impl FsrsState {
+    /// Create a memory state without a desired retention
+    pub fn new(stability: f32, difficulty: f32) -> Self {
+        Self {
+            stability,
+            difficulty,
+            desired_retention: None,
+        }
+    }
+
+    /// Set the retention the card was last scheduled for
+    pub fn with_desired_retention(mut self, retention: f32) -> Self {
+        self.desired_retention = Some(retention);
+        self
+    }
+}
+
+/// Color flag of a card
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Flag {
+    Red,
+    Orange,
+    Green,
+    Blue,
+    Pink,
+    Turquoise,
+    Purple,
+}
+
+impl Flag {
+    /// Value of the `flags` column
+    pub fn to_i64(self) -> i64 {
+        match self {
+            Flag::Red => 1,
+            Flag::Orange => 2,
+            Flag::Green => 3,
+            Flag::Blue => 4,
+            Flag::Pink => 5,
+            Flag::Turquoise => 6,
+            Flag::Purple => 7,
+        }
+    }
+
+    /// Flag for a value of the `flags` column, or `None` when unflagged
+    ///
+    /// Only the lowest three bits hold the flag.
+    pub fn from_i64(value: i64) -> Option<Self> {
+        match value & 0b111 {
+            1 => Some(Flag::Red),
+            2 => Some(Flag::Orange),
+            3 => Some(Flag::Green),
+            4 => Some(Flag::Blue),
+            5 => Some(Flag::Pink),
+            6 => Some(Flag::Turquoise),
+            7 => Some(Flag::Purple),
+            _ => None,
+        }
+    }
+}
+
+/// Why a card is buried until the next day
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Burial {
+    /// Buried because a sibling card of the same note was studied
+    Sibling,
+    /// Buried by hand
+    Manual,
+}
+
+impl Burial {
+    /// Queue a card buried this way is in
+    pub fn queue_value(self) -> i64 {
+        match self {
+            Burial::Sibling => db::queue::SIBLING_BURIED,
+            Burial::Manual => db::queue::MANUALLY_BURIED,
+        }
+    }
+
+    /// Burial for a value of the `queue` column, or `None` when not buried
+    pub fn from_queue(queue: i64) -> Option<Self> {
+        match queue {
+            db::queue::SIBLING_BURIED => Some(Burial::Sibling),
+            db::queue::MANUALLY_BURIED => Some(Burial::Manual),
+            _ => None,
+        }
+    }
+}
 
 /// A flashcard generated from a note
 ///
 /// Cards are created automatically when you add a note to a deck.
 /// The number of cards depends on the model type and templates.
-#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Card {
     /// Ordinal/index of the card
     pub ord: i64,
     /// Whether the card is suspended
     pub suspend: bool,
+    /// Explicit card ID; allocated from the current time when `None`
+    #[serde(default)]
+    pub id: Option<i64>,
+    /// Scheduling state; new by default
+    #[serde(default)]
+    pub scheduling: Scheduling,
+    /// Past reviews, oldest first
+    #[serde(default)]
+    pub revlog: Vec<RevlogEntry>,
+    /// FSRS memory state, if known
+    #[serde(default)]
+    pub fsrs: Option<FsrsState>,
+    /// Color flag, if any
+    #[serde(default)]
+    pub flag: Option<Flag>,
+    /// Burial until the next day, if any; suspension takes precedence
+    #[serde(default)]
+    pub buried: Option<Burial>,
+    /// Deck the card goes into instead of its note's deck
+    #[serde(default)]
+    pub deck_id: Option<i64>,
 }
 
 impl Card {
@@ -25,7 +299,17 @@ impl Card {
     /// * `ord` - The ordinal/index of this card
     /// * `suspend` - Whether the card should be suspended
     pub fn new(ord: i64, suspend: bool) -> Self {
-        Self { ord, suspend }
+        Self {
+            ord,
+            suspend,
+            id: None,
+            scheduling: Scheduling::default(),
+            revlog: Vec::new(),
+            fsrs: None,
+            flag: None,
+            buried: None,
+            deck_id: None,
+        }
     }
 
     /// Get the card's ordinal
@@ -44,12 +328,99 @@ impl Card {
         self
     }
 
+    /// Set an explicit card ID
+    pub fn with_id(mut self, id: i64) -> Self {
+        self.id = Some(id);
+        self
+    }
+
+    /// Get the explicit card ID, if any
+    pub fn id(&self) -> Option<i64> {
+        self.id
+    }
+
+    /// Set the scheduling state
+    pub fn with_scheduling(mut self, scheduling: Scheduling) -> Self {
+        self.scheduling = scheduling;
+        self
+    }
+
+    /// Get the scheduling state
+    pub fn scheduling(&self) -> &Scheduling {
+        &self.scheduling
+    }
+
+    /// Set the review history
+    pub fn with_revlog(mut self, revlog: Vec<RevlogEntry>) -> Self {
+        self.revlog = revlog;
+        self
+    }
+
+    /// Add a past review to the history
+    pub fn with_review(mut self, review: RevlogEntry) -> Self {
+        self.revlog.push(review);
+        self
+    }
+
+    /// Get the review history
+    pub fn revlog(&self) -> &[RevlogEntry] {
+        &self.revlog
+    }
+
+    /// Set the FSRS memory state
+    pub fn with_fsrs(mut self, state: FsrsState) -> Self {
+        self.fsrs = Some(state);
+        self
+    }
+
+    /// Get the FSRS memory state, if known
+    pub fn fsrs(&self) -> Option<&FsrsState> {
+        self.fsrs.as_ref()
+    }
+
+    /// Set the color flag
+    pub fn with_flag(mut self, flag: Flag) -> Self {
+        self.flag = Some(flag);
+        self
+    }
+
+    /// Get the color flag, if any
+    pub fn flag(&self) -> Option<Flag> {
+        self.flag
+    }
+
+    /// Bury the card until the next day
+    pub fn with_buried(mut self, burial: Burial) -> Self {
+        self.buried = Some(burial);
+        self
+    }
+
+    /// Get why the card is buried, if it is
+    pub fn buried(&self) -> Option<Burial> {
+        self.buried
+    }
+
+    /// Put the card into the deck `deck_id` instead of its note's deck
+    ///
+    /// The deck must be part of the same package.
+    pub fn with_deck(mut self, deck_id: i64) -> Self {
+        self.deck_id = Some(deck_id);
+        self
+    }
+
+    /// Get the deck the card goes into instead of its note's deck, if any
+    pub fn deck_id(&self) -> Option<i64> {
+        self.deck_id
+    }
+
     /// Get the queue value for this card
     pub fn queue_value(&self) -> i64 {
         if self.suspend {
             db::queue::SUSPENDED
+        } else if let Some(burial) = self.buried {
+            burial.queue_value()
         } else {
-            db::queue::NEW
+            self.scheduling.queue_value()
         }
     }
 }
@@ -77,4 +448,39 @@ mod tests {
         let card = Card::new(0, false).with_suspended(true);
         assert!(card.is_suspended());
     }
+
+    #[test]
+    fn test_card_queue_follows_scheduling() {
+        let day = Date::from_calendar_date(2025, time::Month::March, 1).unwrap();
+        let review = Card::new(0, false).with_scheduling(Scheduling::review(day, 10, 2500));
+        assert_eq!(review.queue_value(), db::queue::REVIEW);
+        assert_eq!(review.clone().with_suspended(true).queue_value(), -1);
+
+        let learning = Scheduling::learning(Due::Time(SystemTime::UNIX_EPOCH), 2);
+        assert_eq!(learning.queue_value(), db::queue::LEARNING);
+        let relearning = Scheduling::relearning(Due::Day(day), 1, 3, 2300);
+        assert_eq!(relearning.queue_value(), db::queue::DAY_LEARNING);
+        assert_eq!(relearning.card_type.to_i64(), 3);
+        assert_eq!(Card::new(0, false).queue_value(), db::queue::NEW);
+    }
+
+    #[test]
+    fn test_card_flag_and_burial() {
+        let card = Card::new(0, false)
+            .with_flag(Flag::Turquoise)
+            .with_buried(Burial::Sibling);
+        assert_eq!(card.flag().map(Flag::to_i64), Some(6));
+        assert_eq!(card.queue_value(), db::queue::SIBLING_BURIED);
+        assert_eq!(card.clone().with_suspended(true).queue_value(), -1);
+        assert_eq!(Flag::from_i64(0b1010_0100), Some(Flag::Blue));
+        assert_eq!(Flag::from_i64(0), None);
+        assert_eq!(Burial::from_queue(-3), Some(Burial::Manual));
+        assert_eq!(Burial::from_queue(db::queue::REVIEW), None);
+    }
+
+    #[test]
+    fn test_card_with_id() {
+        assert_eq!(Card::new(0, false).id(), None);
+        assert_eq!(Card::new(0, false).with_id(42).id(), Some(42));
+    }
 }
@@ -200,7 +200,7 @@ impl AnkiConfig {
 }
 
 /// Model type enumeration
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum ModelType {
     Basic,
     Cloze,
@@ -219,18 +219,30 @@ pub mod db {
     pub mod queue {
         /// New card
         pub const NEW: i64 = 0;
+        /// Learning or relearning card due later today; `due` is a timestamp
+        pub const LEARNING: i64 = 1;
+        /// Review card; `due` is a day number
+        pub const REVIEW: i64 = 2;
+        /// Learning or relearning card due on a later day; `due` is a day number
+        pub const DAY_LEARNING: i64 = 3;
         /// Suspended card
         pub const SUSPENDED: i64 = -1;
+        /// Buried automatically with a sibling until the next day
+        pub const SIBLING_BURIED: i64 = -2;
+        /// Buried by hand until the next day
+        pub const MANUALLY_BURIED: i64 = -3;
     }
 
     /// Card types
     pub mod card_type {
+        /// New card
+        pub const NEW: i64 = 0;
         /// Learning card
-        pub const LEARNING: i64 = 0;
+        pub const LEARNING: i64 = 1;
         /// Review card
-        pub const REVIEW: i64 = 1;
+        pub const REVIEW: i64 = 2;
         /// Relearning card
-        pub const RELEARNING: i64 = 2;
+        pub const RELEARNING: i64 = 3;
     }
 
     /// Default values for card fields
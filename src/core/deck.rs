@@ -2,9 +2,14 @@
 //!
 //! A deck is a collection of notes.
 
+use crate::core::deck_options::{DEFAULT_OPTIONS_ID, DeckOptions};
+use crate::core::diagnostics::{Diagnostic, Location, ValidationReport};
 use crate::core::model::Model;
 use crate::core::note::Note;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+
+/// ID of Anki's built-in "Default" deck
+const DEFAULT_DECK_ID: i64 = 1;
 
 /// A flashcard deck which can be written to an .apkg file
 ///
@@ -16,6 +21,7 @@ pub struct Deck {
     pub description: String,
     notes: Vec<Note>,
     models: HashMap<i64, Model>,
+    options: Option<DeckOptions>,
 }
 
 impl Deck {
@@ -41,6 +47,7 @@ impl Deck {
             description: description.to_string(),
             notes: Vec::new(),
             models: HashMap::new(),
+            options: None,
         }
     }
 
@@ -90,8 +97,11 @@ impl Deck {
         &self.models
     }
 
+    /// Get all models, ordered by id
     pub fn models(&self) -> Vec<&Model> {
-        self.models.values().collect()
+        let mut models: Vec<&Model> = self.models.values().collect();
+        models.sort_by_key(|model| model.id);
+        models
     }
 
     /// Get number of notes
@@ -120,6 +130,210 @@ impl Deck {
         self.name = name.to_string();
         self
     }
+
+    /// Use an options group instead of Anki's "Default" group
+    ///
+    /// Decks can share a group by using options with the same ID.
+    pub fn with_options(mut self, options: DeckOptions) -> Self {
+        self.options = Some(options);
+        self
+    }
+
+    /// Get the options group, if the deck has its own
+    pub fn options(&self) -> Option<&DeckOptions> {
+        self.options.as_ref()
+    }
+
+    /// Get the ID of the options group the deck uses
+    pub fn options_id(&self) -> i64 {
+        self.options
+            .as_ref()
+            .map_or(DEFAULT_OPTIONS_ID, |options| options.id)
+    }
+
+    /// Check the deck, its models and its notes for problems
+    ///
+    /// Every problem is collected instead of stopping at the first one. Use
+    /// `Package::validate` to also check media references and conflicts
+    /// between decks.
+    pub fn validate(&self) -> ValidationReport {
+        validate_decks(std::slice::from_ref(self))
+    }
+}
+
+/// Validate `decks` together, including conflicts between them
+pub(crate) fn validate_decks(decks: &[Deck]) -> ValidationReport {
+    let mut report = ValidationReport::new();
+
+    let mut deck_ids = HashSet::new();
+    let mut deck_names: HashMap<&str, i64> = HashMap::new();
+    for deck in decks {
+        let location = || Location::Deck {
+            id: deck.id,
+            name: deck.name.clone(),
+        };
+        if deck.id == DEFAULT_DECK_ID {
+            report.push(Diagnostic::error(
+                location(),
+                "deck ID 1 belongs to Anki's Default deck, so its notes would end up there",
+            ));
+        }
+        if !deck_ids.insert(deck.id) {
+            report.push(Diagnostic::error(
+                location(),
+                format!("another deck uses the ID {}", deck.id),
+            ));
+        }
+        match deck_names.get(deck.name.as_str()) {
+            Some(&first) if first != deck.id => report.push(Diagnostic::warning(
+                location(),
+                format!("deck {first} has the same name, so this deck gets renamed in Anki"),
+            )),
+            Some(_) => {}
+            None => {
+                deck_names.insert(&deck.name, deck.id);
+            }
+        }
+    }
+
+    // Like models, only one options group per ID ends up in the collection
+    let mut options_groups: HashMap<i64, &DeckOptions> = HashMap::new();
+    for (deck, options) in decks
+        .iter()
+        .filter_map(|deck| Some((deck, deck.options.as_ref()?)))
+    {
+        let location = || Location::Deck {
+            id: deck.id,
+            name: deck.name.clone(),
+        };
+        match options_groups.get(&options.id) {
+            Some(first) if *first != options => report.push(Diagnostic::error(
+                location(),
+                format!(
+                    "options group ID {} is also used by a different options group '{}'",
+                    options.id, first.name
+                ),
+            )),
+            Some(_) => {}
+            None => {
+                if options.id == DEFAULT_OPTIONS_ID && *options != DeckOptions::default() {
+                    report.push(Diagnostic::warning(
+                        location(),
+                        "options group ID 1 belongs to Anki's Default options group, \
+                         which importing would change",
+                    ));
+                }
+                if !matches!(options.fsrs_params.len(), 0 | 17 | 19 | 21) {
+                    report.push(Diagnostic::error(
+                        location(),
+                        format!(
+                            "options group '{}' has {} FSRS parameters, but FSRS needs 17, \
+                             19 or 21",
+                            options.name,
+                            options.fsrs_params.len()
+                        ),
+                    ));
+                }
+                if !(options.desired_retention > 0.0 && options.desired_retention < 1.0) {
+                    report.push(Diagnostic::error(
+                        location(),
+                        format!(
+                            "options group '{}' has a desired retention of {}, which is not \
+                             between 0 and 1",
+                            options.name, options.desired_retention
+                        ),
+                    ));
+                }
+                options_groups.insert(options.id, options);
+            }
+        }
+    }
+
+    // Models are compared by their full definition, since notes carry their
+    // own copy and only one model per ID ends up in the collection
+    let mut models: HashMap<i64, (u64, &str)> = HashMap::new();
+    let mut model_names: HashMap<&str, i64> = HashMap::new();
+    let mut checked_models = HashSet::new();
+    for model in decks.iter().flat_map(Deck::notes).map(Note::model) {
+        let fingerprint = model.definition_fingerprint();
+        if !checked_models.insert((model.id, fingerprint)) {
+            continue;
+        }
+        report.extend(model.validate());
+        match models.get(&model.id) {
+            Some((_, first)) => report.push(Diagnostic::error(
+                Location::Model {
+                    model: model.name.clone(),
+                },
+                format!(
+                    "model ID {} is also used by a different definition of model '{first}'",
+                    model.id
+                ),
+            )),
+            None => {
+                models.insert(model.id, (fingerprint, &model.name));
+                match model_names.get(model.name.as_str()) {
+                    Some(&first) if first != model.id => report.push(Diagnostic::warning(
+                        Location::Model {
+                            model: model.name.clone(),
+                        },
+                        format!(
+                            "model ID {first} has the same name, so model ID {} gets renamed \
+                             in Anki",
+                            model.id
+                        ),
+                    )),
+                    Some(_) => {}
+                    None => {
+                        model_names.insert(&model.name, model.id);
+                    }
+                }
+            }
+        }
+    }
+
+    let mut guids: HashMap<&str, (&str, usize)> = HashMap::new();
+    for deck in decks {
+        for (index, note) in deck.notes().iter().enumerate() {
+            note.validate(&deck.name, index, &mut report);
+
+            for card in note.cards() {
+                if let Some(card_deck) = card.deck_id()
+                    && !deck_ids.contains(&card_deck)
+                {
+                    report.push(Diagnostic::error(
+                        Location::Note {
+                            deck: deck.name.clone(),
+                            index,
+                            guid: note.guid().to_string(),
+                            field: None,
+                        },
+                        format!(
+                            "card {} goes into deck ID {card_deck}, which is not in the package",
+                            card.ord()
+                        ),
+                    ));
+                }
+            }
+
+            if let Some((first_deck, first_index)) = guids.insert(note.guid(), (&deck.name, index))
+            {
+                report.push(Diagnostic::error(
+                    Location::Note {
+                        deck: deck.name.clone(),
+                        index,
+                        guid: note.guid().to_string(),
+                        field: None,
+                    },
+                    format!(
+                        "duplicate GUID, also used by note {first_index} of deck '{first_deck}'"
+                    ),
+                ));
+            }
+        }
+    }
+
+    report
 }
 
 #[cfg(test)]
@@ -153,6 +367,149 @@ mod tests {
         assert_eq!(deck.num_models(), 1);
     }
 
+    #[test]
+    fn test_deck_validate_collects_note_problems() {
+        let model = Model::new(
+            123,
+            "Basic",
+            vec![Field::new("F"), Field::new("B")],
+            vec![Template::new("C1").qfmt("{{F}}").afmt("{{B}}")],
+        );
+        let mut deck = Deck::new(1, "Test", "");
+        deck.add_note(Note::new(model.clone(), vec!["", "A"]).unwrap());
+        deck.add_note(
+            Note::new(model.clone(), vec!["Q", "x < y > z"])
+                .unwrap()
+                .with_tag("two words")
+                .with_guid("same"),
+        );
+        deck.add_note(
+            Note::new(model.clone().css("x"), vec!["Q2", "A"])
+                .unwrap()
+                .with_guid("same"),
+        );
+
+        let report = deck.validate();
+        let messages: Vec<String> = report
+            .diagnostics()
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(
+            messages,
+            vec![
+                "error: deck 'Test' (1): deck ID 1 belongs to Anki's Default deck, \
+                 so its notes would end up there",
+                "error: model 'Basic': model ID 123 is also used by a different definition \
+                 of model 'Basic'",
+                format!(
+                    "error: deck 'Test', note 0 (guid '{}'): note produces no cards, \
+                     because the fields its templates require are empty",
+                    deck.notes()[0].guid()
+                )
+                .as_str(),
+                "error: deck 'Test', note 1 (guid 'same'): tag 'two words' is empty or \
+                 contains whitespace",
+                "warning: deck 'Test', note 1 (guid 'same'), field 'B': invalid HTML tags: < y >",
+                "error: deck 'Test', note 2 (guid 'same'): duplicate GUID, also used by note 1 \
+                 of deck 'Test'",
+            ]
+        );
+        assert!(report.has_errors());
+        assert_eq!(report.warnings().count(), 1);
+    }
+
+    #[test]
+    fn test_deck_validate_card_decks() {
+        let model = Model::new(
+            123,
+            "Basic",
+            vec![Field::new("F"), Field::new("B")],
+            vec![Template::new("C1").qfmt("{{F}}").afmt("{{B}}")],
+        );
+        let mut note = Note::new(model, vec!["Q", "A"]).unwrap().with_guid("g");
+        note.cards_mut()[0] = note.cards()[0].clone().with_deck(3);
+        let mut deck = Deck::new(2, "Main", "");
+        deck.add_note(note);
+
+        let report = validate_decks(&[deck.clone()]);
+        assert_eq!(
+            report.diagnostics()[0].to_string(),
+            "error: deck 'Main', note 0 (guid 'g'): card 0 goes into deck ID 3, \
+             which is not in the package"
+        );
+        assert!(!validate_decks(&[deck, Deck::new(3, "Main::Other", "")]).has_errors());
+    }
+
+    #[test]
+    fn test_deck_validate_duplicate_names() {
+        let model = |id| {
+            Model::new(
+                id,
+                "Basic",
+                vec![Field::new("F")],
+                vec![Template::new("C1").qfmt("{{F}}")],
+            )
+        };
+        let mut first = Deck::new(2, "Main", "");
+        first.add_note(Note::new(model(10), vec!["Q"]).unwrap());
+        let mut second = Deck::new(3, "Main", "");
+        second.add_note(Note::new(model(11), vec!["Q2"]).unwrap());
+
+        let messages: Vec<String> = validate_decks(&[first, second])
+            .into_iter()
+            .map(|d| d.to_string())
+            .collect();
+        assert_eq!(
+            messages,
+            vec![
+                "warning: deck 'Main' (3): deck 2 has the same name, so this deck gets \
+                 renamed in Anki",
+                "warning: model 'Basic': model ID 10 has the same name, so model ID 11 gets \
+                 renamed in Anki",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_deck_validate_options_groups() {
+        let options = DeckOptions::new(10, "Fast").with_new_per_day(100);
+        let decks = [
+            Deck::new(2, "A", "").with_options(options.clone()),
+            Deck::new(3, "B", "").with_options(options.clone()),
+            Deck::new(4, "C", "").with_options(DeckOptions {
+                name: "Slow".to_string(),
+                ..options
+            }),
+            Deck::new(5, "D", "").with_options(DeckOptions::default().with_autoplay(false)),
+            Deck::new(6, "F", "").with_options(
+                DeckOptions::new(11, "FSRS")
+                    .with_fsrs_params(vec![0.4; 5])
+                    .with_desired_retention(1.5),
+            ),
+        ];
+        assert_eq!(decks[0].options_id(), 10);
+        assert_eq!(Deck::new(7, "E", "").options_id(), DEFAULT_OPTIONS_ID);
+
+        let messages: Vec<String> = validate_decks(&decks)
+            .into_iter()
+            .map(|d| d.to_string())
+            .collect();
+        assert_eq!(
+            messages,
+            vec![
+                "error: deck 'C' (4): options group ID 10 is also used by a different \
+                 options group 'Fast'",
+                "warning: deck 'D' (5): options group ID 1 belongs to Anki's Default \
+                 options group, which importing would change",
+                "error: deck 'F' (6): options group 'FSRS' has 5 FSRS parameters, but FSRS \
+                 needs 17, 19 or 21",
+                "error: deck 'F' (6): options group 'FSRS' has a desired retention of 1.5, \
+                 which is not between 0 and 1",
+            ]
+        );
+    }
+
     #[test]
     fn test_deck_with_modifiers() {
         let deck = Deck::new(1234, "Old Name", "Old Desc")
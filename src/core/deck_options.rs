@@ -0,0 +1,198 @@
+//! Deck options groups
+//!
+//! In Anki, scheduling settings such as daily limits and learning steps live
+//! in options groups (`dconf`) that any number of decks can share. A deck
+//! without its own group uses Anki's "Default" group.
+
+/// ID of Anki's built-in "Default" options group
+pub const DEFAULT_OPTIONS_ID: i64 = 1;
+
+/// What Anki does with a card once it becomes a leech
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LeechAction {
+    /// Suspend the card
+    Suspend,
+    /// Only tag the note with "leech"
+    #[default]
+    TagOnly,
+}
+
+impl LeechAction {
+    /// Value stored in the collection
+    pub fn to_i64(self) -> i64 {
+        match self {
+            LeechAction::Suspend => 0,
+            LeechAction::TagOnly => 1,
+        }
+    }
+
+    /// Action for a value stored in the collection
+    pub fn from_i64(value: i64) -> Self {
+        match value {
+            1 => LeechAction::TagOnly,
+            _ => LeechAction::Suspend,
+        }
+    }
+}
+
+/// An options group decks can share
+///
+/// New groups start with the settings of Anki's "Default" group. Steps are
+/// in minutes and intervals in days.
+///
+/// # Example
+///
+/// ```
+/// use genanki_rs_rev::core::{Deck, DeckOptions};
+///
+/// let options = DeckOptions::new(1700000000, "Vocabulary")
+///     .with_new_per_day(50)
+///     .with_learning_steps(vec![1.0, 10.0, 60.0]);
+/// let deck = Deck::new(1234, "Spanish", "").with_options(options);
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct DeckOptions {
+    pub id: i64,
+    pub name: String,
+    /// New cards introduced per day
+    pub new_per_day: u32,
+    /// Maximum reviews per day
+    pub reviews_per_day: u32,
+    /// Learning steps of new cards, in minutes
+    pub learning_steps: Vec<f32>,
+    /// Relearning steps of lapsed cards, in minutes
+    pub relearning_steps: Vec<f32>,
+    /// Interval after the last learning step, in days
+    pub graduating_interval: u32,
+    /// Interval when a new card is answered Easy, in days
+    pub easy_interval: u32,
+    /// Ease factor of graduating cards, such as 2.5 for 250%
+    pub starting_ease: f32,
+    /// Number of lapses after which a card becomes a leech
+    pub leech_threshold: u32,
+    pub leech_action: LeechAction,
+    /// Bury other new cards of the same note until the next day
+    pub bury_new: bool,
+    /// Bury other review cards of the same note until the next day
+    pub bury_reviews: bool,
+    /// Bury other interday learning cards of the same note until the next day
+    pub bury_interday_learning: bool,
+    /// Play audio automatically when a card side is shown
+    pub autoplay: bool,
+    /// Play the question audio again when the answer is shown
+    pub replay_question: bool,
+    /// FSRS parameters: 17 for FSRS-4.5, 19 for FSRS-5 or 21 for FSRS-6;
+    /// empty to use Anki's defaults
+    pub fsrs_params: Vec<f32>,
+    /// Recall probability FSRS schedules reviews for, from 0 to 1
+    pub desired_retention: f32,
+}
+
+impl DeckOptions {
+    /// Create an options group with Anki's default settings
+    pub fn new(id: i64, name: &str) -> Self {
+        Self {
+            id,
+            name: name.to_string(),
+            new_per_day: 20,
+            reviews_per_day: 100,
+            learning_steps: vec![1.0, 10.0],
+            relearning_steps: vec![10.0],
+            graduating_interval: 1,
+            easy_interval: 4,
+            starting_ease: 2.5,
+            leech_threshold: 8,
+            leech_action: LeechAction::TagOnly,
+            bury_new: true,
+            bury_reviews: true,
+            bury_interday_learning: false,
+            autoplay: true,
+            replay_question: true,
+            fsrs_params: Vec::new(),
+            desired_retention: 0.9,
+        }
+    }
+
+    /// Set the number of new cards per day
+    pub fn with_new_per_day(mut self, limit: u32) -> Self {
+        self.new_per_day = limit;
+        self
+    }
+
+    /// Set the maximum number of reviews per day
+    pub fn with_reviews_per_day(mut self, limit: u32) -> Self {
+        self.reviews_per_day = limit;
+        self
+    }
+
+    /// Set the learning steps, in minutes
+    pub fn with_learning_steps(mut self, steps: Vec<f32>) -> Self {
+        self.learning_steps = steps;
+        self
+    }
+
+    /// Set the relearning steps, in minutes
+    pub fn with_relearning_steps(mut self, steps: Vec<f32>) -> Self {
+        self.relearning_steps = steps;
+        self
+    }
+
+    /// Set the graduating and easy intervals, in days
+    pub fn with_intervals(mut self, graduating: u32, easy: u32) -> Self {
+        self.graduating_interval = graduating;
+        self.easy_interval = easy;
+        self
+    }
+
+    /// Set the starting ease, such as 2.5 for 250%
+    pub fn with_starting_ease(mut self, ease: f32) -> Self {
+        self.starting_ease = ease;
+        self
+    }
+
+    /// Set when a card becomes a leech and what happens to it then
+    pub fn with_leech(mut self, threshold: u32, action: LeechAction) -> Self {
+        self.leech_threshold = threshold;
+        self.leech_action = action;
+        self
+    }
+
+    /// Set which sibling cards are buried until the next day
+    pub fn with_bury(mut self, new: bool, reviews: bool, interday_learning: bool) -> Self {
+        self.bury_new = new;
+        self.bury_reviews = reviews;
+        self.bury_interday_learning = interday_learning;
+        self
+    }
+
+    /// Set whether audio plays automatically
+    pub fn with_autoplay(mut self, autoplay: bool) -> Self {
+        self.autoplay = autoplay;
+        self
+    }
+
+    /// Set whether the question audio plays again on the answer side
+    pub fn with_replay_question(mut self, replay: bool) -> Self {
+        self.replay_question = replay;
+        self
+    }
+
+    /// Set the FSRS parameters, as produced by Anki's optimizer
+    pub fn with_fsrs_params(mut self, params: Vec<f32>) -> Self {
+        self.fsrs_params = params;
+        self
+    }
+
+    /// Set the recall probability FSRS schedules reviews for
+    pub fn with_desired_retention(mut self, retention: f32) -> Self {
+        self.desired_retention = retention;
+        self
+    }
+}
+
+impl Default for DeckOptions {
+    /// Anki's "Default" options group
+    fn default() -> Self {
+        Self::new(DEFAULT_OPTIONS_ID, "Default")
+    }
+}
@@ -0,0 +1,357 @@
+//! Structured diagnostics for linting models and decks
+//!
+//! Validation collects every problem it finds instead of stopping at the
+//! first one, so all of them can be fixed in one go. Models report a list of
+//! [`Diagnostic`]s, decks and packages a [`ValidationReport`].
+//!
+//! Warnings found while a package is written go to its [`DiagnosticSink`].
+
+use crate::core::model::parser::TextPosition;
+use crate::error::{Error, Result};
+use std::fmt;
+use std::sync::{Mutex, PoisonError};
+
+/// How serious a diagnostic is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Severity {
+    /// Likely a mistake, but the package still works in Anki
+    Warning,
+    /// Anki rejects the content or generates broken cards
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        })
+    }
+}
+
+/// Side of a card template
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TemplateSide {
+    /// The question format (`qfmt`)
+    Question,
+    /// The answer format (`afmt`)
+    Answer,
+}
+
+impl fmt::Display for TemplateSide {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            TemplateSide::Question => "question",
+            TemplateSide::Answer => "answer",
+        })
+    }
+}
+
+/// Where a diagnostic was found
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Location {
+    /// A model as a whole
+    Model { model: String },
+    /// A field of a model
+    Field {
+        model: String,
+        index: usize,
+        name: String,
+    },
+    /// A card template of a model, optionally narrowed to a side and position
+    Template {
+        model: String,
+        index: usize,
+        name: String,
+        side: Option<TemplateSide>,
+        position: Option<TextPosition>,
+    },
+    /// A deck
+    Deck { id: i64, name: String },
+    /// A note of a deck, optionally narrowed to one of its fields
+    Note {
+        deck: String,
+        index: usize,
+        guid: String,
+        field: Option<String>,
+    },
+    /// A media file of a package
+    Media { filename: String },
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Location::Model { model } => write!(f, "model '{model}'"),
+            Location::Field { model, name, .. } => write!(f, "model '{model}', field '{name}'"),
+            Location::Template {
+                model,
+                name,
+                side,
+                position,
+                ..
+            } => {
+                write!(f, "model '{model}', template '{name}'")?;
+                if let Some(side) = side {
+                    write!(f, " ({side})")?;
+                }
+                if let Some(position) = position {
+                    write!(f, ", {position}")?;
+                }
+                Ok(())
+            }
+            Location::Deck { id, name } => write!(f, "deck '{name}' ({id})"),
+            Location::Note {
+                deck,
+                index,
+                guid,
+                field,
+            } => {
+                write!(f, "deck '{deck}', note {index} (guid '{guid}')")?;
+                if let Some(field) = field {
+                    write!(f, ", field '{field}'")?;
+                }
+                Ok(())
+            }
+            Location::Media { filename } => write!(f, "media file '{filename}'"),
+        }
+    }
+}
+
+/// A problem found during validation
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub location: Location,
+    pub message: String,
+}
+
+impl Diagnostic {
+    /// Create an error diagnostic
+    pub fn error(location: Location, message: impl ToString) -> Self {
+        Self {
+            severity: Severity::Error,
+            location,
+            message: message.to_string(),
+        }
+    }
+
+    /// Create a warning diagnostic
+    pub fn warning(location: Location, message: impl ToString) -> Self {
+        Self {
+            severity: Severity::Warning,
+            location,
+            message: message.to_string(),
+        }
+    }
+
+    /// Check whether this is an error
+    pub fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}: {}", self.severity, self.location, self.message)
+    }
+}
+
+/// All diagnostics found while validating a deck or package
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ValidationReport {
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl ValidationReport {
+    /// Create an empty report
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a diagnostic
+    pub fn push(&mut self, diagnostic: Diagnostic) {
+        self.diagnostics.push(diagnostic);
+    }
+
+    /// Get all diagnostics, in the order they were found
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        &self.diagnostics
+    }
+
+    /// Iterate over the errors
+    pub fn errors(&self) -> impl Iterator<Item = &Diagnostic> {
+        self.diagnostics.iter().filter(|d| d.is_error())
+    }
+
+    /// Iterate over the warnings
+    pub fn warnings(&self) -> impl Iterator<Item = &Diagnostic> {
+        self.diagnostics.iter().filter(|d| !d.is_error())
+    }
+
+    /// Check whether there are any errors
+    pub fn has_errors(&self) -> bool {
+        self.errors().next().is_some()
+    }
+
+    /// Check whether nothing was found
+    pub fn is_empty(&self) -> bool {
+        self.diagnostics.is_empty()
+    }
+}
+
+impl Extend<Diagnostic> for ValidationReport {
+    fn extend<T: IntoIterator<Item = Diagnostic>>(&mut self, iter: T) {
+        self.diagnostics.extend(iter);
+    }
+}
+
+impl IntoIterator for ValidationReport {
+    type Item = Diagnostic;
+    type IntoIter = std::vec::IntoIter<Diagnostic>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.diagnostics.into_iter()
+    }
+}
+
+impl fmt::Display for ValidationReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for diagnostic in &self.diagnostics {
+            writeln!(f, "{diagnostic}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Receiver for diagnostics produced while writing a package
+///
+/// Returning an error aborts the write, which lets callers treat warnings
+/// as errors. Closures taking a [`Diagnostic`] implement this trait.
+pub trait DiagnosticSink: Send + Sync {
+    /// Handle a diagnostic
+    fn report(&self, diagnostic: Diagnostic) -> Result<()>;
+}
+
+impl<F> DiagnosticSink for F
+where
+    F: Fn(Diagnostic) -> Result<()> + Send + Sync,
+{
+    fn report(&self, diagnostic: Diagnostic) -> Result<()> {
+        self(diagnostic)
+    }
+}
+
+/// Print diagnostics to stderr; the default sink
+#[derive(Debug, Clone, Copy, Default)]
+pub struct StderrDiagnostics;
+
+impl DiagnosticSink for StderrDiagnostics {
+    fn report(&self, diagnostic: Diagnostic) -> Result<()> {
+        eprintln!("{diagnostic}");
+        Ok(())
+    }
+}
+
+/// Silently drop diagnostics
+#[derive(Debug, Clone, Copy, Default)]
+pub struct IgnoreDiagnostics;
+
+impl DiagnosticSink for IgnoreDiagnostics {
+    fn report(&self, _diagnostic: Diagnostic) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Fail with [`Error::Validation`] on the first diagnostic
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DenyDiagnostics;
+
+impl DiagnosticSink for DenyDiagnostics {
+    fn report(&self, diagnostic: Diagnostic) -> Result<()> {
+        Err(Error::Validation(diagnostic.to_string()))
+    }
+}
+
+/// Keep diagnostics for later inspection
+#[derive(Debug, Default)]
+pub struct CollectDiagnostics {
+    diagnostics: Mutex<Vec<Diagnostic>>,
+}
+
+impl CollectDiagnostics {
+    /// Create an empty collector
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Take the diagnostics collected so far, leaving the collector empty
+    pub fn take(&self) -> Vec<Diagnostic> {
+        std::mem::take(
+            &mut *self
+                .diagnostics
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner),
+        )
+    }
+}
+
+impl DiagnosticSink for CollectDiagnostics {
+    fn report(&self, diagnostic: Diagnostic) -> Result<()> {
+        self.diagnostics
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(diagnostic);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_diagnostic_display() {
+        let diagnostic = Diagnostic::error(
+            Location::Template {
+                model: "Basic".to_string(),
+                index: 0,
+                name: "Card 1".to_string(),
+                side: Some(TemplateSide::Question),
+                position: Some(TextPosition { line: 2, column: 5 }),
+            },
+            "no field called 'Frnt'",
+        );
+        assert!(diagnostic.is_error());
+        assert_eq!(
+            diagnostic.to_string(),
+            "error: model 'Basic', template 'Card 1' (question), line 2, column 5: \
+             no field called 'Frnt'"
+        );
+    }
+
+    #[test]
+    fn test_sinks() {
+        let diagnostic = Diagnostic::warning(
+            Location::Model {
+                model: "Basic".to_string(),
+            },
+            "careful",
+        );
+
+        let collect = CollectDiagnostics::new();
+        collect.report(diagnostic.clone()).unwrap();
+        assert_eq!(collect.take(), vec![diagnostic.clone()]);
+        assert!(collect.take().is_empty());
+
+        assert!(IgnoreDiagnostics.report(diagnostic.clone()).is_ok());
+        assert!(matches!(
+            DenyDiagnostics.report(diagnostic.clone()),
+            Err(Error::Validation(msg)) if msg == "warning: model 'Basic': careful"
+        ));
+
+        let closure = |d: Diagnostic| Err(Error::Config(d.message));
+        assert!(matches!(closure.report(diagnostic), Err(Error::Config(_))));
+    }
+}
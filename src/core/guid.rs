@@ -1,9 +1,72 @@
 //! GUID generation for notes
 //!
 //! This module provides functionality to generate globally unique identifiers
-//! based on note field values using BLAKE3 hashing.
+//! for notes. The default strategy hashes the note fields with BLAKE3;
+//! [`GuidStrategy`] also offers GUIDs compatible with Python genanki and
+//! Anki's own random GUIDs.
 
 use crate::core::config::FIELD_SEPARATOR_STR;
+use sha2::{Digest, Sha256};
+
+/// Alphabet used by Anki and genanki for base91-encoded GUIDs
+const BASE91_TABLE: &[u8; 91] =
+    b"abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789!#$%&()*+,-./:;<=>?@[]^_`{|}~";
+
+/// Strategy used to generate note GUIDs
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum GuidStrategy {
+    /// BLAKE3 hash of the fields, as 64 hex characters (see [`guid_for`])
+    #[default]
+    Blake3,
+    /// Base91 of the SHA-256 of the fields, identical to Python genanki's
+    /// `guid_for` (see [`genanki_guid_for`])
+    Genanki,
+    /// Random 64-bit value in base91, like notes created in Anki itself
+    /// (see [`random_guid`])
+    AnkiRandom,
+}
+
+impl GuidStrategy {
+    /// Generate a GUID for a note with the given fields
+    pub fn generate(&self, fields: &[String]) -> String {
+        match self {
+            GuidStrategy::Blake3 => guid_for(fields),
+            GuidStrategy::Genanki => genanki_guid_for(fields),
+            GuidStrategy::AnkiRandom => random_guid(),
+        }
+    }
+
+    /// Check whether a GUID has the format produced by this strategy
+    pub fn is_valid(&self, guid: &str) -> bool {
+        match self {
+            GuidStrategy::Blake3 => {
+                // 64 hex characters (full BLAKE3) or 32 hex characters (truncated)
+                (guid.len() == 64 || guid.len() == 32)
+                    && guid.chars().all(|c| c.is_ascii_hexdigit())
+            }
+            GuidStrategy::Genanki | GuidStrategy::AnkiRandom => {
+                // A u64 takes at most 10 base91 digits
+                (1..=10).contains(&guid.len()) && guid.bytes().all(|b| BASE91_TABLE.contains(&b))
+            }
+        }
+    }
+
+    /// Guess the strategy that produced `guid` for a note with `fields`
+    ///
+    /// Used for notes read from a package. Genanki and Anki GUIDs share the
+    /// base91 format, so a GUID is only detected as [`GuidStrategy::Genanki`]
+    /// if genanki generates it from `fields`, and as
+    /// [`GuidStrategy::AnkiRandom`] otherwise. Returns `None` for GUIDs no
+    /// format matches.
+    pub fn detect(guid: &str, fields: &[String]) -> Option<Self> {
+        if genanki_guid_for(fields) == guid {
+            return Some(GuidStrategy::Genanki);
+        }
+        [GuidStrategy::Blake3, GuidStrategy::AnkiRandom]
+            .into_iter()
+            .find(|strategy| strategy.is_valid(guid))
+    }
+}
 
 /// Generates a GUID based on the provided fields.
 ///
@@ -51,24 +114,59 @@ pub fn guid_for_short(fields: &[String]) -> String {
     hex::encode(&hash.as_bytes()[..16])
 }
 
-/// Validates that a GUID string is properly formatted
+/// Generates a GUID the same way as Python genanki's `guid_for`
 ///
-/// # Arguments
+/// The fields are joined with `__`, hashed with SHA-256, and the first
+/// 8 bytes of the digest are encoded in base91. Decks migrated from genanki
+/// keep their GUIDs, so re-importing them updates notes instead of
+/// duplicating them.
 ///
-/// * `guid` - The GUID string to validate
+/// # Example
 ///
-/// # Returns
+/// ```
+/// use genanki_rs_rev::core::guid::genanki_guid_for;
 ///
-/// `true` if the GUID is valid, `false` otherwise
-pub fn is_valid_guid(guid: &str) -> bool {
-    // GUIDs should be 64 hex characters (full BLAKE3) or 32 hex characters (truncated)
-    let len = guid.len();
-    if len != 64 && len != 32 {
-        return false;
+/// let fields = vec!["Question".to_string(), "Answer".to_string()];
+/// assert_eq!(genanki_guid_for(&fields), "l5<zIi`lY5");
+/// ```
+pub fn genanki_guid_for(fields: &[String]) -> String {
+    let digest = Sha256::digest(fields.join("__").as_bytes());
+    let mut bytes = [0u8; 8];
+    bytes.copy_from_slice(&digest[..8]);
+    base91(u64::from_be_bytes(bytes))
+}
+
+/// Generates a random GUID like the ones Anki assigns to new notes
+pub fn random_guid() -> String {
+    base91(rand::random())
+}
+
+/// Encode a number in base91, most significant digit first
+fn base91(mut num: u64) -> String {
+    let mut digits = Vec::new();
+    while num > 0 {
+        digits.push(BASE91_TABLE[(num % 91) as usize]);
+        num /= 91;
     }
+    digits.reverse();
+    String::from_utf8(digits).expect("base91 table is ASCII")
+}
 
-    // Check all characters are valid hex
-    guid.chars().all(|c| c.is_ascii_hexdigit())
+/// Validates that a GUID string has the format produced by `strategy`
+///
+/// [`Note::has_valid_guid`](crate::core::Note::has_valid_guid) checks a
+/// note's GUID against the strategy the note was created with.
+///
+/// # Example
+///
+/// ```
+/// use genanki_rs_rev::core::guid::{GuidStrategy, is_valid_guid};
+///
+/// assert!(is_valid_guid("l5<zIi`lY5", GuidStrategy::Genanki));
+/// assert!(!is_valid_guid("l5<zIi`lY5", GuidStrategy::Blake3));
+/// ```
+pub fn is_valid_guid(guid: &str, strategy: GuidStrategy) -> bool {
+    strategy.is_valid(guid)
 }
 
 #[cfg(test)]
@@ -80,7 +178,7 @@ mod tests {
         let fields = vec!["Question".to_string(), "Answer".to_string()];
         let guid = guid_for(&fields);
         assert_eq!(guid.len(), 64);
-        assert!(is_valid_guid(&guid));
+        assert!(is_valid_guid(&guid, GuidStrategy::Blake3));
     }
 
     #[test]
@@ -88,7 +186,7 @@ mod tests {
         let fields = vec!["Question".to_string(), "Answer".to_string()];
         let guid = guid_for_short(&fields);
         assert_eq!(guid.len(), 32);
-        assert!(is_valid_guid(&guid));
+        assert!(is_valid_guid(&guid, GuidStrategy::Blake3));
     }
 
     #[test]
@@ -108,11 +206,51 @@ mod tests {
 
     #[test]
     fn test_is_valid_guid() {
+        let blake3 = GuidStrategy::Blake3;
         assert!(is_valid_guid(
-            &"0123456789abcdef0123456789abcdef0123456789abcdef0123456789abcdef"
+            "0123456789abcdef0123456789abcdef0123456789abcdef0123456789abcdef",
+            blake3
         ));
-        assert!(is_valid_guid(&"0123456789abcdef0123456789abcdef"));
-        assert!(!is_valid_guid(&"invalid"));
-        assert!(!is_valid_guid(&"0123456789abcdef"));
+        assert!(is_valid_guid("0123456789abcdef0123456789abcdef", blake3));
+        assert!(!is_valid_guid("invalid", blake3));
+        assert!(!is_valid_guid("0123456789abcdef", blake3));
+        assert!(is_valid_guid("invalid", GuidStrategy::AnkiRandom));
+    }
+
+    #[test]
+    fn test_detect() {
+        let fields = vec!["Question".to_string(), "Answer".to_string()];
+        let detect = |guid: &str| GuidStrategy::detect(guid, &fields);
+        assert_eq!(detect(&guid_for(&fields)), Some(GuidStrategy::Blake3));
+        assert_eq!(
+            detect(&genanki_guid_for(&fields)),
+            Some(GuidStrategy::Genanki)
+        );
+        assert_eq!(detect(&random_guid()), Some(GuidStrategy::AnkiRandom));
+        assert_eq!(detect("not base91 ✗"), None);
+    }
+
+    #[test]
+    fn test_genanki_guid_for() {
+        let fields = vec!["Question".to_string(), "Answer".to_string()];
+        let guid = genanki_guid_for(&fields);
+        assert_eq!(guid, "l5<zIi`lY5");
+        assert!(is_valid_guid(&guid, GuidStrategy::Genanki));
+        assert!(!is_valid_guid(&guid, GuidStrategy::Blake3));
+    }
+
+    #[test]
+    fn test_random_guid() {
+        let guid = GuidStrategy::AnkiRandom.generate(&[]);
+        assert!(is_valid_guid(&guid, GuidStrategy::AnkiRandom));
+        assert_ne!(guid, random_guid());
+    }
+
+    #[test]
+    fn test_base91() {
+        assert_eq!(base91(0), "");
+        assert_eq!(base91(90), "~");
+        assert_eq!(base91(91), "ba");
+        assert_eq!(base91(u64::MAX).len(), 10);
     }
 }
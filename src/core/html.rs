@@ -0,0 +1,194 @@
+//! HTML helpers matching Anki's text processing
+//!
+//! Anki strips HTML from field contents when it computes the sort field,
+//! the duplicate-detection checksum and plain-text renderings.
+
+use fancy_regex::{Captures, Regex};
+use std::sync::LazyLock;
+
+static HTML_TAGS: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?si)<!--.*?-->|<.*?>").unwrap());
+
+static MEDIA_TAGS: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"(?si)<img[^>]*?src=(?:"([^"]+)"|'([^']+)'|([^ >]+))[^>]*>"#).unwrap()
+});
+
+static MEDIA_ATTRIBUTES: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r#"(?si)<(?:img|audio|video|source)\b[^>]*?\bsrc=(?:"([^"]+)"|'([^']+)'|([^ >]+))|<object\b[^>]*?\bdata=(?:"([^"]+)"|'([^']+)'|([^ >]+))"#,
+    )
+    .unwrap()
+});
+
+static SOUND_TAGS: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\[sound:(.+?)\]").unwrap());
+
+static ENTITIES: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"&(#[0-9]+|#[xX][0-9a-fA-F]+|[a-zA-Z]+);").unwrap());
+
+/// Remove all HTML tags and comments, then decode entities
+pub fn strip_html(html: &str) -> String {
+    decode_entities(&HTML_TAGS.replace_all(html, ""))
+}
+
+/// Like [`strip_html`], but keep the filenames of `<img>` tags
+///
+/// This is what Anki uses for the sort field and checksum, so that notes
+/// differing only in their images are not considered duplicates.
+pub fn strip_html_preserving_media_filenames(html: &str) -> String {
+    let without_media = MEDIA_TAGS.replace_all(html, |caps: &Captures| {
+        let filename = (1..=3).find_map(|i| caps.get(i)).map_or("", |m| m.as_str());
+        format!(" {filename} ")
+    });
+    strip_html(&without_media)
+}
+
+/// Filenames of local media referenced by `<img src="...">`, `[sound:...]`
+/// and the `src` or `data` of `<audio>`, `<video>`, `<source>` and `<object>`
+///
+/// Remote URLs and `data:` URIs are skipped, since they are not part of the
+/// package.
+pub fn media_references(html: &str) -> Vec<String> {
+    let tags = MEDIA_ATTRIBUTES
+        .captures_iter(html)
+        .filter_map(|caps| caps.ok())
+        .filter_map(|caps| {
+            (1..=6)
+                .find_map(|i| caps.get(i))
+                .map(|m| decode_entities(m.as_str()))
+        });
+    let sounds = SOUND_TAGS
+        .captures_iter(html)
+        .filter_map(|caps| caps.ok())
+        .map(|caps| caps[1].to_string());
+    tags.chain(sounds)
+        .filter(|name| !name.contains("://") && !name.starts_with("data:"))
+        .collect()
+}
+
+/// Replace the filenames referenced by the media tags in `html`
+///
+/// `rename` is called with each filename found by [`media_references`],
+/// remote URLs included, and returns the new name or `None` to keep it.
+pub fn rewrite_media_references(html: &str, rename: impl Fn(&str) -> Option<String>) -> String {
+    let html = MEDIA_ATTRIBUTES.replace_all(html, |caps: &Captures| {
+        let whole = caps.get(0).expect("group 0 always matches");
+        let Some((index, value)) = (1..=6).find_map(|i| caps.get(i).map(|m| (i, m))) else {
+            return whole.as_str().to_string();
+        };
+        let Some(new_name) = rename(&decode_entities(value.as_str())) else {
+            return whole.as_str().to_string();
+        };
+        let escaped = escape_attribute(&new_name);
+        // Unquoted values get quotes, in case the new name has spaces
+        let replacement = if index % 3 == 0 {
+            format!("\"{escaped}\"")
+        } else {
+            escaped
+        };
+        let start = value.start() - whole.start();
+        let end = value.end() - whole.start();
+        format!(
+            "{}{replacement}{}",
+            &whole.as_str()[..start],
+            &whole.as_str()[end..]
+        )
+    });
+    SOUND_TAGS
+        .replace_all(&html, |caps: &Captures| match rename(&caps[1]) {
+            Some(new_name) => format!("[sound:{new_name}]"),
+            None => caps[0].to_string(),
+        })
+        .into_owned()
+}
+
+fn escape_attribute(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+/// Decode named and numeric HTML character references
+pub fn decode_entities(text: &str) -> String {
+    if !text.contains('&') {
+        return text.to_string();
+    }
+    ENTITIES
+        .replace_all(text, |caps: &Captures| {
+            let entity = &caps[1];
+            let decoded = if let Some(hex) = entity
+                .strip_prefix("#x")
+                .or_else(|| entity.strip_prefix("#X"))
+            {
+                u32::from_str_radix(hex, 16).ok().and_then(char::from_u32)
+            } else if let Some(dec) = entity.strip_prefix('#') {
+                dec.parse().ok().and_then(char::from_u32)
+            } else {
+                match entity {
+                    "nbsp" => Some(' '),
+                    "amp" => Some('&'),
+                    "lt" => Some('<'),
+                    "gt" => Some('>'),
+                    "quot" => Some('"'),
+                    "apos" => Some('\''),
+                    _ => None,
+                }
+            };
+            decoded.map_or_else(|| caps[0].to_string(), String::from)
+        })
+        .into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_strip_html() {
+        assert_eq!(
+            strip_html("<b>Bold</b> &amp; <i>italic</i>"),
+            "Bold & italic"
+        );
+        assert_eq!(strip_html("a<!-- comment -->b"), "ab");
+        assert_eq!(strip_html("x&nbsp;&#65;&#x42;&unknown;"), "x AB&unknown;");
+    }
+
+    #[test]
+    fn test_strip_html_preserving_media_filenames() {
+        assert_eq!(
+            strip_html_preserving_media_filenames(r#"Flag <img src="fr.png">"#),
+            "Flag  fr.png "
+        );
+        assert_eq!(
+            strip_html_preserving_media_filenames("<img src=de.png>"),
+            " de.png "
+        );
+    }
+
+    #[test]
+    fn test_media_references() {
+        assert_eq!(
+            media_references(
+                "<img src=\"a&amp;b.png\"> [sound:c.mp3] <img src='https://x.org/d.png'> \
+                 <video controls src=e.webm></video><object data=\"f.svg\">"
+            ),
+            vec!["a&b.png", "e.webm", "f.svg", "c.mp3"]
+        );
+    }
+
+    #[test]
+    fn test_rewrite_media_references() {
+        let rename = |name: &str| match name {
+            "a&b.png" => Some("a and b.png".to_string()),
+            "c.mp3" => Some("d.mp3".to_string()),
+            _ => None,
+        };
+        assert_eq!(
+            rewrite_media_references(
+                "<img class=x src=\"a&amp;b.png\"> <img src=a&amp;b.png> [sound:c.mp3] <img src='e.png'>",
+                rename
+            ),
+            "<img class=x src=\"a and b.png\"> <img src=\"a and b.png\"> [sound:d.mp3] <img src='e.png'>"
+        );
+    }
+}
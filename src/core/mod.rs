@@ -6,15 +6,30 @@
 pub mod card;
 pub mod config;
 pub mod deck;
+pub mod deck_options;
+pub mod diagnostics;
 pub mod guid;
+pub mod html;
 pub mod model;
 pub mod note;
+pub mod render;
+pub mod revlog;
 
 // Re-exports for convenience
 pub use crate::error::{Error, Result};
-pub use card::Card;
+pub use card::{Burial, Card, CardType, Due, Flag, FsrsState, Scheduling};
 pub use config::{AnkiConfig, DeckConfig, FieldDefaults, ModelConfig, ModelIds};
 pub use deck::Deck;
-pub use guid::guid_for;
-pub use model::{Field, Model, ModelType, Template};
+pub use deck_options::{DeckOptions, LeechAction};
+pub use diagnostics::{
+    CollectDiagnostics, DenyDiagnostics, Diagnostic, DiagnosticSink, IgnoreDiagnostics, Location,
+    Severity, StderrDiagnostics, TemplateSide, ValidationReport,
+};
+pub use guid::{GuidStrategy, guid_for};
+pub use model::parser::{
+    FieldRequirements, ParsedTemplate, SyntaxError, TemplateNode, TextPosition,
+};
+pub use model::{Field, Model, ModelType, Template, TemplateReq};
 pub use note::Note;
+pub use render::RenderedCard;
+pub use revlog::{Rating, ReviewKind, RevlogEntry, RevlogInterval};
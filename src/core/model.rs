@@ -2,14 +2,18 @@
 //!
 //! A model defines the structure of notes, including fields and templates.
 
+pub mod parser;
+
 use crate::core::config::ModelConfig;
+use crate::core::diagnostics::{Diagnostic, Location, TemplateSide};
 use crate::error::{Error, Result};
-use fancy_regex::Regex;
-use ramhorns::Template as RamTemplate;
-use std::collections::HashMap;
+use parser::{FieldRequirements, ParsedTemplate, is_special_field};
+use std::collections::{HashMap, HashSet};
 use std::fs::File;
+use std::hash::{DefaultHasher, Hash, Hasher};
 use std::io::Read;
 use std::path::Path;
+use std::sync::{Arc, Mutex, PoisonError};
 
 // Re-export ModelType from config for convenience
 pub use crate::core::config::ModelType;
@@ -110,6 +114,26 @@ impl Field {
     }
 }
 
+/// Required fields of a template: card ord, `"any"`, `"all"` or `"none"`, and field ords
+pub type TemplateReq = (usize, String, Vec<usize>);
+
+/// Parsed question templates, shared between clones of a model
+///
+/// Entries are keyed by a fingerprint of the question templates and keep
+/// the templates they were parsed from, so editing the public fields of a
+/// model invalidates them and hash collisions are detected.
+#[derive(Clone, Default)]
+struct TemplateCache(Arc<Mutex<HashMap<u64, CachedTemplates>>>);
+
+/// Question templates and their parsed form
+struct CachedTemplates {
+    qfmts: Vec<String>,
+    parsed: Arc<Vec<ParsedTemplate>>,
+}
+
+/// Template versions kept per model before the cache is cleared
+const TEMPLATE_CACHE_CAPACITY: usize = 16;
+
 /// A model defines the structure of notes
 #[derive(Clone)]
 pub struct Model {
@@ -122,6 +146,7 @@ pub struct Model {
     pub latex_pre: String,
     pub latex_post: String,
     pub sort_field_index: i64,
+    template_cache: TemplateCache,
 }
 
 impl Model {
@@ -137,6 +162,7 @@ impl Model {
             latex_pre: ModelConfig::default().latex_pre.to_string(),
             latex_post: ModelConfig::default().latex_post.to_string(),
             sort_field_index: 0,
+            template_cache: TemplateCache::default(),
         }
     }
 
@@ -164,6 +190,7 @@ impl Model {
             latex_pre: latex_pre.unwrap_or(config.latex_pre).to_string(),
             latex_post: latex_post.unwrap_or(config.latex_post).to_string(),
             sort_field_index: sort_field_index.unwrap_or(0),
+            template_cache: TemplateCache::default(),
         }
     }
 
@@ -225,68 +252,410 @@ impl Model {
     }
 
     /// Calculate required fields for each template
-    pub fn req(&self) -> Result<Vec<(usize, String, Vec<usize>)>> {
-        let sentinel = "SeNtInEl".to_string();
-        let field_names: Vec<String> = self.fields.iter().map(|field| field.name.clone()).collect();
-        let field_values: HashMap<&str, String> = field_names
+    ///
+    /// Uses Anki's algorithm on the parsed question templates, so filters,
+    /// sections and special fields are understood. Templates that render no
+    /// field content get the kind `"none"`.
+    pub fn req(&self) -> Result<Vec<TemplateReq>> {
+        let field_names = self.field_names();
+        Ok(self
+            .parsed_templates()?
             .iter()
-            .map(|field| (field.as_str(), format!("{}{}", &field, &sentinel)))
-            .collect();
-
-        let mut req = Vec::new();
+            .enumerate()
+            .map(|(ord, template)| {
+                let requirements = template.requirements(&field_names);
+                (
+                    ord,
+                    requirements.kind().to_string(),
+                    requirements.field_ords().to_vec(),
+                )
+            })
+            .collect())
+    }
 
-        for (template_ord, template) in self.templates.iter().enumerate() {
-            let rendered = RamTemplate::new(template.qfmt.clone())?
-                .render::<HashMap<&str, String>>(&field_values);
+    /// Parse the question template of each card type
+    ///
+    /// The result is cached and shared by clones of the model, so notes
+    /// created from the same model only parse the templates once.
+    pub fn parsed_templates(&self) -> Result<Arc<Vec<ParsedTemplate>>> {
+        let fingerprint = self.template_fingerprint();
+        let lock = || {
+            self.template_cache
+                .0
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+        };
+        if let Some(cached) = lock().get(&fingerprint)
+            && cached
+                .qfmts
+                .iter()
+                .eq(self.templates.iter().map(|template| &template.qfmt))
+        {
+            return Ok(Arc::clone(&cached.parsed));
+        }
 
-            // Try "all" first
-            let required_fields: Vec<usize> = field_values
+        // Parse without holding the lock
+        let parsed = Arc::new(
+            self.templates
                 .iter()
-                .enumerate()
-                .filter(|(_, (_, value))| !contains_other_fields(&rendered, value, &sentinel))
-                .map(|(idx, _)| idx)
-                .collect();
-
-            if !required_fields.is_empty() {
-                req.push((template_ord, "all".to_string(), required_fields));
-                continue;
+                .map(|template| parse_template(&template.name, &template.qfmt))
+                .collect::<Result<Vec<_>>>()?,
+        );
+        let mut cache = lock();
+        if cache.len() >= TEMPLATE_CACHE_CAPACITY {
+            cache.clear();
+        }
+        cache.insert(
+            fingerprint,
+            CachedTemplates {
+                qfmts: self.templates.iter().map(|t| t.qfmt.clone()).collect(),
+                parsed: Arc::clone(&parsed),
+            },
+        );
+        Ok(parsed)
+    }
+
+    /// Lint the model
+    ///
+    /// Reports unknown field references, template syntax errors, duplicate
+    /// names, blank front sides, cloze templates without `{{cloze:}}`, an
+    /// out of range sort field and `{{FrontSide}}` on the question side.
+    /// An empty result means nothing was found.
+    pub fn validate(&self) -> Vec<Diagnostic> {
+        let mut diagnostics = Vec::new();
+
+        let mut seen = HashSet::new();
+        for (index, field) in self.fields.iter().enumerate() {
+            if !seen.insert(field.name.as_str()) {
+                diagnostics.push(Diagnostic::error(
+                    Location::Field {
+                        model: self.name.clone(),
+                        index,
+                        name: field.name.clone(),
+                    },
+                    format!("duplicate field name '{}'", field.name),
+                ));
+            }
+        }
+
+        let mut seen = HashSet::new();
+        for (index, template) in self.templates.iter().enumerate() {
+            if !seen.insert(template.name.as_str()) {
+                diagnostics.push(Diagnostic::error(
+                    self.template_location(index, None, None),
+                    format!("duplicate template name '{}'", template.name),
+                ));
             }
+        }
 
-            // Try "any"
-            let required_fields: Vec<usize> = field_values
-                .iter()
-                .enumerate()
-                .filter(|(_, (_, value))| rendered.contains(value.as_str()))
-                .map(|(idx, _)| idx)
-                .collect();
+        let sort_field_in_range =
+            usize::try_from(self.sort_field_index).is_ok_and(|index| index < self.fields.len());
+        if !sort_field_in_range {
+            diagnostics.push(Diagnostic::error(
+                Location::Model {
+                    model: self.name.clone(),
+                },
+                format!(
+                    "sort field index {} is out of range for {} fields",
+                    self.sort_field_index,
+                    self.fields.len()
+                ),
+            ));
+        }
 
-            if required_fields.is_empty() {
-                return Err(Error::TemplateFormat(template.name.clone()));
+        for (index, template) in self.templates.iter().enumerate() {
+            self.validate_template(
+                index,
+                TemplateSide::Question,
+                &template.qfmt,
+                &mut diagnostics,
+            );
+            self.validate_template(
+                index,
+                TemplateSide::Answer,
+                &template.afmt,
+                &mut diagnostics,
+            );
+        }
+
+        diagnostics
+    }
+
+    fn validate_template(
+        &self,
+        index: usize,
+        side: TemplateSide,
+        text: &str,
+        diagnostics: &mut Vec<Diagnostic>,
+    ) {
+        let parsed = match ParsedTemplate::parse_located(text) {
+            Ok(parsed) => parsed,
+            Err(err) => {
+                diagnostics.push(Diagnostic::error(
+                    self.template_location(index, Some(side), Some(err.position)),
+                    err.message,
+                ));
+                return;
+            }
+        };
+
+        let field_names = self.field_names();
+        for (position, key) in parsed.field_tags() {
+            let location = || self.template_location(index, Some(side), Some(position));
+            if key == "FrontSide" && side == TemplateSide::Question {
+                diagnostics.push(Diagnostic::warning(
+                    location(),
+                    "'{{FrontSide}}' is empty on the question side",
+                ));
+            } else if !field_names.contains(&key) && !is_special_field(key) {
+                diagnostics.push(Diagnostic::error(
+                    location(),
+                    format!("no field called '{key}'"),
+                ));
             }
+        }
 
-            req.push((template_ord, "any".to_string(), required_fields))
+        match self.model_type {
+            ModelType::Basic => {
+                if side == TemplateSide::Question
+                    && parsed.requirements(&field_names) == FieldRequirements::None
+                {
+                    diagnostics.push(Diagnostic::error(
+                        self.template_location(index, Some(side), None),
+                        "the front side shows no fields, so no cards will be generated",
+                    ));
+                }
+            }
+            ModelType::Cloze => {
+                if parsed.cloze_fields().is_empty() {
+                    diagnostics.push(Diagnostic::error(
+                        self.template_location(index, Some(side), None),
+                        "cloze templates need a '{{cloze:Field}}' replacement",
+                    ));
+                }
+            }
         }
+    }
 
-        Ok(req)
+    fn template_location(
+        &self,
+        index: usize,
+        side: Option<TemplateSide>,
+        position: Option<parser::TextPosition>,
+    ) -> Location {
+        Location::Template {
+            model: self.name.clone(),
+            index,
+            name: self.templates[index].name.clone(),
+            side,
+            position,
+        }
+    }
+
+    /// Hash of everything written to the collection for this model
+    ///
+    /// Two models with the same ID but different fingerprints conflict.
+    pub(crate) fn definition_fingerprint(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        self.name.hash(&mut hasher);
+        for field in &self.fields {
+            field.name.hash(&mut hasher);
+        }
+        for template in &self.templates {
+            (&template.name, &template.qfmt, &template.afmt).hash(&mut hasher);
+        }
+        (
+            &self.css,
+            self.model_type,
+            &self.latex_pre,
+            &self.latex_post,
+        )
+            .hash(&mut hasher);
+        self.sort_field_index.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    /// Hash of everything the parsed templates depend on
+    fn template_fingerprint(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        for template in &self.templates {
+            template.qfmt.hash(&mut hasher);
+        }
+        hasher.finish()
     }
 }
 
-fn contains_other_fields(rendered: &str, current_field: &str, sentinel: &str) -> bool {
-    let pattern = format!(
-        r"(?!{field}\b)\b(\w)*{sentinel}+",
-        field = current_field,
-        sentinel = sentinel
-    );
-    Regex::new(&pattern)
-        .unwrap()
-        .is_match(rendered)
-        .unwrap_or(false)
+/// Parse a template, naming it in error messages
+pub(crate) fn parse_template(name: &str, text: &str) -> Result<ParsedTemplate> {
+    ParsedTemplate::parse(text).map_err(|err| match err {
+        Error::TemplateFormat(msg) => Error::TemplateFormat(format!("template '{name}': {msg}")),
+        other => other,
+    })
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_template_cache_is_shared_and_invalidated() {
+        let model = Model::new(
+            123,
+            "Test Model",
+            vec![Field::new("Question"), Field::new("Answer")],
+            vec![Template::new("Card 1").qfmt("{{Question}}")],
+        );
+        let clone = model.clone();
+        assert!(Arc::ptr_eq(
+            &model.parsed_templates().unwrap(),
+            &clone.parsed_templates().unwrap()
+        ));
+
+        let mut edited = model.clone();
+        edited.templates[0].qfmt = "{{#Question}}{{Answer}}{{/Question}}".to_string();
+        assert_eq!(
+            edited.req().unwrap(),
+            vec![(0, "all".to_string(), vec![0, 1])]
+        );
+        assert_eq!(model.req().unwrap(), vec![(0, "any".to_string(), vec![0])]);
+    }
+
+    #[test]
+    fn test_template_cache_checks_templates_on_hit() {
+        let fields = vec![Field::new("Question"), Field::new("Answer")];
+        let model = Model::new(
+            123,
+            "Test Model",
+            fields.clone(),
+            vec![Template::new("Card 1").qfmt("{{Question}}")],
+        );
+        let other = Model::new(
+            456,
+            "Other",
+            fields,
+            vec![Template::new("Card 1").qfmt("{{Answer}}")],
+        );
+        // Pretend the other model's templates collided with this model's
+        model.template_cache.0.lock().unwrap().insert(
+            model.template_fingerprint(),
+            CachedTemplates {
+                qfmts: vec!["{{Answer}}".to_string()],
+                parsed: other.parsed_templates().unwrap(),
+            },
+        );
+        assert_eq!(model.req().unwrap(), vec![(0, "any".to_string(), vec![0])]);
+    }
+
+    #[test]
+    fn test_req_reports_template_errors() {
+        let model = Model::new(
+            123,
+            "Test Model",
+            vec![Field::new("Question")],
+            vec![Template::new("Card 1").qfmt("{{#Question}}")],
+        );
+        assert!(matches!(
+            model.req(),
+            Err(Error::TemplateFormat(msg))
+                if msg == "template 'Card 1': line 1, column 1: section 'Question' is never closed"
+        ));
+    }
+
+    #[test]
+    fn test_validate_reports_each_problem() {
+        use crate::core::diagnostics::Severity;
+
+        let model = Model::new(
+            1,
+            "Lint",
+            vec![Field::new("Front"), Field::new("Front")],
+            vec![
+                Template::new("Card 1")
+                    .qfmt("{{FrontSide}}\n{{Frnt}}")
+                    .afmt("{{#Front}}"),
+                Template::new("Card 1").qfmt("{{Front}}").afmt("{{Front}}"),
+            ],
+        )
+        .sort_field_index(2);
+
+        let found: Vec<(Severity, String)> = model
+            .validate()
+            .into_iter()
+            .map(|d| (d.severity, d.to_string()))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                (
+                    Severity::Error,
+                    "error: model 'Lint', field 'Front': duplicate field name 'Front'".to_string()
+                ),
+                (
+                    Severity::Error,
+                    "error: model 'Lint', template 'Card 1': duplicate template name 'Card 1'"
+                        .to_string()
+                ),
+                (
+                    Severity::Error,
+                    "error: model 'Lint': sort field index 2 is out of range for 2 fields"
+                        .to_string()
+                ),
+                (
+                    Severity::Warning,
+                    "warning: model 'Lint', template 'Card 1' (question), line 1, column 1: \
+                     '{{FrontSide}}' is empty on the question side"
+                        .to_string()
+                ),
+                (
+                    Severity::Error,
+                    "error: model 'Lint', template 'Card 1' (question), line 2, column 1: \
+                     no field called 'Frnt'"
+                        .to_string()
+                ),
+                (
+                    Severity::Error,
+                    "error: model 'Lint', template 'Card 1' (question): \
+                     the front side shows no fields, so no cards will be generated"
+                        .to_string()
+                ),
+                (
+                    Severity::Error,
+                    "error: model 'Lint', template 'Card 1' (answer), line 1, column 1: \
+                     section 'Front' is never closed"
+                        .to_string()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_validate_cloze_model() {
+        let model = Model::new(
+            2,
+            "Cloze",
+            vec![Field::new("Text")],
+            vec![
+                Template::new("Cloze")
+                    .qfmt("{{cloze:Text}}")
+                    .afmt("{{Text}}"),
+            ],
+        )
+        .model_type(ModelType::Cloze);
+        let diagnostics = model.validate();
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(
+            diagnostics[0].message,
+            "cloze templates need a '{{cloze:Field}}' replacement"
+        );
+        assert!(matches!(
+            diagnostics[0].location,
+            Location::Template {
+                side: Some(TemplateSide::Answer),
+                ..
+            }
+        ));
+    }
+
     #[test]
     fn test_model_new() {
         let model = Model::new(
@@ -0,0 +1,510 @@
+//! Parser for Anki's card template language
+//!
+//! Templates are plain text with `{{...}}` tags:
+//!
+//! * `{{Field}}` and `{{filter:Field}}` replacements, where filters such as
+//!   `text`, `hint`, `type`, `cloze` or `furigana` can be chained
+//!   (`{{text:hint:Field}}`)
+//! * `{{#Field}}...{{/Field}}` sections, shown when the field is non-empty
+//! * `{{^Field}}...{{/Field}}` inverted sections, shown when it is empty
+//!
+//! Special fields like `{{FrontSide}}`, `{{Tags}}`, `{{Deck}}` and `{{Card}}`
+//! parse as regular replacements; [`is_special_field`] tells them apart.
+
+use crate::error::{Error, Result};
+use std::collections::HashSet;
+use std::fmt;
+
+/// Fields Anki provides in addition to the note's own fields
+pub const SPECIAL_FIELDS: &[&str] = &[
+    "FrontSide",
+    "Tags",
+    "Type",
+    "Deck",
+    "Subdeck",
+    "Card",
+    "CardFlag",
+    "CardID",
+];
+
+/// Check whether a field name refers to one of Anki's [`SPECIAL_FIELDS`]
+pub fn is_special_field(name: &str) -> bool {
+    SPECIAL_FIELDS.contains(&name)
+}
+
+/// A node of a parsed template
+///
+/// Tags keep the position of their opening `{{`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TemplateNode {
+    /// Literal text, including HTML
+    Text(String),
+    /// `{{filter:...:key}}`; filters are listed in source order
+    Replacement {
+        key: String,
+        filters: Vec<String>,
+        position: TextPosition,
+    },
+    /// `{{#key}}...{{/key}}`
+    Conditional {
+        key: String,
+        children: Vec<TemplateNode>,
+        position: TextPosition,
+    },
+    /// `{{^key}}...{{/key}}`
+    NegatedConditional {
+        key: String,
+        children: Vec<TemplateNode>,
+        position: TextPosition,
+    },
+}
+
+/// Fields a template needs to produce a non-empty card
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FieldRequirements {
+    /// The card is generated if any of these fields is non-empty
+    Any(Vec<usize>),
+    /// The card is generated only if all of these fields are non-empty
+    All(Vec<usize>),
+    /// The template never renders anything based on the fields alone
+    None,
+}
+
+impl FieldRequirements {
+    /// The kind as written in the legacy `req` JSON: `"any"`, `"all"` or `"none"`
+    pub fn kind(&self) -> &'static str {
+        match self {
+            FieldRequirements::Any(_) => "any",
+            FieldRequirements::All(_) => "all",
+            FieldRequirements::None => "none",
+        }
+    }
+
+    /// The required field ordinals
+    pub fn field_ords(&self) -> &[usize] {
+        match self {
+            FieldRequirements::Any(ords) | FieldRequirements::All(ords) => ords,
+            FieldRequirements::None => &[],
+        }
+    }
+}
+
+/// Line and column in a template, both starting at 1
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct TextPosition {
+    pub line: usize,
+    pub column: usize,
+}
+
+impl TextPosition {
+    /// Position of a byte offset into `template`
+    fn at(template: &str, offset: usize) -> Self {
+        let before = &template[..offset];
+        Self {
+            line: before.matches('\n').count() + 1,
+            column: before[before.rfind('\n').map_or(0, |nl| nl + 1)..]
+                .chars()
+                .count()
+                + 1,
+        }
+    }
+}
+
+impl fmt::Display for TextPosition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}, column {}", self.line, self.column)
+    }
+}
+
+/// Syntax error in a template
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SyntaxError {
+    pub position: TextPosition,
+    pub message: String,
+}
+
+impl fmt::Display for SyntaxError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.position, self.message)
+    }
+}
+
+impl From<SyntaxError> for Error {
+    fn from(err: SyntaxError) -> Self {
+        Error::TemplateFormat(err.to_string())
+    }
+}
+
+/// Abstract syntax tree of a card template
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct ParsedTemplate {
+    nodes: Vec<TemplateNode>,
+}
+
+impl ParsedTemplate {
+    /// Parse a template
+    ///
+    /// Unclosed tags, empty field references and mismatched or unclosed
+    /// sections are reported as [`Error::TemplateFormat`] with the line and
+    /// column where they occur.
+    pub fn parse(template: &str) -> Result<Self> {
+        Ok(Self::parse_located(template)?)
+    }
+
+    /// Parse a template, keeping the position of syntax errors
+    pub fn parse_located(template: &str) -> std::result::Result<Self, SyntaxError> {
+        let mut stack: Vec<OpenSection> = Vec::new();
+        let mut nodes = Vec::new();
+        let mut rest = template;
+
+        while let Some(start) = rest.find("{{") {
+            if start > 0 {
+                nodes.push(TemplateNode::Text(rest[..start].to_string()));
+            }
+            let offset = template.len() - rest.len() + start;
+            let after_open = &rest[start + 2..];
+            let end = after_open
+                .find("}}")
+                .ok_or_else(|| parse_error(template, offset, "unclosed '{{'"))?;
+            let tag = after_open[..end].trim();
+            rest = &after_open[end + 2..];
+
+            match tag.chars().next() {
+                Some(kind @ ('#' | '^')) => {
+                    let key = section_key(template, offset, tag)?;
+                    stack.push(OpenSection {
+                        key: key.to_string(),
+                        negated: kind == '^',
+                        offset,
+                        parent: std::mem::take(&mut nodes),
+                    });
+                }
+                Some('/') => {
+                    let key = section_key(template, offset, tag)?;
+                    let Some(section) = stack.pop() else {
+                        return Err(parse_error(
+                            template,
+                            offset,
+                            &format!("'{{{{/{key}}}}}' closes a section that was never opened"),
+                        ));
+                    };
+                    if section.key != key {
+                        return Err(parse_error(
+                            template,
+                            offset,
+                            &format!(
+                                "'{{{{/{key}}}}}' does not match the open section '{}'",
+                                section.key
+                            ),
+                        ));
+                    }
+                    let children = std::mem::replace(&mut nodes, section.parent);
+                    let position = TextPosition::at(template, section.offset);
+                    nodes.push(if section.negated {
+                        TemplateNode::NegatedConditional {
+                            key: section.key,
+                            children,
+                            position,
+                        }
+                    } else {
+                        TemplateNode::Conditional {
+                            key: section.key,
+                            children,
+                            position,
+                        }
+                    });
+                }
+                _ => {
+                    let mut parts: Vec<&str> = tag.split(':').collect();
+                    let key = parts.pop().unwrap_or_default().trim();
+                    if key.is_empty() {
+                        return Err(parse_error(template, offset, "empty field reference"));
+                    }
+                    nodes.push(TemplateNode::Replacement {
+                        key: key.to_string(),
+                        filters: parts.iter().map(|f| f.trim().to_string()).collect(),
+                        position: TextPosition::at(template, offset),
+                    });
+                }
+            }
+        }
+
+        if !rest.is_empty() {
+            nodes.push(TemplateNode::Text(rest.to_string()));
+        }
+        if let Some(section) = stack.pop() {
+            return Err(parse_error(
+                template,
+                section.offset,
+                &format!("section '{}' is never closed", section.key),
+            ));
+        }
+
+        Ok(Self { nodes })
+    }
+
+    /// Get the top-level nodes
+    pub fn nodes(&self) -> &[TemplateNode] {
+        &self.nodes
+    }
+
+    /// Iterate over every node, depth first
+    pub fn walk(&self) -> impl Iterator<Item = &TemplateNode> {
+        let mut stack: Vec<&TemplateNode> = self.nodes.iter().rev().collect();
+        std::iter::from_fn(move || {
+            let node = stack.pop()?;
+            if let TemplateNode::Conditional { children, .. }
+            | TemplateNode::NegatedConditional { children, .. } = node
+            {
+                stack.extend(children.iter().rev());
+            }
+            Some(node)
+        })
+    }
+
+    /// Names of all fields referenced by replacements and sections
+    pub fn field_references(&self) -> Vec<&str> {
+        self.walk()
+            .filter_map(|node| match node {
+                TemplateNode::Text(_) => None,
+                TemplateNode::Replacement { key, .. }
+                | TemplateNode::Conditional { key, .. }
+                | TemplateNode::NegatedConditional { key, .. } => Some(key.as_str()),
+            })
+            .collect()
+    }
+
+    /// The field name of every replacement and section, with the position
+    /// of its tag, in source order
+    pub fn field_tags(&self) -> Vec<(TextPosition, &str)> {
+        self.walk()
+            .filter_map(|node| match node {
+                TemplateNode::Text(_) => None,
+                TemplateNode::Replacement { key, position, .. }
+                | TemplateNode::Conditional { key, position, .. }
+                | TemplateNode::NegatedConditional { key, position, .. } => {
+                    Some((*position, key.as_str()))
+                }
+            })
+            .collect()
+    }
+
+    /// Names of fields rendered with the `cloze` filter
+    pub fn cloze_fields(&self) -> Vec<&str> {
+        self.walk()
+            .filter_map(|node| match node {
+                TemplateNode::Replacement { key, filters, .. }
+                    if filters.iter().any(|f| f == "cloze") =>
+                {
+                    Some(key.as_str())
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Whether the template renders any field content when only
+    /// `nonempty_fields` have a value
+    ///
+    /// This is how Anki decides whether a card should exist for a note.
+    pub fn renders_with_fields(&self, nonempty_fields: &HashSet<&str>) -> bool {
+        !template_is_empty(nonempty_fields, &self.nodes)
+    }
+
+    /// Work out which fields are required, using Anki's algorithm
+    ///
+    /// If any single field is enough to render the template, the result is
+    /// [`FieldRequirements::Any`] with all such fields. Otherwise it is
+    /// [`FieldRequirements::All`] with the fields that cannot be left empty.
+    pub fn requirements(&self, field_names: &[&str]) -> FieldRequirements {
+        let mut nonempty: HashSet<&str> = HashSet::new();
+        let any: Vec<usize> = field_names
+            .iter()
+            .enumerate()
+            .filter(|&(_, name)| {
+                nonempty.clear();
+                nonempty.insert(name);
+                self.renders_with_fields(&nonempty)
+            })
+            .map(|(ord, _)| ord)
+            .collect();
+        if !any.is_empty() {
+            return FieldRequirements::Any(any);
+        }
+
+        nonempty.extend(field_names.iter().copied());
+        let all: Vec<usize> = field_names
+            .iter()
+            .enumerate()
+            .filter(|&(_, name)| {
+                nonempty.remove(name);
+                let renders = self.renders_with_fields(&nonempty);
+                nonempty.insert(name);
+                !renders
+            })
+            .map(|(ord, _)| ord)
+            .collect();
+        if !all.is_empty() && self.renders_with_fields(&nonempty) {
+            FieldRequirements::All(all)
+        } else {
+            FieldRequirements::None
+        }
+    }
+}
+
+/// A section whose closing tag has not been seen yet
+struct OpenSection {
+    key: String,
+    negated: bool,
+    offset: usize,
+    parent: Vec<TemplateNode>,
+}
+
+fn template_is_empty(nonempty_fields: &HashSet<&str>, nodes: &[TemplateNode]) -> bool {
+    nodes.iter().all(|node| match node {
+        TemplateNode::Text(_) => true,
+        TemplateNode::Replacement { key, .. } => !nonempty_fields.contains(key.as_str()),
+        TemplateNode::Conditional { key, children, .. } => {
+            !nonempty_fields.contains(key.as_str()) || template_is_empty(nonempty_fields, children)
+        }
+        TemplateNode::NegatedConditional { children, .. } => {
+            template_is_empty(nonempty_fields, children)
+        }
+    })
+}
+
+/// The field name of a `{{#key}}`, `{{^key}}` or `{{/key}}` tag
+fn section_key<'a>(
+    template: &str,
+    offset: usize,
+    tag: &'a str,
+) -> std::result::Result<&'a str, SyntaxError> {
+    let key = tag[1..].trim();
+    if key.is_empty() {
+        Err(parse_error(
+            template,
+            offset,
+            "section without a field name",
+        ))
+    } else {
+        Ok(key)
+    }
+}
+
+fn parse_error(template: &str, offset: usize, message: &str) -> SyntaxError {
+    SyntaxError {
+        position: TextPosition::at(template, offset),
+        message: message.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(line: usize, column: usize) -> TextPosition {
+        TextPosition { line, column }
+    }
+
+    fn replacement(key: &str, filters: &[&str], column: usize) -> TemplateNode {
+        TemplateNode::Replacement {
+            key: key.to_string(),
+            filters: filters.iter().map(|f| f.to_string()).collect(),
+            position: at(1, column),
+        }
+    }
+
+    #[test]
+    fn test_parse_replacements_and_filters() {
+        let parsed = ParsedTemplate::parse("Q: {{Front}} {{text:hint:Back}}{{FrontSide}}").unwrap();
+        assert_eq!(
+            parsed.nodes(),
+            &[
+                TemplateNode::Text("Q: ".to_string()),
+                replacement("Front", &[], 4),
+                TemplateNode::Text(" ".to_string()),
+                replacement("Back", &["text", "hint"], 14),
+                replacement("FrontSide", &[], 32),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_sections() {
+        let parsed = ParsedTemplate::parse("{{#A}}{{B}}{{^C}}x{{/C}}{{/A}}").unwrap();
+        assert_eq!(
+            parsed.nodes(),
+            &[TemplateNode::Conditional {
+                key: "A".to_string(),
+                children: vec![
+                    replacement("B", &[], 7),
+                    TemplateNode::NegatedConditional {
+                        key: "C".to_string(),
+                        children: vec![TemplateNode::Text("x".to_string())],
+                        position: at(1, 12),
+                    },
+                ],
+                position: at(1, 1),
+            }]
+        );
+        assert_eq!(parsed.field_references(), vec!["A", "B", "C"]);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let error = |text| match ParsedTemplate::parse(text) {
+            Err(Error::TemplateFormat(msg)) => msg,
+            other => panic!("expected a parse error, got {other:?}"),
+        };
+        assert_eq!(error("ab\n  {{Front"), "line 2, column 3: unclosed '{{'");
+        assert_eq!(
+            error("{{#A}}{{/B}}"),
+            "line 1, column 7: '{{/B}}' does not match the open section 'A'"
+        );
+        assert_eq!(
+            error("x{{#A}}"),
+            "line 1, column 2: section 'A' is never closed"
+        );
+        assert_eq!(
+            error("{{/A}}"),
+            "line 1, column 1: '{{/A}}' closes a section that was never opened"
+        );
+        assert_eq!(
+            error("{{text:}}"),
+            "line 1, column 1: empty field reference"
+        );
+    }
+
+    #[test]
+    fn test_parse_located() {
+        let err = ParsedTemplate::parse_located("é{{#A}}\n{{/B}}").unwrap_err();
+        assert_eq!(err.position, TextPosition { line: 2, column: 1 });
+        let parsed = ParsedTemplate::parse_located("é{{#A}}\n {{text:B}}{{/A}}").unwrap();
+        assert_eq!(parsed.field_tags(), vec![(at(1, 2), "A"), (at(2, 2), "B")]);
+    }
+
+    #[test]
+    fn test_cloze_fields() {
+        let parsed = ParsedTemplate::parse("{{cloze:Text}}{{type:cloze:Extra}}{{Back}}").unwrap();
+        assert_eq!(parsed.cloze_fields(), vec!["Text", "Extra"]);
+    }
+
+    #[test]
+    fn test_requirements() {
+        let fields = ["Front", "Back", "Extra"];
+        let req = |text| ParsedTemplate::parse(text).unwrap().requirements(&fields);
+
+        assert_eq!(req("{{Front}}"), FieldRequirements::Any(vec![0]));
+        assert_eq!(req("{{Front}}{{Back}}"), FieldRequirements::Any(vec![0, 1]));
+        assert_eq!(
+            req("{{#Front}}{{Back}}{{/Front}}"),
+            FieldRequirements::All(vec![0, 1])
+        );
+        assert_eq!(
+            req("{{^Front}}{{Back}}{{/Front}}"),
+            FieldRequirements::Any(vec![1])
+        );
+        assert_eq!(req("{{FrontSide}}{{Tags}}"), FieldRequirements::None);
+        assert_eq!(req(""), FieldRequirements::None);
+    }
+}
@@ -4,12 +4,29 @@
 
 use crate::core::card::Card;
 use crate::core::config::FIELD_SEPARATOR_STR;
-use crate::core::guid::guid_for;
+use crate::core::diagnostics::{Diagnostic, Location, ValidationReport};
+use crate::core::guid::{GuidStrategy, is_valid_guid};
+use crate::core::html::{rewrite_media_references, strip_html_preserving_media_filenames};
 use crate::core::model::{Model, ModelType};
+use crate::core::render::{RenderedCard, render_card};
 use crate::error::{Error, Result};
 use fancy_regex::Regex;
-use std::collections::HashSet;
+use sha1::{Digest, Sha1};
+use std::collections::{BTreeMap, BTreeSet, HashSet};
 use std::str::FromStr;
+use std::sync::LazyLock;
+
+/// Cloze fields in old `<%cloze:Field%>` templates
+static LEGACY_CLOZE_FIELDS: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new("<%cloze:(.+?)%>").unwrap());
+
+/// Card numbers of the cloze deletions in a field
+static CLOZE_NUMBERS: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?s){{c(\d+)::.+?}}").unwrap());
+
+/// Tags in a field that are not well-formed HTML
+static INVALID_HTML_TAGS: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"<(?!/?[a-z0-9]+(?: .*|/?)>)(?:.|\n)*?>").unwrap());
 
 /// A note (flashcard) to be added to a deck
 ///
@@ -18,10 +35,12 @@ use std::str::FromStr;
 pub struct Note {
     model: Model,
     fields: Vec<String>,
-    sort_field: bool,
+    sort_field: Option<String>,
     tags: Vec<String>,
     guid: String,
+    guid_strategy: GuidStrategy,
     cards: Vec<Card>,
+    id: Option<i64>,
 }
 
 impl Note {
@@ -46,41 +65,52 @@ impl Note {
     /// let note = Note::new(model, vec!["Capital of France", "Paris"]).unwrap();
     /// ```
     pub fn new(model: Model, fields: Vec<&str>) -> Result<Self> {
-        let fields: Vec<String> = fields.iter().map(|s| s.to_string()).collect();
-
-        // Validate field count
-        if model.num_fields() != fields.len() {
-            return Err(Error::ModelFieldCountMismatch(
-                model.num_fields(),
-                fields.len(),
-            ));
-        }
-
-        let cards = match model.model_type {
-            ModelType::Basic => generate_basic_cards(&model, &fields)?,
-            ModelType::Cloze => generate_cloze_cards(&model, &fields),
-        };
+        Self::new_with_strategy(model, fields, GuidStrategy::default())
+    }
 
-        let guid = guid_for(&fields);
+    /// Create a new note whose GUID is generated by `strategy`
+    ///
+    /// The note keeps the strategy, so [`Note::has_valid_guid`] checks its
+    /// GUID against it.
+    pub fn new_with_strategy(
+        model: Model,
+        fields: Vec<&str>,
+        strategy: GuidStrategy,
+    ) -> Result<Self> {
+        Self::with_options_and_strategy(model, fields, None, None, None, strategy)
+    }
 
-        Ok(Self {
+    /// Create a new note with options
+    ///
+    /// `sort_field` overrides the sort field value, like
+    /// [`Note::with_sort_field_value`].
+    #[allow(clippy::too_many_arguments)]
+    pub fn with_options(
+        model: Model,
+        fields: Vec<&str>,
+        sort_field: Option<&str>,
+        tags: Option<Vec<&str>>,
+        guid: Option<&str>,
+    ) -> Result<Self> {
+        Self::with_options_and_strategy(
             model,
             fields,
-            sort_field: false,
-            tags: Vec::new(),
+            sort_field,
+            tags,
             guid,
-            cards,
-        })
+            GuidStrategy::default(),
+        )
     }
 
-    /// Create a new note with options
-    #[allow(clippy::too_many_arguments)]
-    pub fn with_options(
+    /// Create a note with options, generating its GUID with `strategy`
+    /// unless `guid` is given
+    pub(crate) fn with_options_and_strategy(
         model: Model,
         fields: Vec<&str>,
-        sort_field: Option<bool>,
+        sort_field: Option<&str>,
         tags: Option<Vec<&str>>,
         guid: Option<&str>,
+        guid_strategy: GuidStrategy,
     ) -> Result<Self> {
         let tags: Vec<String> = tags
             .unwrap_or_default()
@@ -103,24 +133,62 @@ impl Note {
 
         let cards = match model.model_type {
             ModelType::Basic => generate_basic_cards(&model, &fields)?,
-            ModelType::Cloze => generate_cloze_cards(&model, &fields),
+            ModelType::Cloze => generate_cloze_cards(&model, &fields)?,
         };
 
-        let guid = guid.unwrap_or(&guid_for(&fields)).to_string();
+        let guid = match guid {
+            Some(guid) => guid.to_string(),
+            None => guid_strategy.generate(&fields),
+        };
+
+        Ok(Self {
+            model,
+            fields,
+            sort_field: sort_field.map(str::to_string),
+            tags,
+            guid,
+            guid_strategy,
+            cards,
+            id: None,
+        })
+    }
+
+    /// Rebuild a note from stored parts, keeping its existing cards
+    ///
+    /// The GUID strategy is detected from the GUID and fields, falling back
+    /// to the default for GUIDs no strategy produces.
+    pub(crate) fn from_parts(
+        model: Model,
+        fields: Vec<String>,
+        tags: Vec<String>,
+        guid: String,
+        cards: Vec<Card>,
+    ) -> Result<Self> {
+        if model.num_fields() != fields.len() {
+            return Err(Error::ModelFieldCountMismatch(
+                model.num_fields(),
+                fields.len(),
+            ));
+        }
 
         Ok(Self {
+            guid_strategy: GuidStrategy::detect(&guid, &fields).unwrap_or_default(),
             model,
             fields,
-            sort_field: sort_field.unwrap_or(false),
+            sort_field: None,
             tags,
             guid,
             cards,
+            id: None,
         })
     }
 
-    /// Set sort field
-    pub fn with_sort_field(mut self, sort_field: bool) -> Self {
-        self.sort_field = sort_field;
+    /// Override the sort field value
+    ///
+    /// By default the sort field is taken from the field at the model's
+    /// `sort_field_index`.
+    pub fn with_sort_field_value(mut self, sort_field: impl Into<String>) -> Self {
+        self.sort_field = Some(sort_field.into());
         self
     }
 
@@ -142,6 +210,40 @@ impl Note {
         self
     }
 
+    /// Replace the GUID with one generated from the fields by `strategy`
+    ///
+    /// Use [`GuidStrategy::Genanki`] to keep the GUIDs of notes created by
+    /// Python genanki, so re-importing a migrated deck updates the notes.
+    pub fn with_guid_strategy(mut self, strategy: GuidStrategy) -> Self {
+        self.guid = strategy.generate(&self.fields);
+        self.guid_strategy = strategy;
+        self
+    }
+
+    /// Get the strategy the GUID is generated and validated with
+    pub fn guid_strategy(&self) -> GuidStrategy {
+        self.guid_strategy
+    }
+
+    /// Check whether the GUID has the format of the note's strategy
+    pub fn has_valid_guid(&self) -> bool {
+        is_valid_guid(&self.guid, self.guid_strategy)
+    }
+
+    /// Set an explicit note ID
+    ///
+    /// Without one, an ID is allocated from the current time when the
+    /// package is written.
+    pub fn with_id(mut self, id: i64) -> Self {
+        self.id = Some(id);
+        self
+    }
+
+    /// Get the explicit note ID, if any
+    pub fn id(&self) -> Option<i64> {
+        self.id
+    }
+
     /// Get the model
     pub fn model(&self) -> &Model {
         &self.model
@@ -157,6 +259,11 @@ impl Note {
         &self.cards
     }
 
+    /// Get the cards (mutable)
+    pub fn cards_mut(&mut self) -> &mut [Card] {
+        &mut self.cards
+    }
+
     /// Get the GUID
     pub fn guid(&self) -> &str {
         &self.guid
@@ -172,6 +279,34 @@ impl Note {
         &self.tags
     }
 
+    /// Get the sort field value (`sfld`), with HTML stripped
+    pub fn sort_field(&self) -> String {
+        let raw = self.sort_field.as_deref().unwrap_or_else(|| {
+            usize::try_from(self.model.sort_field_index)
+                .ok()
+                .and_then(|idx| self.fields.get(idx))
+                .map_or("", String::as_str)
+        });
+        strip_html_preserving_media_filenames(raw)
+    }
+
+    /// Get the duplicate-detection checksum (`csum`)
+    ///
+    /// This is the first 8 hex digits of the SHA-1 of the stripped first
+    /// field, as computed by Anki.
+    pub fn checksum(&self) -> u32 {
+        let first = self.fields.first().map_or("", String::as_str);
+        let digest = Sha1::digest(strip_html_preserving_media_filenames(first).as_bytes());
+        u32::from_be_bytes([digest[0], digest[1], digest[2], digest[3]])
+    }
+
+    /// Render the question and answer HTML of one of this note's cards
+    ///
+    /// See [`render_card`] for how `card_ord` and `deck_name` are used.
+    pub fn render_card(&self, card_ord: i64, deck_name: &str) -> Result<RenderedCard> {
+        render_card(self, card_ord, deck_name)
+    }
+
     /// Format fields for database storage
     pub fn format_fields(&self) -> String {
         self.fields.join(FIELD_SEPARATOR_STR)
@@ -182,7 +317,111 @@ impl Note {
         format!(" {} ", self.tags.join(" "))
     }
 
-    /// Check for invalid HTML tags in fields
+    /// Point media references in the fields to new filenames
+    ///
+    /// `renames` maps old names to new ones, as returned by
+    /// [`MediaFiles::normalize`](crate::export::MediaFiles::normalize).
+    pub fn rename_media_references(&mut self, renames: &BTreeMap<String, String>) {
+        if renames.is_empty() {
+            return;
+        }
+        for field in &mut self.fields {
+            *field = rewrite_media_references(field, |name| renames.get(name).cloned());
+        }
+    }
+
+    /// Report problems with this note into `report`
+    ///
+    /// `deck` and `index` locate the note; GUID and model conflicts between
+    /// notes are checked by the deck.
+    pub(crate) fn validate(&self, deck: &str, index: usize, report: &mut ValidationReport) {
+        let location = |field: Option<&str>| Location::Note {
+            deck: deck.to_string(),
+            index,
+            guid: self.guid.clone(),
+            field: field.map(str::to_string),
+        };
+
+        match self.model.model_type {
+            ModelType::Basic if self.cards.is_empty() => report.push(Diagnostic::error(
+                location(None),
+                "note produces no cards, because the fields its templates require are empty",
+            )),
+            ModelType::Cloze
+                if cloze_card_ords(&self.model, &self.fields).is_ok_and(|ords| ords.is_empty()) =>
+            {
+                report.push(Diagnostic::warning(
+                    location(None),
+                    "cloze note has no cloze deletions, so its card is empty",
+                ))
+            }
+            _ => {}
+        }
+
+        for tag in &self.tags {
+            if tag.is_empty() || tag.contains(char::is_whitespace) {
+                report.push(Diagnostic::error(
+                    location(None),
+                    format!("tag '{tag}' is empty or contains whitespace"),
+                ));
+            }
+        }
+
+        for card in &self.cards {
+            let Some(state) = card.fsrs() else { continue };
+            let retention_ok = state
+                .desired_retention
+                .is_none_or(|retention| retention > 0.0 && retention < 1.0);
+            if !(state.stability > 0.0 && (1.0..=10.0).contains(&state.difficulty) && retention_ok)
+            {
+                report.push(Diagnostic::error(
+                    location(None),
+                    format!(
+                        "card {} has an FSRS memory state out of range; stability must be \
+                         positive, difficulty between 1 and 10 and desired retention between \
+                         0 and 1",
+                        card.ord()
+                    ),
+                ));
+            }
+        }
+
+        report.extend(self.html_diagnostics(deck, index));
+    }
+
+    /// Warnings for fields containing invalid HTML tags
+    pub(crate) fn html_diagnostics(&self, deck: &str, index: usize) -> Vec<Diagnostic> {
+        self.model
+            .fields
+            .iter()
+            .zip(&self.fields)
+            .filter_map(|(field, value)| {
+                let invalid_tags = find_invalid_html_tags(value);
+                (!invalid_tags.is_empty()).then(|| {
+                    Diagnostic::warning(
+                        Location::Note {
+                            deck: deck.to_string(),
+                            index,
+                            guid: self.guid.clone(),
+                            field: Some(field.name.clone()),
+                        },
+                        format!("invalid HTML tags: {}", invalid_tags.join(", ")),
+                    )
+                })
+            })
+            .collect()
+    }
+
+    /// Print a warning to stderr for each field with invalid HTML tags
+    ///
+    /// [`Deck::validate`] and [`Package::validate`] report these as
+    /// diagnostics, and writing a package sends them to its
+    /// [`DiagnosticSink`].
+    ///
+    /// [`Deck::validate`]: crate::core::Deck::validate
+    /// [`Package::validate`]: crate::Package::validate
+    /// [`DiagnosticSink`]: crate::core::diagnostics::DiagnosticSink
+    #[deprecated(note = "use `Deck::validate` or `Package::validate` to get invalid HTML warnings")]
     pub fn check_invalid_html(&self) {
         for field in &self.fields {
             let invalid_tags = find_invalid_html_tags(field);
@@ -197,55 +436,65 @@ impl Note {
 }
 
 /// Generate cards for basic model type
+///
+/// Like Anki, a card is created for each template that renders some field
+/// content with the note's non-empty fields.
 fn generate_basic_cards(model: &Model, fields: &[String]) -> Result<Vec<Card>> {
-    let mut cards = Vec::new();
-
-    for (card_ord, any_or_all, required_field_ords) in model.req()?.iter() {
-        let should_create = match any_or_all.as_str() {
-            "any" => required_field_ords
-                .iter()
-                .any(|&ord| !fields[ord].is_empty()),
-            "all" => required_field_ords
-                .iter()
-                .all(|&ord| !fields[ord].is_empty()),
-            _ => {
-                return Err(Error::Validation(format!(
-                    "Invalid req type: {}",
-                    any_or_all
-                )));
-            }
-        };
+    let nonempty_fields: HashSet<&str> = model
+        .fields
+        .iter()
+        .zip(fields)
+        .filter(|(_, value)| !field_is_empty(value))
+        .map(|(field, _)| field.name.as_str())
+        .collect();
+
+    Ok(model
+        .parsed_templates()?
+        .iter()
+        .enumerate()
+        .filter(|(_, template)| template.renders_with_fields(&nonempty_fields))
+        .map(|(card_ord, _)| Card::new(card_ord as i64, false))
+        .collect())
+}
 
-        if should_create {
-            cards.push(Card::new(*card_ord as i64, false));
-        }
+/// Generate cards for cloze model type
+fn generate_cloze_cards(model: &Model, fields: &[String]) -> Result<Vec<Card>> {
+    let mut card_ords = cloze_card_ords(model, fields)?;
+    if card_ords.is_empty() {
+        card_ords.insert(0);
     }
 
-    Ok(cards)
+    Ok(card_ords.iter().map(|&ord| Card::new(ord, false)).collect())
 }
 
-/// Generate cards for cloze model type
-fn generate_cloze_cards(model: &Model, fields: &[String]) -> Vec<Card> {
-    let mut card_ords: HashSet<i64> = HashSet::new();
-    let mut cloze_replacements: HashSet<String> = HashSet::new();
-
-    // Find cloze field names in templates
-    cloze_replacements.extend(re_findall(
-        r"{{[^}]*?cloze:(?:[^}]?:)*(.+?)}}",
-        &model.templates[0].qfmt,
-    ));
-    cloze_replacements.extend(re_findall("<%cloze:(.+?)%>", &model.templates[0].qfmt));
-
-    let empty_string = String::new();
-    for field_name in cloze_replacements {
-        let field_value = model
+/// Card ordinals of the cloze deletions in the fields rendered with `cloze`
+fn cloze_card_ords(model: &Model, fields: &[String]) -> Result<BTreeSet<i64>> {
+    let mut card_ords: BTreeSet<i64> = BTreeSet::new();
+
+    let parsed = model.parsed_templates()?;
+    let mut cloze_fields = parsed
+        .first()
+        .map(|template| template.cloze_fields())
+        .unwrap_or_default();
+    // Old Anki versions also accepted `<%cloze:Field%>`
+    let legacy_fields = model
+        .templates
+        .first()
+        .map(|template| re_findall(&LEGACY_CLOZE_FIELDS, &template.qfmt))
+        .unwrap_or_default();
+    cloze_fields.extend(legacy_fields.iter().map(String::as_str));
+
+    for field_name in cloze_fields {
+        let Some(field_value) = model
             .fields
             .iter()
             .position(|f| f.name == field_name)
             .map(|idx| &fields[idx])
-            .unwrap_or(&empty_string);
+        else {
+            continue;
+        };
 
-        let updates_str = re_findall(r"(?s){{c(\d+)::.+?}}", field_value);
+        let updates_str = re_findall(&CLOZE_NUMBERS, field_value);
         let updates = updates_str
             .iter()
             .filter_map(|m| i64::from_str(m).ok())
@@ -255,16 +504,20 @@ fn generate_cloze_cards(model: &Model, fields: &[String]) -> Vec<Card> {
         card_ords.extend(updates);
     }
 
-    if card_ords.is_empty() {
-        card_ords.insert(0);
-    }
+    Ok(card_ords)
+}
 
-    card_ords.iter().map(|&ord| Card::new(ord, false)).collect()
+/// Whether a field counts as empty for card generation
+///
+/// Anki ignores HTML and whitespace, so `<br>` or `&nbsp;` alone is empty.
+pub(crate) fn field_is_empty(value: &str) -> bool {
+    strip_html_preserving_media_filenames(value)
+        .chars()
+        .all(|c| c.is_whitespace() || c == '\u{200b}')
 }
 
-/// Find all regex matches in a string
-fn re_findall(pattern: &str, text: &str) -> Vec<String> {
-    let regex = Regex::new(pattern).expect("Invalid regex pattern");
+/// Find the capture groups of all regex matches in a string
+fn re_findall(regex: &Regex, text: &str) -> Vec<String> {
     regex
         .captures_iter(text)
         .filter_map(|m| m.ok())
@@ -289,8 +542,7 @@ fn validate_tags(tags: &[String]) -> Result<()> {
 
 /// Find invalid HTML tags in a field
 fn find_invalid_html_tags(field: &str) -> Vec<String> {
-    let regex = Regex::new(r"<(?!/?[a-z0-9]+(?: .*|/?)>)(?:.|\n)*?>").unwrap();
-    regex
+    INVALID_HTML_TAGS
         .find_iter(field)
         .filter_map(|m| m.ok())
         .map(|m| m.as_str().to_string())
@@ -372,4 +624,87 @@ mod tests {
         let note = Note::new(model, vec!["A", "B"]).unwrap();
         assert_eq!(note.format_fields(), "A\x1fB");
     }
+
+    #[test]
+    fn test_sort_field_and_checksum() {
+        let model = Model::new(
+            123,
+            "Basic",
+            vec![Field::new("F1"), Field::new("F2")],
+            vec![Template::new("Card 1")],
+        )
+        .sort_field_index(1);
+
+        let note = Note::new(model, vec!["<b>hello</b>", "<i>42</i>"]).unwrap();
+        assert_eq!(note.sort_field(), "42");
+        // sha1("hello") = aaf4c61d...
+        assert_eq!(note.checksum(), 0xaaf4c61d);
+
+        let note = note.with_sort_field_value("<u>custom</u>");
+        assert_eq!(note.sort_field(), "custom");
+    }
+
+    #[test]
+    fn test_cards_follow_template_sections() {
+        let model = Model::new(
+            123,
+            "Optional Reverse",
+            vec![
+                Field::new("Front"),
+                Field::new("Back"),
+                Field::new("Add Reverse"),
+            ],
+            vec![
+                Template::new("Card 1").qfmt("{{Front}}"),
+                Template::new("Card 2").qfmt("{{#Add Reverse}}{{Back}}{{/Add Reverse}}"),
+            ],
+        );
+
+        let note = Note::new(model.clone(), vec!["Q", "A", "<br>&nbsp;"]).unwrap();
+        assert_eq!(note.cards(), &[Card::new(0, false)]);
+
+        let note = Note::new(model, vec!["Q", "A", "y"]).unwrap();
+        assert_eq!(note.cards(), &[Card::new(0, false), Card::new(1, false)]);
+    }
+
+    #[test]
+    fn test_legacy_cloze_syntax() {
+        let model = Model::new(
+            123,
+            "Old Cloze",
+            vec![Field::new("Text")],
+            vec![Template::new("Cloze").qfmt("<%cloze:Text%>")],
+        )
+        .model_type(ModelType::Cloze);
+
+        let note = Note::new(model, vec!["{{c1::a}} {{c3::b}}"]).unwrap();
+        assert_eq!(note.cards(), &[Card::new(0, false), Card::new(2, false)]);
+    }
+
+    #[test]
+    fn test_guid_strategy() {
+        let model = Model::new(
+            123,
+            "Basic",
+            vec![Field::new("Front"), Field::new("Back")],
+            vec![Template::new("Card 1")],
+        );
+
+        let note = Note::new(model, vec!["Question", "Answer"]).unwrap();
+        assert!(GuidStrategy::Blake3.is_valid(note.guid()));
+
+        let note = note.with_guid_strategy(GuidStrategy::Genanki);
+        assert_eq!(note.guid(), "l5<zIi`lY5");
+        assert!(note.has_valid_guid());
+
+        let note = Note::new_with_strategy(
+            note.model().clone(),
+            vec!["Q", "A"],
+            GuidStrategy::AnkiRandom,
+        )
+        .unwrap();
+        assert_eq!(note.guid_strategy(), GuidStrategy::AnkiRandom);
+        assert!(note.has_valid_guid());
+        assert!(!note.with_guid("not base91 ✗").has_valid_guid());
+    }
 }
@@ -0,0 +1,409 @@
+//! Card preview rendering
+//!
+//! Renders the question and answer HTML of a note's card the way Anki's
+//! reviewer does, so generated content can be checked without importing it.
+
+use crate::core::ModelType;
+use crate::core::html::strip_html;
+use crate::core::model::parse_template;
+use crate::core::model::parser::TemplateNode;
+use crate::core::note::{Note, field_is_empty};
+use crate::error::{Error, Result};
+use fancy_regex::{Captures, Regex};
+use std::collections::HashMap;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::sync::LazyLock;
+
+static CLOZE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?s)\{\{c(\d+)::(.*?)(?:::(.*?))?\}\}").unwrap());
+
+static FURIGANA: LazyLock<Regex> = LazyLock::new(|| Regex::new(r" ?([^ >]+?)\[(.+?)\]").unwrap());
+
+/// Question and answer HTML of a single card
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RenderedCard {
+    /// Ordinal of the rendered card
+    pub card_ord: i64,
+    /// Rendered question side, without styling
+    pub question: String,
+    /// Rendered answer side, without styling
+    pub answer: String,
+    /// The model CSS
+    pub css: String,
+}
+
+impl RenderedCard {
+    /// The question side as a standalone HTML page styled with the model CSS
+    pub fn question_html(&self) -> String {
+        self.page(&self.question)
+    }
+
+    /// The answer side as a standalone HTML page styled with the model CSS
+    pub fn answer_html(&self) -> String {
+        self.page(&self.answer)
+    }
+
+    fn page(&self, body: &str) -> String {
+        format!(
+            "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<style>\n{}\n</style>\n</head>\n\
+             <body class=\"card card{}\">\n<div id=\"qa\">{}</div>\n</body>\n</html>\n",
+            self.css,
+            self.card_ord + 1,
+            body
+        )
+    }
+}
+
+/// Render a card of a note
+///
+/// For standard models `card_ord` selects the template; for cloze models it
+/// selects the cloze number (`0` for `c1`). `deck_name` fills `{{Deck}}` and
+/// `{{Subdeck}}`.
+pub fn render_card(note: &Note, card_ord: i64, deck_name: &str) -> Result<RenderedCard> {
+    let model = note.model();
+    let (template, cloze_ord) = match model.model_type {
+        ModelType::Basic => {
+            let template = usize::try_from(card_ord)
+                .ok()
+                .and_then(|ord| model.templates.get(ord));
+            (template, None)
+        }
+        ModelType::Cloze => (model.templates.first(), Some(card_ord + 1)),
+    };
+    let template = template.ok_or_else(|| {
+        Error::Validation(format!(
+            "Model '{}' has no card with ordinal {card_ord}",
+            model.name
+        ))
+    })?;
+
+    let mut fields: HashMap<&str, String> = model
+        .fields
+        .iter()
+        .map(|field| field.name.as_str())
+        .zip(note.fields().iter().cloned())
+        .collect();
+    let subdeck = deck_name.rsplit("::").next().unwrap_or(deck_name);
+    fields.insert("Tags", note.tags().join(" "));
+    fields.insert("Type", model.name.clone());
+    fields.insert("Deck", deck_name.to_string());
+    fields.insert("Subdeck", subdeck.to_string());
+    fields.insert("Card", template.name.clone());
+    fields.insert("CardFlag", String::new());
+    fields.insert("FrontSide", String::new());
+
+    let mut context = RenderContext {
+        template_name: &template.name,
+        fields,
+        cloze_ord,
+        question_side: true,
+    };
+
+    let question = context.render(parse_template(&template.name, &template.qfmt)?.nodes())?;
+    context.fields.insert("FrontSide", question.clone());
+    context.question_side = false;
+    let answer = context.render(parse_template(&template.name, &template.afmt)?.nodes())?;
+
+    Ok(RenderedCard {
+        card_ord,
+        question,
+        answer,
+        css: model.css.clone(),
+    })
+}
+
+struct RenderContext<'a> {
+    template_name: &'a str,
+    fields: HashMap<&'a str, String>,
+    cloze_ord: Option<i64>,
+    question_side: bool,
+}
+
+impl RenderContext<'_> {
+    fn render(&self, nodes: &[TemplateNode]) -> Result<String> {
+        let mut out = String::new();
+        self.render_into(nodes, &mut out)?;
+        Ok(out)
+    }
+
+    fn render_into(&self, nodes: &[TemplateNode], out: &mut String) -> Result<()> {
+        for node in nodes {
+            match node {
+                TemplateNode::Text(text) => out.push_str(text),
+                TemplateNode::Replacement { key, filters, .. } => {
+                    out.push_str(&self.replacement(key, filters)?);
+                }
+                TemplateNode::Conditional { key, children, .. } => {
+                    if !field_is_empty(self.field(key)?) {
+                        self.render_into(children, out)?;
+                    }
+                }
+                TemplateNode::NegatedConditional { key, children, .. } => {
+                    if field_is_empty(self.field(key)?) {
+                        self.render_into(children, out)?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn field(&self, key: &str) -> Result<&str> {
+        self.fields.get(key).map(String::as_str).ok_or_else(|| {
+            Error::TemplateFormat(format!(
+                "template '{}': no field called '{key}'",
+                self.template_name
+            ))
+        })
+    }
+
+    fn replacement(&self, key: &str, filters: &[String]) -> Result<String> {
+        let value = self.field(key)?;
+
+        // `type` replaces the whole field with an answer box, even when
+        // combined with `cloze`
+        if filters.first().is_some_and(|f| f == "type") {
+            return Ok(if self.question_side {
+                "<center><input id=\"typeans\" type=\"text\" onkeypress=\"_typeAnsPress();\"></center>"
+                    .to_string()
+            } else {
+                let expected = if filters.iter().any(|f| f == "cloze") {
+                    self.active_clozes(value).join(", ")
+                } else {
+                    value.to_string()
+                };
+                format!(
+                    "<center><code id=\"typeans\">{}</code></center>",
+                    strip_html(&expected)
+                )
+            });
+        }
+
+        // Filters apply right to left, starting with the one next to the field
+        Ok(filters
+            .iter()
+            .rev()
+            .fold(value.to_string(), |text, filter| {
+                self.apply_filter(filter, key, text)
+            }))
+    }
+
+    fn apply_filter(&self, filter: &str, key: &str, text: String) -> String {
+        match filter {
+            "text" => strip_html(&text),
+            "hint" => hint(key, &text),
+            "cloze" => self.reveal_clozes(&text),
+            "cloze-only" => self.active_clozes(&text).join(", "),
+            "furigana" => FURIGANA
+                .replace_all(&text, "<ruby><rb>$1</rb><rt>$2</rt></ruby>")
+                .into_owned(),
+            "kanji" => FURIGANA.replace_all(&text, "$1").into_owned(),
+            "kana" => FURIGANA.replace_all(&text, "$2").into_owned(),
+            _ => match filter.strip_prefix("tts ") {
+                Some(args) => {
+                    let lang = args.split_whitespace().next().unwrap_or_default();
+                    format!("[anki:tts lang={lang}]{text}[/anki:tts]")
+                }
+                // Unknown filters are left to add-ons in Anki
+                None => text,
+            },
+        }
+    }
+
+    /// Render cloze deletions, hiding the active one on the question side
+    ///
+    /// Like Anki, a field without the active cloze renders as empty.
+    fn reveal_clozes(&self, text: &str) -> String {
+        let Some(active) = self.cloze_ord else {
+            return text.to_string();
+        };
+        if self.active_clozes(text).is_empty() {
+            return String::new();
+        }
+
+        CLOZE
+            .replace_all(text, |caps: &Captures| {
+                let ord: i64 = caps[1].parse().unwrap_or_default();
+                let answer = &caps[2];
+                if ord != active {
+                    format!("<span class=\"cloze-inactive\" data-ordinal=\"{ord}\">{answer}</span>")
+                } else if self.question_side {
+                    let hint = caps.get(3).map_or("...", |m| m.as_str());
+                    format!(
+                        "<span class=\"cloze\" data-cloze=\"{}\" data-ordinal=\"{ord}\">[{hint}]</span>",
+                        escape_attribute(answer)
+                    )
+                } else {
+                    format!("<span class=\"cloze\" data-ordinal=\"{ord}\">{answer}</span>")
+                }
+            })
+            .into_owned()
+    }
+
+    /// Texts of the active cloze deletions in a field
+    fn active_clozes(&self, text: &str) -> Vec<String> {
+        let Some(active) = self.cloze_ord else {
+            return Vec::new();
+        };
+        CLOZE
+            .captures_iter(text)
+            .filter_map(|caps| caps.ok())
+            .filter(|caps| caps[1].parse::<i64>().ok() == Some(active))
+            .map(|caps| caps[2].to_string())
+            .collect()
+    }
+}
+
+/// Anki's collapsible hint link
+fn hint(field_name: &str, text: &str) -> String {
+    if text.trim().is_empty() {
+        return String::new();
+    }
+    let mut hasher = DefaultHasher::new();
+    text.hash(&mut hasher);
+    let id = hasher.finish();
+    format!(
+        "<a class=hint href=\"#\" onclick=\"this.style.display='none';\
+         document.getElementById('hint{id}').style.display='block';return false;\" \
+         draggable=false>{field_name}</a><div id=\"hint{id}\" class=hint style=\"display: none\">{text}</div>"
+    )
+}
+
+fn escape_attribute(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('"', "&quot;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::{Field, Model, Template};
+
+    fn basic() -> Model {
+        Model::new(
+            1,
+            "Basic",
+            vec![Field::new("Front"), Field::new("Back"), Field::new("Extra")],
+            vec![
+                Template::new("Card 1")
+                    .qfmt("{{Front}}{{#Extra}}<i>{{hint:Extra}}</i>{{/Extra}}")
+                    .afmt("{{FrontSide}}<hr id=answer>{{Back}} {{Tags}} {{Deck}} {{Subdeck}}"),
+            ],
+        )
+        .css(".card { color: black; }")
+    }
+
+    fn cloze() -> Model {
+        Model::new(
+            2,
+            "Cloze",
+            vec![Field::new("Text")],
+            vec![
+                Template::new("Cloze")
+                    .qfmt("{{cloze:Text}}")
+                    .afmt("{{cloze:Text}}"),
+            ],
+        )
+        .model_type(ModelType::Cloze)
+    }
+
+    #[test]
+    fn test_render_basic_card() {
+        let note = Note::new(basic(), vec!["Q", "A", ""])
+            .unwrap()
+            .with_tag("geo");
+        let card = render_card(&note, 0, "Parent::Child").unwrap();
+        assert_eq!(card.question, "Q");
+        assert_eq!(card.answer, "Q<hr id=answer>A geo Parent::Child Child");
+        assert!(card.question_html().contains(".card { color: black; }"));
+        assert!(card.question_html().contains("<body class=\"card card1\">"));
+    }
+
+    #[test]
+    fn test_render_conditional_hint() {
+        let note = Note::new(basic(), vec!["Q", "A", "a hint"]).unwrap();
+        let card = render_card(&note, 0, "Default").unwrap();
+        assert!(card.question.starts_with("Q<i><a class=hint"));
+        assert!(card.question.contains(">Extra</a>"));
+        assert!(card.question.contains("a hint</div></i>"));
+    }
+
+    #[test]
+    fn test_render_cloze() {
+        let note = Note::new(cloze(), vec!["{{c1::Paris::city}} is in {{c2::France}}"]).unwrap();
+
+        let card = render_card(&note, 0, "Default").unwrap();
+        assert_eq!(
+            card.question,
+            "<span class=\"cloze\" data-cloze=\"Paris\" data-ordinal=\"1\">[city]</span> is in \
+             <span class=\"cloze-inactive\" data-ordinal=\"2\">France</span>"
+        );
+        assert_eq!(
+            card.answer,
+            "<span class=\"cloze\" data-ordinal=\"1\">Paris</span> is in \
+             <span class=\"cloze-inactive\" data-ordinal=\"2\">France</span>"
+        );
+
+        let card = render_card(&note, 2, "Default").unwrap();
+        assert_eq!(card.question, "");
+    }
+
+    #[test]
+    fn test_render_type_answer() {
+        let model = Model::new(
+            3,
+            "Typing",
+            vec![Field::new("Front"), Field::new("Back")],
+            vec![
+                Template::new("Card 1")
+                    .qfmt("{{Front}} {{type:Back}}")
+                    .afmt("{{type:Back}}"),
+            ],
+        );
+        let note = Note::new(model, vec!["Q", "<b>A</b>"]).unwrap();
+        let card = render_card(&note, 0, "Default").unwrap();
+        assert!(card.question.contains("<input id=\"typeans\""));
+        assert_eq!(
+            card.answer,
+            "<center><code id=\"typeans\">A</code></center>"
+        );
+    }
+
+    #[test]
+    fn test_render_filters() {
+        let model = Model::new(
+            4,
+            "Filters",
+            vec![Field::new("Word")],
+            vec![
+                Template::new("Card 1")
+                    .qfmt("{{furigana:Word}}|{{kana:Word}}|{{text:Word}}")
+                    .afmt(""),
+            ],
+        );
+        let note = Note::new(model, vec!["<b>日本[にほん]</b>"]).unwrap();
+        let card = render_card(&note, 0, "Default").unwrap();
+        assert_eq!(
+            card.question,
+            "<b><ruby><rb>日本</rb><rt>にほん</rt></ruby></b>|<b>にほん</b>|日本[にほん]"
+        );
+    }
+
+    #[test]
+    fn test_render_errors() {
+        let note = Note::new(basic(), vec!["Q", "A", ""]).unwrap();
+        assert!(matches!(
+            render_card(&note, 1, "Default"),
+            Err(Error::Validation(_))
+        ));
+
+        let model = basic().with_template(Template::new("Card 2").qfmt("{{Missing}}{{Front}}"));
+        let note = Note::new(model, vec!["Q", "A", ""]).unwrap();
+        assert!(matches!(
+            render_card(&note, 1, "Default"),
+            Err(Error::TemplateFormat(msg)) if msg == "template 'Card 2': no field called 'Missing'"
+        ));
+    }
+}
@@ -0,0 +1,206 @@
+//! Review history of cards
+//!
+//! Every answer in Anki adds a row to the review log (`revlog`). Statistics
+//! and the FSRS optimizer read it, so migrated cards should bring their
+//! history along with their scheduling state.
+
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, SystemTime};
+
+/// Button pressed when answering a card
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Rating {
+    /// No button; the card was rescheduled by hand
+    Manual,
+    Again,
+    Hard,
+    Good,
+    Easy,
+}
+
+impl Rating {
+    /// Value of the `ease` column
+    pub fn to_i64(self) -> i64 {
+        match self {
+            Rating::Manual => 0,
+            Rating::Again => 1,
+            Rating::Hard => 2,
+            Rating::Good => 3,
+            Rating::Easy => 4,
+        }
+    }
+
+    /// Rating for a value of the `ease` column
+    pub fn from_i64(value: i64) -> Self {
+        match value {
+            1 => Rating::Again,
+            2 => Rating::Hard,
+            3 => Rating::Good,
+            4 => Rating::Easy,
+            _ => Rating::Manual,
+        }
+    }
+}
+
+/// What kind of review an entry records
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum ReviewKind {
+    /// Answer during the learning steps
+    Learning,
+    /// Answer of a review card
+    #[default]
+    Review,
+    /// Answer during the relearning steps
+    Relearning,
+    /// Answer in a filtered deck
+    Filtered,
+    /// Due date set by hand
+    Manual,
+    /// Rescheduled, for example by FSRS
+    Rescheduled,
+}
+
+impl ReviewKind {
+    /// Value of the `type` column
+    pub fn to_i64(self) -> i64 {
+        match self {
+            ReviewKind::Learning => 0,
+            ReviewKind::Review => 1,
+            ReviewKind::Relearning => 2,
+            ReviewKind::Filtered => 3,
+            ReviewKind::Manual => 4,
+            ReviewKind::Rescheduled => 5,
+        }
+    }
+
+    /// Kind for a value of the `type` column
+    pub fn from_i64(value: i64) -> Self {
+        match value {
+            0 => ReviewKind::Learning,
+            2 => ReviewKind::Relearning,
+            3 => ReviewKind::Filtered,
+            4 => ReviewKind::Manual,
+            5 => ReviewKind::Rescheduled,
+            _ => ReviewKind::Review,
+        }
+    }
+}
+
+/// Interval of a card before or after a review
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum RevlogInterval {
+    /// A learning step, in seconds
+    Seconds(u32),
+    /// A review interval, in days
+    Days(u32),
+}
+
+impl RevlogInterval {
+    /// Value of the `ivl` and `lastIvl` columns: days, or negative seconds
+    pub fn to_i64(self) -> i64 {
+        match self {
+            RevlogInterval::Seconds(secs) => -i64::from(secs),
+            RevlogInterval::Days(days) => i64::from(days),
+        }
+    }
+
+    /// Interval for a value of the `ivl` or `lastIvl` column
+    pub fn from_i64(value: i64) -> Self {
+        if value < 0 {
+            RevlogInterval::Seconds(value.unsigned_abs().min(u32::MAX as u64) as u32)
+        } else {
+            RevlogInterval::Days(value.min(u32::MAX as i64) as u32)
+        }
+    }
+}
+
+impl Default for RevlogInterval {
+    fn default() -> Self {
+        RevlogInterval::Days(0)
+    }
+}
+
+/// One past review of a card
+///
+/// # Example
+///
+/// ```
+/// use genanki_rs_rev::core::{Rating, RevlogEntry, RevlogInterval, ReviewKind};
+/// use std::time::{Duration, UNIX_EPOCH};
+///
+/// let review = RevlogEntry::new(
+///     UNIX_EPOCH + Duration::from_secs(1_700_000_000),
+///     Rating::Good,
+///     ReviewKind::Review,
+/// )
+/// .with_intervals(RevlogInterval::Days(9), RevlogInterval::Days(4))
+/// .with_ease_factor(2500)
+/// .with_duration(Duration::from_secs(6));
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct RevlogEntry {
+    /// When the card was answered
+    pub time: SystemTime,
+    pub rating: Rating,
+    pub kind: ReviewKind,
+    /// Interval after the review
+    pub interval: RevlogInterval,
+    /// Interval before the review
+    pub last_interval: RevlogInterval,
+    /// Ease factor after the review in permille, such as 2500 for 250%
+    pub ease_factor: u32,
+    /// Time spent answering
+    pub duration: Duration,
+}
+
+impl RevlogEntry {
+    /// Create an entry for an answer at `time`
+    pub fn new(time: SystemTime, rating: Rating, kind: ReviewKind) -> Self {
+        Self {
+            time,
+            rating,
+            kind,
+            interval: RevlogInterval::default(),
+            last_interval: RevlogInterval::default(),
+            ease_factor: 0,
+            duration: Duration::ZERO,
+        }
+    }
+
+    /// Set the intervals after and before the review
+    pub fn with_intervals(mut self, interval: RevlogInterval, last: RevlogInterval) -> Self {
+        self.interval = interval;
+        self.last_interval = last;
+        self
+    }
+
+    /// Set the ease factor after the review, in permille
+    pub fn with_ease_factor(mut self, ease_factor: u32) -> Self {
+        self.ease_factor = ease_factor;
+        self
+    }
+
+    /// Set the time spent answering
+    pub fn with_duration(mut self, duration: Duration) -> Self {
+        self.duration = duration;
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_column_values() {
+        assert_eq!(RevlogInterval::Seconds(600).to_i64(), -600);
+        assert_eq!(RevlogInterval::from_i64(-600), RevlogInterval::Seconds(600));
+        assert_eq!(RevlogInterval::from_i64(3), RevlogInterval::Days(3));
+        for rating in [Rating::Manual, Rating::Again, Rating::Easy] {
+            assert_eq!(Rating::from_i64(rating.to_i64()), rating);
+        }
+        for kind in [ReviewKind::Learning, ReviewKind::Rescheduled] {
+            assert_eq!(ReviewKind::from_i64(kind.to_i64()), kind);
+        }
+    }
+}
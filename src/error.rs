@@ -21,10 +21,8 @@ pub enum Error {
     #[error("JSON error: {0}")]
     Json(#[from] serde_json::Error),
 
-    /// Template formatting errors
-    #[error(
-        "Could not compute required fields for this template; please check the formatting: {0:?}"
-    )]
+    /// Template syntax errors, with the template name, line and column
+    #[error("Invalid template format: {0}")]
     TemplateFormat(String),
 
     /// Model field count mismatch
@@ -39,10 +37,6 @@ pub enum Error {
     #[error("I/O error: {0}")]
     Io(#[from] IoError),
 
-    /// Template rendering errors
-    #[error("Template rendering error: {0}")]
-    TemplateRendering(#[from] ramhorns::Error),
-
     /// System time errors
     #[error("System time error: {0}")]
     SystemTime(#[from] std::time::SystemTimeError),
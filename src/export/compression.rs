@@ -0,0 +1,219 @@
+//! Compression settings for package entries
+//!
+//! The collection database and the media files can be compressed
+//! differently, so builds can trade speed for package size. Which methods
+//! are available depends on the [`PackageFormat`]: the legacy layouts use
+//! zip's own compression, while [`PackageFormat::Anki21b`] stores every entry
+//! as a zstd stream.
+
+use crate::export::format::PackageFormat;
+use crate::export::media::is_compressed_format;
+use crate::{Error, Result};
+use zip::CompressionMethod;
+use zip::write::SimpleFileOptions;
+
+/// How one kind of entry is compressed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    /// No compression; fastest to write
+    Stored,
+    /// Deflate at a level from 0 to 9, or the default level (6) when `None`
+    ///
+    /// Only for [`PackageFormat::Legacy`] and [`PackageFormat::Anki21`].
+    Deflate(Option<i64>),
+    /// zstd at a level from 1 to 22, or the default level (3) when 0
+    ///
+    /// Only for [`PackageFormat::Anki21b`], which Anki reads since 2.1.50.
+    Zstd(i32),
+}
+
+impl Compression {
+    /// Check that `format` can hold entries compressed this way
+    fn check(self, format: PackageFormat, entry: &str) -> Result<()> {
+        match self {
+            Compression::Stored | Compression::Deflate(_) if format.is_zstd_compressed() => {
+                Err(Error::Config(format!(
+                    "{format:?} packages need zstd compression for {entry}"
+                )))
+            }
+            Compression::Zstd(_) if !format.is_zstd_compressed() => Err(Error::Config(format!(
+                "{format:?} packages cannot use zstd compression for {entry}"
+            ))),
+            Compression::Deflate(Some(level)) if !(0..=9).contains(&level) => Err(Error::Config(
+                format!("Deflate level {level} for {entry} is not between 0 and 9"),
+            )),
+            Compression::Zstd(level) if !zstd::compression_level_range().contains(&level) => Err(
+                Error::Config(format!("zstd level {level} for {entry} is not supported")),
+            ),
+            _ => Ok(()),
+        }
+    }
+
+    /// Apply to zip entry options; zstd is applied to the entry data instead
+    fn zip_options(self, options: SimpleFileOptions) -> SimpleFileOptions {
+        match self {
+            Compression::Stored | Compression::Zstd(_) => {
+                options.compression_method(CompressionMethod::Stored)
+            }
+            Compression::Deflate(level) => options
+                .compression_method(CompressionMethod::Deflated)
+                .compression_level(level),
+        }
+    }
+
+    /// zstd level for the entry data
+    fn zstd_level(self) -> i32 {
+        match self {
+            Compression::Zstd(level) => level,
+            _ => 0,
+        }
+    }
+}
+
+/// Compression of the collection database and the media files
+///
+/// Entries left unset use the format's default: deflate for the database,
+/// and deflate for media except for formats that are already compressed,
+/// such as JPEG or MP3, which are stored. [`PackageFormat::Anki21b`] uses
+/// zstd's default level for both.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct CompressionOptions {
+    database: Option<Compression>,
+    media: Option<Compression>,
+}
+
+impl CompressionOptions {
+    /// Use the format's defaults for every entry
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Use `compression` for the database and every media file
+    pub fn all(compression: Compression) -> Self {
+        Self {
+            database: Some(compression),
+            media: Some(compression),
+        }
+    }
+
+    /// Set how the collection database is compressed
+    pub fn with_database(mut self, compression: Compression) -> Self {
+        self.database = Some(compression);
+        self
+    }
+
+    /// Set how every media file is compressed, whatever its format
+    pub fn with_media(mut self, compression: Compression) -> Self {
+        self.media = Some(compression);
+        self
+    }
+
+    /// Get the database compression, if set
+    pub fn database(&self) -> Option<Compression> {
+        self.database
+    }
+
+    /// Get the media compression, if set
+    pub fn media(&self) -> Option<Compression> {
+        self.media
+    }
+
+    /// Check that `format` supports the chosen compression
+    pub(crate) fn check(&self, format: PackageFormat) -> Result<()> {
+        if let Some(database) = self.database {
+            database.check(format, "the database")?;
+        }
+        if let Some(media) = self.media {
+            media.check(format, "media files")?;
+        }
+        Ok(())
+    }
+
+    /// Compression of the collection database in `format`
+    fn database_in(&self, format: PackageFormat) -> Compression {
+        self.database.unwrap_or_else(|| default_compression(format))
+    }
+
+    /// Compression of the media file `name` in `format`
+    fn media_in(&self, format: PackageFormat, name: &str) -> Compression {
+        self.media.unwrap_or_else(|| {
+            if !format.is_zstd_compressed() && is_compressed_format(name) {
+                Compression::Stored
+            } else {
+                default_compression(format)
+            }
+        })
+    }
+
+    /// Zip options and zstd level for the collection database
+    pub(crate) fn database_entry(
+        &self,
+        format: PackageFormat,
+        options: SimpleFileOptions,
+    ) -> (SimpleFileOptions, i32) {
+        let compression = self.database_in(format);
+        (compression.zip_options(options), compression.zstd_level())
+    }
+
+    /// Zip options and zstd level for the media file `name`
+    pub(crate) fn media_entry(
+        &self,
+        format: PackageFormat,
+        name: &str,
+        options: SimpleFileOptions,
+    ) -> (SimpleFileOptions, i32) {
+        let compression = self.media_in(format, name);
+        (compression.zip_options(options), compression.zstd_level())
+    }
+}
+
+fn default_compression(format: PackageFormat) -> Compression {
+    if format.is_zstd_compressed() {
+        Compression::Zstd(0)
+    } else {
+        Compression::Deflate(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_defaults() {
+        let options = CompressionOptions::new();
+        assert_eq!(
+            options.media_in(PackageFormat::Legacy, "a.jpg"),
+            Compression::Stored
+        );
+        assert_eq!(
+            options.media_in(PackageFormat::Legacy, "a.svg"),
+            Compression::Deflate(None)
+        );
+        assert_eq!(
+            options.media_in(PackageFormat::Anki21b, "a.jpg"),
+            Compression::Zstd(0)
+        );
+        assert_eq!(
+            options.database_in(PackageFormat::Anki21),
+            Compression::Deflate(None)
+        );
+    }
+
+    #[test]
+    fn test_check() {
+        let deflate = CompressionOptions::all(Compression::Deflate(Some(9)));
+        assert!(deflate.check(PackageFormat::Legacy).is_ok());
+        assert!(matches!(
+            deflate.check(PackageFormat::Anki21b),
+            Err(Error::Config(_))
+        ));
+
+        let zstd = CompressionOptions::new().with_media(Compression::Zstd(19));
+        assert!(zstd.check(PackageFormat::Anki21b).is_ok());
+        assert!(zstd.check(PackageFormat::Anki21).is_err());
+
+        let bad_level = CompressionOptions::new().with_database(Compression::Deflate(Some(10)));
+        assert!(bad_level.check(PackageFormat::Legacy).is_err());
+    }
+}
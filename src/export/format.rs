@@ -0,0 +1,161 @@
+//! Package format versions
+//!
+//! Anki has used three .apkg layouts over time. The legacy layout is read by
+//! every version; the newer ones are what current Anki exports natively.
+
+use crate::Result;
+use crate::storage::SchemaVersion;
+use crate::storage::protobuf::{ProtoReader, ProtoValue, ProtoWriter};
+
+/// Layout of the .apkg archive to write
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PackageFormat {
+    /// `collection.anki2` with a JSON media map (Anki 2.0 and later)
+    #[default]
+    Legacy,
+    /// `collection.anki21` with a JSON media map (Anki 2.1 and later)
+    Anki21,
+    /// zstd-compressed `collection.anki21b` with a protobuf media map (Anki 2.1.50 and later)
+    Anki21b,
+}
+
+impl PackageFormat {
+    /// Name of the collection file inside the archive
+    pub fn collection_filename(self) -> &'static str {
+        match self {
+            PackageFormat::Legacy => crate::constants::DATABASE_FILENAME,
+            PackageFormat::Anki21 => crate::constants::DATABASE_21_FILENAME,
+            PackageFormat::Anki21b => crate::constants::DATABASE_21B_FILENAME,
+        }
+    }
+
+    /// Whether media is described by the JSON `collection.media` map
+    pub fn is_legacy(self) -> bool {
+        !matches!(self, PackageFormat::Anki21b)
+    }
+
+    /// Whether the collection and media files are zstd-compressed
+    pub fn is_zstd_compressed(self) -> bool {
+        !self.is_legacy()
+    }
+
+    /// Collection schema written for this format
+    ///
+    /// The modern layout uses the normalized schema current Anki writes natively;
+    /// the others keep schema 11 so older versions can read them.
+    pub fn schema_version(self) -> SchemaVersion {
+        match self {
+            PackageFormat::Anki21b => SchemaVersion::V18,
+            _ => SchemaVersion::V11,
+        }
+    }
+
+    /// Whether a placeholder `collection.anki2` is needed for older Anki versions
+    pub fn needs_dummy_collection(self) -> bool {
+        self != PackageFormat::Legacy
+    }
+
+    /// Encoded `meta` entry, if this format has one
+    pub(crate) fn meta(self) -> Option<Vec<u8>> {
+        let version = match self {
+            PackageFormat::Legacy => return None,
+            PackageFormat::Anki21 => 2,
+            PackageFormat::Anki21b => 3,
+        };
+        let mut meta = ProtoWriter::new();
+        meta.uint(1, version);
+        Some(meta.into_bytes())
+    }
+}
+
+/// Entry of the protobuf media map used by [`PackageFormat::Anki21b`]
+///
+/// The position of an entry in the map is the name of its zip entry.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct MediaEntry {
+    pub name: String,
+    pub size: u32,
+    pub sha1: Vec<u8>,
+}
+
+/// Encode the `media` entry of an [`PackageFormat::Anki21b`] package (before compression)
+pub(crate) fn encode_media_entries(entries: &[MediaEntry]) -> Vec<u8> {
+    let mut map = ProtoWriter::new();
+    for entry in entries {
+        let mut message = ProtoWriter::new();
+        message
+            .string(1, &entry.name)
+            .uint(2, entry.size as u64)
+            .bytes(3, &entry.sha1);
+        map.message(1, &message);
+    }
+    map.into_bytes()
+}
+
+/// Decode the `media` entry of an [`PackageFormat::Anki21b`] package (after decompression)
+pub(crate) fn decode_media_entries(data: &[u8]) -> Result<Vec<MediaEntry>> {
+    let mut entries = Vec::new();
+    let mut map = ProtoReader::new(data);
+    while let Some((field, value)) = map.next_field()? {
+        let (1, ProtoValue::Bytes(message)) = (field, value) else {
+            continue;
+        };
+        let mut entry = MediaEntry {
+            name: String::new(),
+            size: 0,
+            sha1: Vec::new(),
+        };
+        let mut reader = ProtoReader::new(message);
+        while let Some((field, value)) = reader.next_field()? {
+            match (field, value) {
+                (1, ProtoValue::Bytes(name)) => {
+                    entry.name = String::from_utf8_lossy(name).into_owned()
+                }
+                (2, ProtoValue::Varint(size)) => entry.size = size as u32,
+                (3, ProtoValue::Bytes(sha1)) => entry.sha1 = sha1.to_vec(),
+                _ => {}
+            }
+        }
+        entries.push(entry);
+    }
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_collection_filenames() {
+        assert_eq!(
+            PackageFormat::default().collection_filename(),
+            "collection.anki2"
+        );
+        assert_eq!(
+            PackageFormat::Anki21.collection_filename(),
+            "collection.anki21"
+        );
+        assert_eq!(
+            PackageFormat::Anki21b.collection_filename(),
+            "collection.anki21b"
+        );
+    }
+
+    #[test]
+    fn test_meta_version() {
+        assert_eq!(PackageFormat::Legacy.meta(), None);
+        assert_eq!(PackageFormat::Anki21.meta(), Some(vec![0x08, 2]));
+        assert_eq!(PackageFormat::Anki21b.meta(), Some(vec![0x08, 3]));
+    }
+
+    #[test]
+    fn test_media_entries_round_trip() {
+        let entries = vec![MediaEntry {
+            name: "sound.mp3".to_string(),
+            size: 3,
+            sha1: vec![0xab; 20],
+        }];
+        let decoded = decode_media_entries(&encode_media_entries(&entries)).unwrap();
+        assert_eq!(decoded, entries);
+    }
+}
@@ -1,11 +1,175 @@
 //! Media file management
 
-use std::collections::HashMap;
+use crate::core::diagnostics::{Diagnostic, Location, TemplateSide};
+use crate::core::html::media_references;
+use crate::core::{Deck, Model};
+use crate::error::{Error, Result};
+use fancy_regex::Regex;
+use sha1::{Digest, Sha1};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, LazyLock, Mutex, PoisonError};
+use unicode_normalization::UnicodeNormalization;
+
+static CSS_URLS: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"(?i)url\(\s*(?:"([^"]+)"|'([^']+)'|([^)"'\s]+))\s*\)"#).unwrap()
+});
+
+/// Longest filename Anki accepts, in bytes
+const MAX_FILENAME_LENGTH: usize = 120;
+
+/// Stems Windows reserves for devices, whatever the extension
+static WINDOWS_DEVICE_NAME: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?i)^(con|prn|aux|nul|com[1-9]|lpt[1-9])(\.|$)").unwrap());
+
+/// Extensions of formats that are already compressed, so deflating them
+/// only costs time
+const COMPRESSED_EXTENSIONS: &[&str] = &[
+    "mp3", "ogg", "oga", "opus", "m4a", "aac", "flac", "webm", "mp4", "mkv", "jpg", "jpeg", "png",
+    "gif", "webp", "avif", "woff", "woff2", "zip", "gz",
+];
+
+/// Check whether a media file is in an already compressed format
+pub fn is_compressed_format(name: &str) -> bool {
+    Path::new(name)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| {
+            COMPRESSED_EXTENSIONS
+                .iter()
+                .any(|known| known.eq_ignore_ascii_case(ext))
+        })
+}
+
+/// Normalize a media filename the way Anki does when adding a file
+///
+/// The name is converted to Unicode NFC, characters that are not allowed
+/// in filenames on some platform (`[]<>:"/?*^\|` and control characters)
+/// are removed, Windows device names and trailing dots or spaces are made
+/// safe, and the stem is shortened so the name fits in 120 bytes.
+pub fn normalize_filename(name: &str) -> String {
+    let mut name: String = name
+        .nfc()
+        .filter(|&c| {
+            !matches!(
+                c,
+                '[' | ']' | '<' | '>' | ':' | '"' | '/' | '?' | '*' | '^' | '\\' | '|'
+            ) && !c.is_control()
+        })
+        .collect();
+
+    // Like Anki, device names are fixed before trailing dots and spaces
+    name = WINDOWS_DEVICE_NAME.replace(&name, "${1}_${2}").into_owned();
+    if name.ends_with(['.', ' ']) {
+        name.push('_');
+    }
+    if name.is_empty() {
+        name.push('_');
+    }
+    with_stem_suffix(&name, "")
+}
+
+/// Append `suffix` to the stem of `name`, shortening the stem so the result
+/// fits in [`MAX_FILENAME_LENGTH`] bytes
+fn with_stem_suffix(name: &str, suffix: &str) -> String {
+    let (stem, extension) = match name.rfind('.') {
+        Some(dot) if dot > 0 => name.split_at(dot),
+        _ => (name, ""),
+    };
+    let max_stem = MAX_FILENAME_LENGTH.saturating_sub(extension.len() + suffix.len());
+    let mut end = stem.len().min(max_stem);
+    while !stem.is_char_boundary(end) {
+        end -= 1;
+    }
+    format!("{}{suffix}{extension}", &stem[..end])
+}
+
+/// Content of a media file that is read only when the package is written
+///
+/// Files are opened on every write and streamed into the archive, so they
+/// never have to fit in memory. Readers are consumed by the first write;
+/// writing a package using one again fails.
+#[derive(Clone)]
+pub struct MediaSource(SourceKind);
+
+type SharedReader = Arc<Mutex<Option<Box<dyn Read + Send>>>>;
+
+#[derive(Clone)]
+enum SourceKind {
+    Path(PathBuf),
+    Reader(SharedReader),
+}
+
+impl MediaSource {
+    /// Read the media file from `path`
+    pub fn path<P: AsRef<Path>>(path: P) -> Self {
+        Self(SourceKind::Path(path.as_ref().to_path_buf()))
+    }
+
+    /// Read the media file from `reader`, once
+    pub fn reader<R: Read + Send + 'static>(reader: R) -> Self {
+        Self(SourceKind::Reader(Arc::new(Mutex::new(Some(Box::new(
+            reader,
+        ))))))
+    }
+
+    /// Get the path, if the source is a file
+    pub fn as_path(&self) -> Option<&Path> {
+        match &self.0 {
+            SourceKind::Path(path) => Some(path),
+            SourceKind::Reader(_) => None,
+        }
+    }
+
+    /// Open the content of the media file called `name`
+    pub(crate) fn open(&self, name: &str) -> Result<Box<dyn Read + Send>> {
+        match &self.0 {
+            SourceKind::Path(path) => Ok(Box::new(File::open(path)?)),
+            SourceKind::Reader(reader) => reader
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .take()
+                .ok_or_else(|| consumed_error(name)),
+        }
+    }
+
+    /// Check whether the source is a reader that was already read
+    pub(crate) fn is_consumed(&self) -> bool {
+        match &self.0 {
+            SourceKind::Path(_) => false,
+            SourceKind::Reader(reader) => reader
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .is_none(),
+        }
+    }
+}
+
+fn consumed_error(name: &str) -> Error {
+    Error::Validation(format!("media source '{name}' was already consumed"))
+}
+
+impl fmt::Debug for MediaSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.0 {
+            SourceKind::Path(path) => f.debug_tuple("Path").field(path).finish(),
+            SourceKind::Reader(_) => f.write_str("Reader"),
+        }
+    }
+}
 
 /// Collection of media files
+///
+/// Files are either held in memory or read from a [`MediaSource`] when the
+/// package is written. A source takes precedence over in-memory data with
+/// the same name.
 #[derive(Debug, Clone, Default)]
 pub struct MediaFiles {
     files: HashMap<String, Vec<u8>>,
+    sources: HashMap<String, MediaSource>,
 }
 
 impl MediaFiles {
@@ -17,19 +181,437 @@ impl MediaFiles {
         self.files.insert(name, data);
     }
 
+    /// Add a file that is streamed from `path` when the package is written
+    pub fn add_path<P: AsRef<Path>>(&mut self, name: String, path: P) {
+        self.add_source(name, MediaSource::path(path));
+    }
+
+    /// Add a file that is read from `source` when the package is written
+    pub fn add_source(&mut self, name: String, source: MediaSource) {
+        self.sources.insert(name, source);
+    }
+
+    /// Get the in-memory data of a file
     pub fn get(&self, name: &str) -> Option<&[u8]> {
         self.files.get(name).map(|v| v.as_slice())
     }
 
+    /// Number of files, in memory or not
     pub fn len(&self) -> usize {
         self.files.len()
+            + self
+                .sources
+                .keys()
+                .filter(|name| !self.files.contains_key(*name))
+                .count()
     }
 
     pub fn is_empty(&self) -> bool {
-        self.files.is_empty()
+        self.files.is_empty() && self.sources.is_empty()
     }
 
+    /// Get the files held in memory
     pub fn files(&self) -> &HashMap<String, Vec<u8>> {
         &self.files
     }
+
+    /// Get the files held in memory (mutable)
+    pub fn files_mut(&mut self) -> &mut HashMap<String, Vec<u8>> {
+        &mut self.files
+    }
+
+    /// Get the files read when the package is written
+    pub fn sources(&self) -> &HashMap<String, MediaSource> {
+        &self.sources
+    }
+
+    /// Check whether a file called `name` is held in memory or as a source
+    pub fn contains(&self, name: &str) -> bool {
+        self.files.contains_key(name) || self.sources.contains_key(name)
+    }
+
+    /// Get the names of all files, in order
+    pub fn names(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = self
+            .files
+            .keys()
+            .chain(self.sources.keys())
+            .map(String::as_str)
+            .collect();
+        names.sort_unstable();
+        names.dedup();
+        names
+    }
+
+    /// Fail if a reader source was already consumed by an earlier write
+    ///
+    /// Called before any output is created, so writing a package twice fails
+    /// up front instead of partway through.
+    pub(crate) fn check_sources(&self) -> Result<()> {
+        match self.names().into_iter().find(|name| {
+            self.sources
+                .get(*name)
+                .is_some_and(MediaSource::is_consumed)
+        }) {
+            Some(name) => Err(consumed_error(name)),
+            None => Ok(()),
+        }
+    }
+
+    /// Normalize filenames and merge files with identical content
+    ///
+    /// Names are normalized with [`normalize_filename`]. When several files
+    /// have the same content, only the one with the first name is kept;
+    /// when different files end up with the same name, a number is added to
+    /// the later ones. Returns the new name of every renamed or merged file,
+    /// for [`Note::rename_media_references`](crate::core::Note::rename_media_references).
+    ///
+    /// Files from paths are read to hash them. Reader sources cannot be read
+    /// twice, so they are renamed but never merged.
+    pub fn normalize(&mut self) -> Result<BTreeMap<String, String>> {
+        let mut files = std::mem::take(&mut self.files);
+        let mut sources = std::mem::take(&mut self.sources);
+        files.retain(|name, _| !sources.contains_key(name));
+
+        // Names that are already normal go first, so they keep their name
+        let mut names: Vec<String> = files.keys().chain(sources.keys()).cloned().collect();
+        names.sort_by_cached_key(|name| (normalize_filename(name) != *name, name.clone()));
+
+        let mut renames = BTreeMap::new();
+        let mut by_hash: HashMap<[u8; 20], String> = HashMap::new();
+        for name in names {
+            let hash = match (files.get(&name), sources.get(&name)) {
+                (Some(data), _) => Some(Sha1::digest(data).into()),
+                (None, Some(source)) => match source.as_path() {
+                    Some(path) => {
+                        let mut hasher = Sha1::new();
+                        io::copy(&mut File::open(path)?, &mut hasher)?;
+                        Some(hasher.finalize().into())
+                    }
+                    None => None,
+                },
+                (None, None) => None,
+            };
+
+            let final_name = match hash.and_then(|hash| by_hash.get(&hash)) {
+                Some(kept) => kept.clone(),
+                None => {
+                    let normalized = normalize_filename(&name);
+                    let mut final_name = normalized.clone();
+                    let mut counter = 1;
+                    while self.files.contains_key(&final_name)
+                        || self.sources.contains_key(&final_name)
+                    {
+                        final_name = with_stem_suffix(&normalized, &format!("-{counter}"));
+                        counter += 1;
+                    }
+                    if let Some(hash) = hash {
+                        by_hash.insert(hash, final_name.clone());
+                    }
+                    if let Some(data) = files.remove(&name) {
+                        self.files.insert(final_name.clone(), data);
+                    } else if let Some(source) = sources.remove(&name) {
+                        self.sources.insert(final_name.clone(), source);
+                    }
+                    final_name
+                }
+            };
+            if final_name != name {
+                renames.insert(name, final_name);
+            }
+        }
+        Ok(renames)
+    }
+
+    /// Open a file for reading, preferring its source over in-memory data
+    pub(crate) fn open(&self, name: &str) -> Result<Box<dyn Read + Send + '_>> {
+        if let Some(source) = self.sources.get(name) {
+            return source.open(name);
+        }
+        let data = self.files.get(name).ok_or_else(|| {
+            Error::Validation(format!("Media file {name} is not part of the package"))
+        })?;
+        Ok(Box::new(data.as_slice()))
+    }
+}
+
+impl From<HashMap<String, Vec<u8>>> for MediaFiles {
+    fn from(files: HashMap<String, Vec<u8>>) -> Self {
+        Self {
+            files,
+            sources: HashMap::new(),
+        }
+    }
+}
+
+/// Media files referenced by notes, card templates and model CSS
+///
+/// Fields and templates are searched for `<img src>`, `[sound:]` and the
+/// other tags Anki recognises, CSS for `url(...)`, such as the `_font.ttf`
+/// of an `@font-face` rule.
+#[derive(Debug, Clone, Default)]
+pub struct MediaScan {
+    references: BTreeMap<String, Vec<Location>>,
+}
+
+impl MediaScan {
+    /// Find every media file referenced in `decks`
+    pub fn scan(decks: &[Deck]) -> Self {
+        let mut scan = Self::default();
+        let mut scanned_models = HashSet::new();
+        for deck in decks {
+            for (index, note) in deck.notes().iter().enumerate() {
+                let model = note.model();
+                if scanned_models.insert((model.id, model.definition_fingerprint())) {
+                    scan.scan_model(model);
+                }
+                for (field, value) in model.fields.iter().zip(note.fields()) {
+                    for name in media_references(value) {
+                        scan.add(
+                            name,
+                            Location::Note {
+                                deck: deck.name.clone(),
+                                index,
+                                guid: note.guid().to_string(),
+                                field: Some(field.name.clone()),
+                            },
+                        );
+                    }
+                }
+            }
+        }
+        scan
+    }
+
+    fn scan_model(&mut self, model: &Model) {
+        for (index, template) in model.templates.iter().enumerate() {
+            for (side, text) in [
+                (TemplateSide::Question, &template.qfmt),
+                (TemplateSide::Answer, &template.afmt),
+            ] {
+                for name in media_references(text) {
+                    self.add(
+                        name,
+                        Location::Template {
+                            model: model.name.clone(),
+                            index,
+                            name: template.name.clone(),
+                            side: Some(side),
+                            position: None,
+                        },
+                    );
+                }
+            }
+        }
+
+        let css_urls = CSS_URLS
+            .captures_iter(&model.css)
+            .filter_map(|caps| caps.ok())
+            .filter_map(|caps| {
+                (1..=3)
+                    .find_map(|i| caps.get(i))
+                    .map(|m| m.as_str().to_string())
+            })
+            .filter(|name| !name.contains("://") && !name.starts_with("data:"));
+        for name in css_urls {
+            self.add(
+                name,
+                Location::Model {
+                    model: model.name.clone(),
+                },
+            );
+        }
+    }
+
+    fn add(&mut self, name: String, location: Location) {
+        self.references.entry(name).or_default().push(location);
+    }
+
+    /// Get each referenced filename with the places referencing it
+    pub fn references(&self) -> &BTreeMap<String, Vec<Location>> {
+        &self.references
+    }
+
+    /// Iterate over the referenced filenames, in order
+    pub fn filenames(&self) -> impl Iterator<Item = &str> {
+        self.references.keys().map(String::as_str)
+    }
+
+    /// Check whether a file is referenced anywhere
+    pub fn is_referenced(&self, filename: &str) -> bool {
+        self.references.contains_key(filename)
+    }
+
+    /// Referenced filenames that are not among `available`, in order
+    pub fn missing<S: AsRef<str>>(&self, available: impl IntoIterator<Item = S>) -> Vec<&str> {
+        let available: Vec<S> = available.into_iter().collect();
+        let available: HashSet<&str> = available.iter().map(AsRef::as_ref).collect();
+        self.filenames()
+            .filter(|name| !available.contains(name))
+            .collect()
+    }
+
+    /// Files among `available` that nothing references, in order
+    ///
+    /// Like Anki, files starting with `_` are never reported: they are
+    /// shared by templates and may be loaded from scripts.
+    pub fn unused<S: AsRef<str>>(&self, available: impl IntoIterator<Item = S>) -> Vec<S> {
+        let mut unused: Vec<S> = available
+            .into_iter()
+            .filter(|name| {
+                let name = name.as_ref();
+                !name.starts_with('_') && !self.is_referenced(name)
+            })
+            .collect();
+        unused.sort_by(|a, b| a.as_ref().cmp(b.as_ref()));
+        unused
+    }
+
+    /// An error for each reference to a missing file and a warning for each
+    /// unused file
+    pub fn diagnostics<S: AsRef<str>>(
+        &self,
+        available: impl IntoIterator<Item = S>,
+    ) -> Vec<Diagnostic> {
+        let available: Vec<S> = available.into_iter().collect();
+        let available: Vec<&str> = available.iter().map(AsRef::as_ref).collect();
+
+        let mut diagnostics = Vec::new();
+        for name in self.missing(&available) {
+            for location in &self.references[name] {
+                diagnostics.push(Diagnostic::error(
+                    location.clone(),
+                    format!("references missing media file '{name}'"),
+                ));
+            }
+        }
+        for name in self.unused(&available) {
+            diagnostics.push(Diagnostic::warning(
+                Location::Media {
+                    filename: name.to_string(),
+                },
+                "not referenced by any note, template or CSS",
+            ));
+        }
+        diagnostics
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::{Field, Note, Template};
+
+    #[test]
+    fn test_is_compressed_format() {
+        assert!(is_compressed_format("a.MP3"));
+        assert!(is_compressed_format("photo.jpeg"));
+        assert!(!is_compressed_format("_style.css"));
+        assert!(!is_compressed_format("noextension"));
+    }
+
+    #[test]
+    fn test_normalize_filename() {
+        assert_eq!(normalize_filename("a/b?c*.png"), "abc.png");
+        // "e" followed by a combining acute accent becomes a single "é"
+        assert_eq!(normalize_filename("cafe\u{301}.mp3"), "café.mp3");
+        assert_eq!(normalize_filename("con.txt"), "con_.txt");
+        assert_eq!(normalize_filename("LPT1"), "LPT1_");
+        assert_eq!(normalize_filename("nul."), "nul_._");
+        assert_eq!(normalize_filename("trailing."), "trailing._");
+        assert_eq!(normalize_filename("???"), "_");
+
+        let long = format!("{}.jpeg", "é".repeat(100));
+        let normalized = normalize_filename(&long);
+        assert_eq!(normalized.len(), 119);
+        assert!(normalized.ends_with("é.jpeg"));
+    }
+
+    #[test]
+    fn test_normalize_dedupes_and_renames() {
+        let mut media = MediaFiles::new();
+        media.add("flag.png".to_string(), vec![1]);
+        media.add("flag_copy.png".to_string(), vec![1]);
+        media.add("flag?.png".to_string(), vec![2]);
+        media.add("sound:1.mp3".to_string(), vec![3]);
+        media.add_source("a|b.txt".to_string(), MediaSource::reader(&b"x"[..]));
+
+        let renames = media.normalize().unwrap();
+        assert_eq!(
+            renames.into_iter().collect::<Vec<_>>(),
+            vec![
+                ("a|b.txt".to_string(), "ab.txt".to_string()),
+                ("flag?.png".to_string(), "flag-1.png".to_string()),
+                ("flag_copy.png".to_string(), "flag.png".to_string()),
+                ("sound:1.mp3".to_string(), "sound1.mp3".to_string()),
+            ]
+        );
+        assert_eq!(
+            media.names(),
+            vec!["ab.txt", "flag-1.png", "flag.png", "sound1.mp3"]
+        );
+        assert_eq!(media.get("flag-1.png"), Some(&[2][..]));
+    }
+
+    #[test]
+    fn test_media_sources() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("b.txt");
+        std::fs::write(&path, "from disk").unwrap();
+
+        let mut media = MediaFiles::new();
+        media.add("a.txt".to_string(), b"in memory".to_vec());
+        media.add("b.txt".to_string(), b"replaced".to_vec());
+        media.add_path("b.txt".to_string(), &path);
+        media.add_source("c.txt".to_string(), MediaSource::reader(&b"streamed"[..]));
+        assert_eq!(media.len(), 3);
+        assert_eq!(media.names(), vec!["a.txt", "b.txt", "c.txt"]);
+        assert!(media.contains("a.txt") && media.contains("c.txt"));
+        assert!(!media.contains("d.txt"));
+
+        let read = |name| {
+            let mut content = String::new();
+            media.open(name)?.read_to_string(&mut content)?;
+            Ok::<_, Error>(content)
+        };
+        assert_eq!(read("a.txt").unwrap(), "in memory");
+        assert_eq!(read("b.txt").unwrap(), "from disk");
+        assert_eq!(read("b.txt").unwrap(), "from disk");
+        assert_eq!(read("c.txt").unwrap(), "streamed");
+        assert!(matches!(read("c.txt"), Err(Error::Validation(_))));
+        assert!(matches!(read("d.txt"), Err(Error::Validation(_))));
+    }
+
+    #[test]
+    fn test_scan_notes_templates_and_css() {
+        let model = Model::new(
+            1,
+            "Flags",
+            vec![Field::new("Country"), Field::new("Flag")],
+            vec![
+                Template::new("Card 1")
+                    .qfmt("{{Country}}<img src=\"_logo.svg\">")
+                    .afmt("{{Flag}}"),
+            ],
+        )
+        .css("@font-face { font-family: x; src: url('_font.ttf'); } .a { background: url(https://x.org/b.png) }");
+        let mut deck = Deck::new(2, "Deck", "");
+        deck.add_note(Note::new(model.clone(), vec!["France", "<img src=\"fr.png\">"]).unwrap());
+        deck.add_note(Note::new(model, vec!["Germany", "[sound:de.mp3]"]).unwrap());
+
+        let scan = MediaScan::scan(&[deck]);
+        assert_eq!(
+            scan.filenames().collect::<Vec<_>>(),
+            vec!["_font.ttf", "_logo.svg", "de.mp3", "fr.png"]
+        );
+        assert!(matches!(
+            &scan.references()["de.mp3"][..],
+            [Location::Note { index: 1, field: Some(field), .. }] if field == "Flag"
+        ));
+
+        let available = ["_font.ttf", "_unused.css", "fr.png", "old.png"];
+        assert_eq!(scan.missing(available), vec!["_logo.svg", "de.mp3"]);
+        assert_eq!(scan.unused(available), vec!["old.png"]);
+        assert_eq!(scan.diagnostics(available).len(), 3);
+    }
 }
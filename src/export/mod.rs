@@ -2,9 +2,15 @@
 //!
 //! This module handles writing decks to .apkg files.
 
+pub mod compression;
+pub mod format;
 pub mod media;
 pub mod package;
+pub mod site;
 
 // Re-exports
-pub use media::MediaFiles;
+pub use compression::{Compression, CompressionOptions};
+pub use format::PackageFormat;
+pub use media::{MediaFiles, MediaScan, MediaSource};
 pub use package::{Package, PackageWriter};
+pub use site::SiteWriter;
@@ -1,25 +1,37 @@
 //! Package creation and export
 
 use crate::core::Deck;
-use crate::storage::{CollectionManager, cards, decks, models, notes};
-use crate::{Error, ModelDbEntry, Result};
-use std::collections::HashMap;
+use crate::core::ValidationReport;
+use crate::core::deck::validate_decks;
+use crate::core::diagnostics::{DiagnosticSink, StderrDiagnostics};
+use crate::export::compression::CompressionOptions;
+use crate::export::format::{MediaEntry, PackageFormat, encode_media_entries};
+use crate::export::media::{MediaFiles, MediaScan, MediaSource};
+use crate::export::site::SiteWriter;
+use crate::storage::{
+    CollectionManager, IdAllocator, SchemaVersion, cards, decks, models, notes, revlog, schema18,
+};
+use crate::{Error, Result};
+use sha1::{Digest, Sha1};
+use std::collections::{BTreeMap, HashMap};
 use std::fs::File;
-use std::io::{Read, Seek, Write};
-use std::ops::RangeFrom;
-use std::path::Path;
+use std::io::{self, Cursor, Read, Seek, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 use std::time::SystemTime;
 use tempfile::NamedTempFile;
-use zip::ZipWriter;
+use time::Date;
 use zip::write::SimpleFileOptions;
-
-/// Errors that can occur during package writing
+use zip::{CompressionMethod, DateTime, ZipWriter};
 
 /// Package containing one or more decks
-#[allow(dead_code)]
 pub struct Package {
     decks: Vec<Deck>,
-    media_files: HashMap<String, Vec<u8>>,
+    media: MediaFiles,
+    format: PackageFormat,
+    compression: CompressionOptions,
+    timestamp: Option<SystemTime>,
+    diagnostics: Arc<dyn DiagnosticSink>,
 }
 
 impl Package {
@@ -28,101 +40,347 @@ impl Package {
         if decks.is_empty() {
             return Err(Error::NoDecks);
         }
-        Ok(Self { decks, media_files })
+        Ok(Self {
+            decks,
+            media: MediaFiles::from(media_files),
+            format: PackageFormat::default(),
+            compression: CompressionOptions::default(),
+            timestamp: None,
+            diagnostics: Arc::new(StderrDiagnostics),
+        })
+    }
+
+    /// Read a package from an .apkg file
+    pub fn read_from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        crate::import::PackageReader::open(path)?.read()
+    }
+
+    /// Get the decks
+    pub fn decks(&self) -> &[Deck] {
+        &self.decks
+    }
+
+    /// Get the decks (mutable)
+    pub fn decks_mut(&mut self) -> &mut Vec<Deck> {
+        &mut self.decks
+    }
+
+    /// Get the media files held in memory
+    ///
+    /// Files added with [`PackageWriter::add_media`] or as a
+    /// [`MediaSource`] are streamed when writing and are not included; use
+    /// [`Package::media`] to see every file.
+    pub fn media_files(&self) -> &HashMap<String, Vec<u8>> {
+        self.media.files()
+    }
+
+    /// Get the media files held in memory (mutable)
+    pub fn media_files_mut(&mut self) -> &mut HashMap<String, Vec<u8>> {
+        self.media.files_mut()
+    }
+
+    /// Get all media files, including those streamed from sources
+    pub fn media(&self) -> &MediaFiles {
+        &self.media
+    }
+
+    /// Get all media files (mutable)
+    pub fn media_mut(&mut self) -> &mut MediaFiles {
+        &mut self.media
+    }
+
+    /// Add a media file that is streamed from `source` when writing
+    ///
+    /// Use [`MediaSource::path`] for large files, so they are never held in
+    /// memory.
+    pub fn with_media_source(mut self, name: &str, source: MediaSource) -> Self {
+        self.media.add_source(name.to_string(), source);
+        self
+    }
+
+    /// Set the archive layout to write
+    pub fn with_format(mut self, format: PackageFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// Get the archive layout to write
+    pub fn format(&self) -> PackageFormat {
+        self.format
+    }
+
+    /// Set how the database and media entries are compressed
+    ///
+    /// Writing fails if the format does not support the chosen compression.
+    pub fn with_compression(mut self, compression: CompressionOptions) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /// Get the compression settings
+    pub fn compression(&self) -> CompressionOptions {
+        self.compression
+    }
+
+    /// Fix the time the package is written at
+    ///
+    /// The timestamp is used for modification times, generated note and card
+    /// IDs and zip entry dates. Without it the current time is used; with it,
+    /// writing the same package twice produces byte-identical files.
+    pub fn with_timestamp(mut self, timestamp: SystemTime) -> Self {
+        self.timestamp = Some(timestamp);
+        self
+    }
+
+    /// Get the fixed write timestamp, if any
+    pub fn timestamp(&self) -> Option<SystemTime> {
+        self.timestamp
+    }
+
+    /// Set where warnings found while writing go, such as fields with
+    /// invalid HTML
+    ///
+    /// Defaults to [`StderrDiagnostics`]. Use `CollectDiagnostics` to inspect
+    /// them, `IgnoreDiagnostics` to silence them or `DenyDiagnostics` to
+    /// fail the write.
+    pub fn with_diagnostics(mut self, sink: Arc<dyn DiagnosticSink>) -> Self {
+        self.diagnostics = sink;
+        self
     }
 
     /// Write to a file
+    ///
+    /// The package is written to a temporary file next to `path`, which
+    /// replaces `path` only once writing succeeded, so a failed write leaves
+    /// no partial package and keeps any existing file.
     pub fn write_to_file<P: AsRef<Path>>(self, path: P) -> Result<()> {
-        let mut temp_file = NamedTempFile::new()?;
+        let path = path.as_ref();
+        let dir = match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+        self.media.check_sources()?;
+        let mut file = NamedTempFile::new_in(dir)?;
+        self.write_to(file.as_file_mut())?;
+        file.persist(path).map_err(|e| e.error)?;
+        Ok(())
+    }
 
-        let mut collection = CollectionManager::open(&temp_file)?;
-        collection.init_schema()?;
+    /// Write the package to any seekable sink, such as a file or an HTTP body buffer
+    pub fn write_to<W: Write + Seek>(&self, writer: W) -> Result<()> {
+        self.compression.check(self.format)?;
+        self.media.check_sources()?;
+        let now = self.timestamp.unwrap_or_else(SystemTime::now);
+        let collection = self.build_collection(now)?;
 
-        // Write decks, models, notes, and cards
-        let mut id_gen = 0..;
-        let timestamp = SystemTime::now()
-            .duration_since(SystemTime::UNIX_EPOCH)?
-            .as_secs_f64()
-            * 1000.0;
+        let opt = SimpleFileOptions::default().last_modified_time(zip_datetime(now));
+        let stored = opt.compression_method(CompressionMethod::Stored);
+        let mut zip = ZipWriter::new(writer);
 
-        for deck in &self.decks {
-            self.write_deck_to_db(deck, collection.connection_mut(), timestamp, &mut id_gen)?;
+        if let Some(meta) = self.format.meta() {
+            zip.start_file(crate::constants::META_FILENAME, stored)?;
+            zip.write_all(&meta)?;
         }
 
-        let package_file = File::create(path)?;
+        let (options, level) = self.compression.database_entry(self.format, opt);
+        zip.start_file(self.format.collection_filename(), options)?;
+        if self.format.is_zstd_compressed() {
+            zip.write_all(&zstd::encode_all(collection.as_slice(), level)?)?;
+        } else {
+            zip.write_all(&collection)?;
+        }
 
-        let opt = SimpleFileOptions::default();
-        let mut zip = ZipWriter::new(package_file);
-        let mut buf = vec![];
-        temp_file.rewind()?;
-        temp_file.read_to_end(&mut buf)?;
-        zip.start_file(crate::constants::DATABASE_FILENAME, opt)?;
-        zip.write_all(&buf)?;
+        if self.format.needs_dummy_collection() {
+            zip.start_file(crate::constants::DATABASE_FILENAME, opt)?;
+            zip.write_all(&empty_collection()?)?;
+        }
 
-        let media_files_mapping_string =
-            serde_json::to_string(&self.prepare_media_files_mapping())?;
+        let media_entries = self.media_entries();
+        if self.format.is_legacy() {
+            let media_files_mapping: BTreeMap<&str, &str> = media_entries
+                .iter()
+                .map(|(entry, name)| (entry.as_str(), *name))
+                .collect();
 
-        zip.start_file(crate::constants::MEDIA_MAPPING_FILENAME, opt)?;
-        zip.write_all(media_files_mapping_string.as_bytes())?;
+            zip.start_file(crate::constants::MEDIA_MAPPING_FILENAME, opt)?;
+            zip.write_all(serde_json::to_string(&media_files_mapping)?.as_bytes())?;
 
-        self.media_files.iter().try_for_each(|(name, data)| {
-            zip.start_file(format!("{}/{name}", crate::constants::MEDIA_DIRNAME), opt)?;
-            zip.write_all(data)?;
-            Ok::<(), Error>(())
-        })?;
+            for (entry, name) in &media_entries {
+                let (options, _) = self.compression.media_entry(self.format, name, opt);
+                zip.start_file(entry.as_str(), options)?;
+                io::copy(&mut self.media.open(name)?, &mut zip)?;
+            }
+        } else {
+            let mut media_map = Vec::with_capacity(media_entries.len());
+            for (entry, name) in &media_entries {
+                // Hash and count the file while compressing it, so it is
+                // only read once
+                let mut reader = HashingReader::new(self.media.open(name)?);
+                let (options, level) = self.compression.media_entry(self.format, name, opt);
+                zip.start_file(entry.as_str(), options)?;
+                let mut encoder = zstd::Encoder::new(&mut zip, level)?;
+                io::copy(&mut reader, &mut encoder)?;
+                encoder.finish()?;
 
+                media_map.push(MediaEntry {
+                    name: name.to_string(),
+                    size: u32::try_from(reader.len).map_err(|_| {
+                        Error::Validation(format!("Media file {name} exceeds 4 GiB"))
+                    })?,
+                    sha1: reader.sha1.finalize().to_vec(),
+                });
+            }
+
+            zip.start_file(crate::constants::MEDIA_ENTRIES_FILENAME, stored)?;
+            zip.write_all(&zstd::encode_all(
+                encode_media_entries(&media_map).as_slice(),
+                0,
+            )?)?;
+        }
+
+        zip.finish()?;
         Ok(())
     }
 
-    fn prepare_media_files_mapping(&self) -> HashMap<String, String> {
-        self.media_files
-            .keys()
-            .map(|name| {
-                (
-                    name.clone(),
-                    format!("{}/{}", crate::constants::MEDIA_DIRNAME, name),
-                )
-            })
+    /// Check the decks, their notes and media files for problems
+    ///
+    /// In addition to [`Deck::validate`], this finds GUID, model ID and deck
+    /// ID conflicts between decks, references to media files missing from the
+    /// package and media files nothing references.
+    pub fn validate(&self) -> ValidationReport {
+        let mut report = validate_decks(&self.decks);
+        report.extend(MediaScan::scan(&self.decks).diagnostics(self.media.names()));
+        report
+    }
+
+    /// Normalize media filenames, merge media files with identical content
+    /// and update the note fields that reference them
+    ///
+    /// Returns the new name of every renamed or merged file; see
+    /// [`MediaFiles::normalize`]. References in templates and CSS are left
+    /// as they are.
+    pub fn normalize_media(&mut self) -> Result<BTreeMap<String, String>> {
+        let renames = self.media.normalize()?;
+        for deck in &mut self.decks {
+            for note in deck.notes_mut() {
+                note.rename_media_references(&renames);
+            }
+        }
+        Ok(renames)
+    }
+
+    /// Write a static HTML review site for the decks and media into `dir`
+    ///
+    /// See [`SiteWriter`] for the layout.
+    pub fn write_site<P: AsRef<Path>>(&self, dir: P) -> Result<()> {
+        SiteWriter::new()
+            .media(self.media.clone())
+            .write(&self.decks, dir)
+    }
+
+    /// Write the package into memory and return the `.apkg` bytes
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        let mut buffer = Cursor::new(Vec::new());
+        self.write_to(&mut buffer)?;
+        Ok(buffer.into_inner())
+    }
+
+    /// Build the SQLite collection and return its bytes
+    fn build_collection(&self, now: SystemTime) -> Result<Vec<u8>> {
+        let schema = self.format.schema_version();
+        let mut collection = CollectionManager::memory()?;
+        collection.init_schema_version(schema)?;
+
+        // Write decks, models, notes, and cards
+        let timestamp = now.duration_since(SystemTime::UNIX_EPOCH)?.as_secs_f64() * 1000.0;
+        let mut ids = IdAllocator::new(timestamp as i64);
+        ids.reserve_explicit_ids(&self.decks);
+        let mut revlog_ids = IdAllocator::new(0);
+        let creation_date = cards::creation_date(collection.connection())?;
+
+        for deck in &self.decks {
+            self.write_deck_to_db(
+                deck,
+                collection.connection_mut(),
+                schema,
+                timestamp,
+                creation_date,
+                &mut ids,
+                &mut revlog_ids,
+            )?;
+        }
+
+        Ok(collection.to_bytes()?)
+    }
+
+    /// Assign each media file its numbered zip entry, as Anki expects
+    ///
+    /// Files are numbered in name order, so `collection.media` maps
+    /// `"0"`, `"1"`, ... to the original filenames.
+    fn media_entries(&self) -> Vec<(String, &str)> {
+        self.media
+            .names()
+            .into_iter()
+            .enumerate()
+            .map(|(i, name)| (i.to_string(), name))
             .collect()
     }
 
+    #[allow(clippy::too_many_arguments)]
     fn write_deck_to_db(
         &self,
         deck: &Deck,
         conn: &mut rusqlite::Connection,
+        schema: SchemaVersion,
         timestamp: f64,
-        id_gen: &mut RangeFrom<usize>,
+        creation_date: Date,
+        ids: &mut IdAllocator,
+        revlog_ids: &mut IdAllocator,
     ) -> Result<()> {
         let transaction = conn.transaction()?;
 
-        // 1. Write deck
-        decks::write_deck_to_db(deck, &transaction)?;
-
-        // 2. Write models
-        {
-            // a. Read existing models from DB
-            let models_json_str: String =
-                transaction.query_row("SELECT models FROM col", [], |row| row.get(0))?;
-
-            let mut models: HashMap<i64, ModelDbEntry> = serde_json::from_str(&models_json_str)?;
-
-            // b. Convert each model to DB entry and insert into map
-            for model in deck.models() {
-                let mut model_clone = model.clone(); // or avoid clone if possible
-                let db_entry = models::model_to_db_entry(&mut model_clone, timestamp, deck.id);
-                models.insert(model.id, db_entry);
+        // 1. Write deck, its options group and models
+        match schema {
+            SchemaVersion::V11 => {
+                if let Some(options) = deck.options() {
+                    decks::write_deck_options_to_db(options, &transaction, timestamp)?;
+                }
+                decks::write_deck_to_db(deck, &transaction)?;
+                models::write_models_to_db(deck.models(), &transaction, timestamp, deck.id)?;
+            }
+            SchemaVersion::V18 => {
+                if let Some(options) = deck.options() {
+                    schema18::write_deck_options_to_db(options, &transaction, timestamp)?;
+                }
+                schema18::write_deck_to_db(deck, &transaction, timestamp)?;
+                for model in deck.models() {
+                    schema18::write_model_to_db(model, &transaction, timestamp, deck.id)?;
+                }
             }
-
-            // c. Write back updated models JSON
-            let models_json = serde_json::to_string(&models)?;
-            transaction.execute("UPDATE col SET models = ?", [models_json])?;
         }
 
-        // 3. Write notes and cards
-        for note in deck.notes() {
-            let note_id = notes::write_note_to_db(note, &transaction, timestamp, deck.id, id_gen)?;
+        // 2. Write notes, cards and their review history
+        for (index, note) in deck.notes().iter().enumerate() {
+            for diagnostic in note.html_diagnostics(&deck.name, index) {
+                self.diagnostics.report(diagnostic)?;
+            }
+            let note_id = notes::write_note_to_db(note, &transaction, timestamp, deck.id, ids)?;
             for card in note.cards() {
-                cards::write_card_to_db(card, &transaction, timestamp, deck.id, note_id, id_gen)?;
+                let card_id = cards::write_card_to_db(
+                    card,
+                    &transaction,
+                    timestamp,
+                    creation_date,
+                    deck.id,
+                    note_id,
+                    ids,
+                )?;
+                for entry in card.revlog() {
+                    revlog::write_revlog_to_db(entry, &transaction, card_id, revlog_ids)?;
+                }
+            }
+            if schema == SchemaVersion::V18 {
+                schema18::write_tags_to_db(note.tags(), &transaction)?;
             }
         }
 
@@ -133,28 +391,159 @@ impl Package {
 
 /// Writer for creating packages
 pub struct PackageWriter {
-    media: HashMap<String, Vec<u8>>,
+    media: MediaFiles,
+    format: PackageFormat,
+    compression: CompressionOptions,
+    timestamp: Option<SystemTime>,
+    diagnostics: Option<Arc<dyn DiagnosticSink>>,
+    media_dir: Option<PathBuf>,
+    normalize_media: bool,
 }
 
 impl PackageWriter {
     pub fn new() -> Self {
         Self {
-            media: HashMap::new(),
+            media: MediaFiles::new(),
+            format: PackageFormat::default(),
+            compression: CompressionOptions::default(),
+            timestamp: None,
+            diagnostics: None,
+            media_dir: None,
+            normalize_media: false,
         }
     }
 
+    pub fn format(mut self, format: PackageFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    pub fn compression(mut self, compression: CompressionOptions) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    pub fn timestamp(mut self, timestamp: SystemTime) -> Self {
+        self.timestamp = Some(timestamp);
+        self
+    }
+
+    pub fn diagnostics(mut self, sink: Arc<dyn DiagnosticSink>) -> Self {
+        self.diagnostics = Some(sink);
+        self
+    }
+
+    /// Add the files referenced by the notes, templates and CSS from `dir`
+    /// when building
+    ///
+    /// Files added with [`add_media`](Self::add_media) take precedence.
+    /// Referenced files that do not exist in `dir` are skipped; use
+    /// [`Package::validate`] to find them.
+    pub fn media_dir<P: AsRef<Path>>(mut self, dir: P) -> Self {
+        self.media_dir = Some(dir.as_ref().to_path_buf());
+        self
+    }
+
+    /// Normalize media filenames and merge duplicate files when building
+    ///
+    /// See [`Package::normalize_media`].
+    pub fn normalize_media(mut self, normalize: bool) -> Self {
+        self.normalize_media = normalize;
+        self
+    }
+
+    /// Add a media file that is streamed from `path` when the package is
+    /// written
+    ///
+    /// Fails if `path` is not a readable file.
     pub fn add_media<P: AsRef<Path>>(&mut self, name: &str, path: P) -> Result<()> {
-        use std::io::Read;
-        let mut file = std::fs::File::open(path)?;
-        let mut data = Vec::new();
-        file.read_to_end(&mut data)?;
-        self.media.insert(name.to_string(), data);
+        File::open(&path)?;
+        self.media.add_path(name.to_string(), path);
         Ok(())
     }
 
-    pub fn build(self, decks: Vec<Deck>) -> Result<Package> {
-        Package::new(decks, self.media)
+    /// Add a media file that is read from `reader` when the package is
+    /// written
+    ///
+    /// The reader is consumed by the first write, so the package can then
+    /// only be written once: a later [`Package::write_to`],
+    /// [`Package::to_bytes`], [`Package::write_to_file`] or
+    /// [`Package::write_site`] fails with [`Error::Validation`] before
+    /// creating any output. Use [`add_media`](Self::add_media) for files
+    /// that are written more than once.
+    pub fn add_media_reader<R: Read + Send + 'static>(&mut self, name: &str, reader: R) {
+        self.media
+            .add_source(name.to_string(), MediaSource::reader(reader));
     }
+
+    pub fn build(mut self, decks: Vec<Deck>) -> Result<Package> {
+        if let Some(dir) = &self.media_dir {
+            for name in MediaScan::scan(&decks).filenames() {
+                // Only plain filenames, so references cannot escape `dir`
+                let is_plain = Path::new(name).file_name() == Some(name.as_ref());
+                if !is_plain || self.media.contains(name) {
+                    continue;
+                }
+                let path = dir.join(name);
+                if path.is_file() {
+                    self.media.add_path(name.to_string(), path);
+                }
+            }
+        }
+
+        let mut package = Package::new(decks, HashMap::new())?
+            .with_format(self.format)
+            .with_compression(self.compression);
+        package.media = self.media;
+        if let Some(timestamp) = self.timestamp {
+            package = package.with_timestamp(timestamp);
+        }
+        if let Some(sink) = self.diagnostics {
+            package = package.with_diagnostics(sink);
+        }
+        if self.normalize_media {
+            package.normalize_media()?;
+        }
+        Ok(package)
+    }
+}
+
+/// Reader that hashes and counts what passes through it
+struct HashingReader<R> {
+    inner: R,
+    sha1: Sha1,
+    len: u64,
+}
+
+impl<R: Read> HashingReader<R> {
+    fn new(inner: R) -> Self {
+        Self {
+            inner,
+            sha1: Sha1::new(),
+            len: 0,
+        }
+    }
+}
+
+impl<R: Read> Read for HashingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.sha1.update(&buf[..n]);
+        self.len += n as u64;
+        Ok(n)
+    }
+}
+
+/// Zip entry date for a timestamp, clamped to the DOS date range
+fn zip_datetime(timestamp: SystemTime) -> DateTime {
+    DateTime::try_from(time::OffsetDateTime::from(timestamp)).unwrap_or_default()
+}
+
+/// Bytes of an empty collection, used as the placeholder `collection.anki2`
+fn empty_collection() -> Result<Vec<u8>> {
+    let mut collection = CollectionManager::memory()?;
+    collection.init_schema()?;
+    Ok(collection.to_bytes()?)
 }
 
 impl Default for PackageWriter {
@@ -0,0 +1,249 @@
+//! Static HTML review site export
+//!
+//! Writes a self-contained directory that can be opened in any browser:
+//! an `index.html` listing the decks, one index page per deck and one page
+//! per card showing its rendered front and back with the model CSS. Media
+//! files are copied next to the pages so `<img src="...">` references work,
+//! so a media file cannot share its name with a page.
+
+use crate::core::html::strip_html;
+use crate::core::{Deck, RenderedCard};
+use crate::export::media::MediaFiles;
+use crate::{Error, Result};
+use std::collections::HashSet;
+use std::fmt::Write as _;
+use std::fs::{self, File};
+use std::io;
+use std::path::Path;
+
+/// Styling for the site's own chrome; cards use their model CSS
+const SITE_CSS: &str = "\
+body.site { font-family: sans-serif; margin: 2em; }
+.site nav { margin-bottom: 1em; }
+.site table { border-collapse: collapse; }
+.site td, .site th { border: 1px solid #ccc; padding: 0.3em 0.6em; text-align: left; }
+.site .side { border: 1px solid #ccc; margin: 1em 0; }
+.site .side > h2 { background: #eee; margin: 0; padding: 0.3em 0.6em; font-size: 1em; }";
+
+/// Writer for static HTML review sites
+pub struct SiteWriter {
+    media: MediaFiles,
+    title: String,
+}
+
+impl SiteWriter {
+    pub fn new() -> Self {
+        Self {
+            media: MediaFiles::new(),
+            title: "Deck Review".to_string(),
+        }
+    }
+
+    pub fn media(mut self, media: MediaFiles) -> Self {
+        self.media = media;
+        self
+    }
+
+    pub fn title(mut self, title: &str) -> Self {
+        self.title = title.to_string();
+        self
+    }
+
+    /// Write the site for `decks` into `dir`, creating it if needed
+    ///
+    /// Fails before writing anything if a media file has a path in its name
+    /// or the name of one of the pages.
+    pub fn write<P: AsRef<Path>>(&self, decks: &[Deck], dir: P) -> Result<()> {
+        let dir = dir.as_ref();
+        self.media.check_sources()?;
+        let pages = page_names(decks);
+        for name in self.media.names() {
+            if Path::new(name).file_name() != Some(name.as_ref()) {
+                return Err(Error::Validation(format!(
+                    "Media file name '{name}' must not contain a path"
+                )));
+            }
+            if pages.contains(name) {
+                return Err(Error::Validation(format!(
+                    "Media file name '{name}' is also the name of a page of the site"
+                )));
+            }
+        }
+        fs::create_dir_all(dir)?;
+
+        for name in self.media.names() {
+            io::copy(
+                &mut self.media.open(name)?,
+                &mut File::create(dir.join(name))?,
+            )?;
+        }
+
+        let mut index = String::from("<ul>\n");
+        for deck in decks {
+            let cards = self.write_deck(deck, dir)?;
+            writeln!(
+                index,
+                "<li><a href=\"{}\">{}</a> ({} notes, {cards} cards)</li>",
+                deck_page(deck),
+                escape(&deck.name),
+                deck.num_notes()
+            )
+            .expect("writing to a String cannot fail");
+        }
+        index.push_str("</ul>\n");
+        fs::write(dir.join("index.html"), site_page(&self.title, &index))?;
+
+        Ok(())
+    }
+
+    /// Write a deck's index and card pages, returning the number of cards
+    fn write_deck(&self, deck: &Deck, dir: &Path) -> Result<usize> {
+        let pages: Vec<(String, usize, RenderedCard)> = deck
+            .notes()
+            .iter()
+            .enumerate()
+            .flat_map(|(note_idx, note)| {
+                note.cards().iter().map(move |card| {
+                    let rendered = note.render_card(card.ord(), &deck.name)?;
+                    Ok((card_page(deck, note_idx, card.ord()), note_idx, rendered))
+                })
+            })
+            .collect::<Result<_>>()?;
+
+        let mut rows = String::from(
+            "<table>\n<tr><th>Note</th><th>Card</th><th>Model</th><th>Question</th></tr>\n",
+        );
+        for (i, (page, note_idx, rendered)) in pages.iter().enumerate() {
+            let note = &deck.notes()[*note_idx];
+            let mut question = strip_html(&rendered.question);
+            if let Some((cut, _)) = question.char_indices().nth(80) {
+                question.truncate(cut);
+                question.push('…');
+            }
+            writeln!(
+                rows,
+                "<tr><td>{}</td><td><a href=\"{page}\">{}</a></td><td>{}</td><td>{}</td></tr>",
+                note_idx + 1,
+                rendered.card_ord + 1,
+                escape(&note.model().name),
+                escape(&question)
+            )
+            .expect("writing to a String cannot fail");
+
+            let nav = navigation(
+                deck,
+                i.checked_sub(1).map(|prev| pages[prev].0.as_str()),
+                pages.get(i + 1).map(|next| next.0.as_str()),
+            );
+            let body = format!(
+                "{nav}<style>\n{}\n</style>\n\
+                 <div class=\"side\"><h2>Front</h2><div class=\"card card{card}\">{}</div></div>\n\
+                 <div class=\"side\"><h2>Back</h2><div class=\"card card{card}\">{}</div></div>\n\
+                 <p>Tags: {}</p>\n",
+                rendered.css,
+                rendered.question,
+                rendered.answer,
+                escape(&note.tags().join(" ")),
+                card = rendered.card_ord + 1,
+            );
+            let title = format!(
+                "{} – note {}, card {}",
+                deck.name,
+                note_idx + 1,
+                rendered.card_ord + 1
+            );
+            fs::write(dir.join(page), site_page(&title, &body))?;
+        }
+        rows.push_str("</table>\n");
+
+        let body = format!(
+            "<nav><a href=\"index.html\">All decks</a></nav>\n<p>{}</p>\n{rows}",
+            escape(&deck.description)
+        );
+        fs::write(dir.join(deck_page(deck)), site_page(&deck.name, &body))?;
+        Ok(pages.len())
+    }
+}
+
+impl Default for SiteWriter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Names of all pages written for `decks`
+fn page_names(decks: &[Deck]) -> HashSet<String> {
+    let mut pages = HashSet::from(["index.html".to_string()]);
+    for deck in decks {
+        pages.insert(deck_page(deck));
+        for (note_idx, note) in deck.notes().iter().enumerate() {
+            pages.extend(
+                note.cards()
+                    .iter()
+                    .map(|card| card_page(deck, note_idx, card.ord())),
+            );
+        }
+    }
+    pages
+}
+
+fn deck_page(deck: &Deck) -> String {
+    format!("deck-{}.html", deck.id)
+}
+
+fn card_page(deck: &Deck, note_idx: usize, card_ord: i64) -> String {
+    format!(
+        "deck-{}-note-{}-card-{}.html",
+        deck.id,
+        note_idx + 1,
+        card_ord + 1
+    )
+}
+
+fn navigation(deck: &Deck, prev: Option<&str>, next: Option<&str>) -> String {
+    let mut nav = format!(
+        "<nav><a href=\"index.html\">All decks</a> | <a href=\"{}\">{}</a>",
+        deck_page(deck),
+        escape(&deck.name)
+    );
+    if let Some(prev) = prev {
+        write!(nav, " | <a href=\"{prev}\">Previous</a>").expect("writing to a String cannot fail");
+    }
+    if let Some(next) = next {
+        write!(nav, " | <a href=\"{next}\">Next</a>").expect("writing to a String cannot fail");
+    }
+    nav.push_str("</nav>\n");
+    nav
+}
+
+fn site_page(title: &str, body: &str) -> String {
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{title}</title>\n\
+         <style>\n{SITE_CSS}\n</style>\n</head>\n<body class=\"site\">\n<h1>{title}</h1>\n{body}</body>\n</html>\n",
+        title = escape(title)
+    )
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_page_names() {
+        let deck = Deck::new(42, "Deck", "");
+        assert_eq!(deck_page(&deck), "deck-42.html");
+        assert_eq!(card_page(&deck, 0, 1), "deck-42-note-1-card-2.html");
+    }
+
+    #[test]
+    fn test_escape() {
+        assert_eq!(escape("<a & \"b\">"), "&lt;a &amp; &quot;b&quot;&gt;");
+    }
+}
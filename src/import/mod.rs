@@ -0,0 +1,8 @@
+//! APKG import functionality for genanki-rs
+//!
+//! This module handles reading .apkg files back into decks.
+
+pub mod package;
+
+// Re-exports
+pub use package::PackageReader;
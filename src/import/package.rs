@@ -0,0 +1,262 @@
+//! Package reading and import
+
+use crate::core::config::{FIELD_SEPARATOR, db};
+use crate::core::deck_options::DEFAULT_OPTIONS_ID;
+use crate::core::{Burial, Card, Deck, DeckOptions, Flag, Model, Note, RevlogEntry};
+use crate::export::Package;
+use crate::export::format::{PackageFormat, decode_media_entries};
+use crate::storage::{
+    AnkiSchema, CollectionManager, SchemaVersion, cards, decks, models, notes, revlog, schema18,
+};
+use crate::{Error, Result};
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, Write};
+use std::path::Path;
+use tempfile::NamedTempFile;
+use zip::ZipArchive;
+
+/// Collection layouts in order of preference
+const FORMATS: [PackageFormat; 3] = [
+    PackageFormat::Anki21b,
+    PackageFormat::Anki21,
+    PackageFormat::Legacy,
+];
+
+/// Id of the default deck every Anki collection contains
+const DEFAULT_DECK_ID: i64 = 1;
+
+/// Reader for .apkg packages
+pub struct PackageReader<R: Read + Seek> {
+    archive: ZipArchive<R>,
+}
+
+impl PackageReader<BufReader<File>> {
+    /// Open a package from a file
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Self::new(BufReader::new(File::open(path)?))
+    }
+}
+
+impl<R: Read + Seek> PackageReader<R> {
+    /// Create a reader over any seekable source
+    pub fn new(reader: R) -> Result<Self> {
+        Ok(Self {
+            archive: ZipArchive::new(reader)?,
+        })
+    }
+
+    /// Read the decks, notes, models and media into a Package
+    pub fn read(mut self) -> Result<Package> {
+        let format = self.detect_format()?;
+        let collection_file = self.extract_collection(format)?;
+        let collection = CollectionManager::open(collection_file.path())?;
+        let decks = read_decks(collection.connection())?;
+        let media_files = if format.is_legacy() {
+            self.read_legacy_media()?
+        } else {
+            self.read_media()?
+        };
+        Ok(Package::new(decks, media_files)?.with_format(format))
+    }
+
+    fn detect_format(&self) -> Result<PackageFormat> {
+        FORMATS
+            .into_iter()
+            .find(|format| {
+                self.archive
+                    .index_for_name(format.collection_filename())
+                    .is_some()
+            })
+            .ok_or_else(|| Error::Validation("Package contains no collection".to_string()))
+    }
+
+    fn read_entry(&mut self, name: &str, zstd_compressed: bool) -> Result<Vec<u8>> {
+        let mut buf = Vec::new();
+        self.archive.by_name(name)?.read_to_end(&mut buf)?;
+        if zstd_compressed {
+            buf = zstd::decode_all(buf.as_slice())?;
+        }
+        Ok(buf)
+    }
+
+    fn extract_collection(&mut self, format: PackageFormat) -> Result<NamedTempFile> {
+        let buf = self.read_entry(format.collection_filename(), format.is_zstd_compressed())?;
+
+        let mut temp_file = NamedTempFile::new()?;
+        temp_file.write_all(&buf)?;
+        temp_file.flush()?;
+        Ok(temp_file)
+    }
+
+    fn read_media(&mut self) -> Result<HashMap<String, Vec<u8>>> {
+        if self
+            .archive
+            .index_for_name(crate::constants::MEDIA_ENTRIES_FILENAME)
+            .is_none()
+        {
+            return Ok(HashMap::new());
+        }
+
+        let media_map = self.read_entry(crate::constants::MEDIA_ENTRIES_FILENAME, true)?;
+        let mut media_files = HashMap::new();
+        for (i, entry) in decode_media_entries(&media_map)?.into_iter().enumerate() {
+            let data = self.read_entry(&i.to_string(), true)?;
+            media_files.insert(entry.name, data);
+        }
+
+        Ok(media_files)
+    }
+
+    fn read_legacy_media(&mut self) -> Result<HashMap<String, Vec<u8>>> {
+        let Some(index) = self
+            .archive
+            .index_for_name(crate::constants::MEDIA_MAPPING_FILENAME)
+        else {
+            return Ok(HashMap::new());
+        };
+
+        let mut mapping_json = String::new();
+        self.archive
+            .by_index(index)?
+            .read_to_string(&mut mapping_json)?;
+        let mapping: HashMap<String, String> = serde_json::from_str(&mapping_json)?;
+
+        let mut media_files = HashMap::new();
+        for (key, value) in mapping {
+            // Anki maps zip entry -> filename, older genanki-rs packages filename -> zip entry
+            let (entry, name) = if self.archive.index_for_name(&key).is_some() {
+                (key, value)
+            } else {
+                (value, key)
+            };
+            let mut data = Vec::new();
+            self.archive.by_name(&entry)?.read_to_end(&mut data)?;
+            media_files.insert(name, data);
+        }
+
+        Ok(media_files)
+    }
+}
+
+/// Rebuild the decks stored in a collection
+fn read_decks(conn: &rusqlite::Connection) -> Result<Vec<Deck>> {
+    let (model_entries, deck_entries, options_entries) = match AnkiSchema::version(conn)? {
+        SchemaVersion::V11 => (
+            models::read_models_from_db(conn)?,
+            decks::read_decks_from_db(conn)?,
+            decks::read_deck_options_from_db(conn)?,
+        ),
+        SchemaVersion::V18 => (
+            schema18::read_models_from_db(conn)?,
+            schema18::read_decks_from_db(conn)?,
+            schema18::read_deck_options_from_db(conn)?,
+        ),
+    };
+    let models = model_entries
+        .iter()
+        .map(|entry| models::db_entry_to_model(entry).map(|model| (model.id, model)))
+        .collect::<Result<HashMap<i64, Model>>>()?;
+
+    let options: HashMap<i64, DeckOptions> = options_entries
+        .iter()
+        .map(|entry| (entry.id, decks::db_entry_to_options(entry)))
+        .collect();
+
+    // Decks on the Default options group keep none of their own
+    let mut decks: BTreeMap<i64, Deck> = deck_entries
+        .iter()
+        .map(|entry| {
+            let mut deck = decks::db_entry_to_deck(entry);
+            if entry.conf != DEFAULT_OPTIONS_ID
+                && let Some(options) = options.get(&entry.conf)
+            {
+                deck = deck.with_options(options.clone());
+            }
+            (entry.id, deck)
+        })
+        .collect();
+
+    let mut note_cards: HashMap<i64, Vec<cards::CardDbEntry>> = HashMap::new();
+    let mut card_revlog: HashMap<i64, Vec<RevlogEntry>> = HashMap::new();
+    for entry in revlog::read_revlog_from_db(conn)? {
+        card_revlog
+            .entry(entry.cid)
+            .or_default()
+            .push(revlog::db_entry_to_revlog(&entry));
+    }
+
+    for card in cards::read_cards_from_db(conn)? {
+        note_cards.entry(card.nid).or_default().push(card);
+    }
+    let creation_date = cards::creation_date(conn)?;
+
+    for entry in notes::read_notes_from_db(conn)? {
+        let model = models.get(&entry.mid).cloned().ok_or_else(|| {
+            Error::Validation(format!(
+                "Note {} references unknown model {}",
+                entry.id, entry.mid
+            ))
+        })?;
+        let db_cards = note_cards.remove(&entry.id).unwrap_or_default();
+        let deck_id = db_cards
+            .first()
+            .map_or(DEFAULT_DECK_ID, cards::CardDbEntry::home_deck);
+        let note_cards = db_cards
+            .iter()
+            .map(|card| {
+                let card_deck = card.home_deck();
+                if !decks.contains_key(&card_deck) {
+                    return Err(Error::Validation(format!(
+                        "Card {} references unknown deck {card_deck}",
+                        card.id
+                    )));
+                }
+                let mut restored = Card::new(card.ord, card.queue == db::queue::SUSPENDED)
+                    .with_id(card.id)
+                    .with_scheduling(cards::db_entry_to_scheduling(card, creation_date))
+                    .with_revlog(card_revlog.remove(&card.id).unwrap_or_default());
+                restored.flag = Flag::from_i64(card.flags);
+                restored.buried = Burial::from_queue(card.queue);
+                // Cards outside the note's deck, which is taken from its first card
+                if card_deck != deck_id {
+                    restored = restored.with_deck(card_deck);
+                }
+                Ok(match cards::db_entry_to_fsrs(card)? {
+                    Some(state) => restored.with_fsrs(state),
+                    None => restored,
+                })
+            })
+            .collect::<Result<_>>()?;
+
+        let note = Note::from_parts(
+            model,
+            entry
+                .flds
+                .split(FIELD_SEPARATOR)
+                .map(str::to_string)
+                .collect(),
+            entry.tags.split_whitespace().map(str::to_string).collect(),
+            entry.guid,
+            note_cards,
+        )?
+        .with_id(entry.id);
+
+        decks
+            .get_mut(&deck_id)
+            .ok_or_else(|| {
+                Error::Validation(format!(
+                    "Note {} belongs to unknown deck {deck_id}",
+                    entry.id
+                ))
+            })?
+            .add_note(note);
+    }
+
+    // Anki always creates the default deck; only keep it if it is actually used
+    if decks.len() > 1 && decks.get(&DEFAULT_DECK_ID).is_some_and(Deck::is_empty) {
+        decks.remove(&DEFAULT_DECK_ID);
+    }
+
+    Ok(decks.into_values().collect())
+}
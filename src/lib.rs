@@ -11,28 +11,41 @@ pub mod builder;
 // Export module - APKG export functionality
 pub mod export;
 
+// Import module - APKG import functionality
+pub mod import;
+
 // Storage module - database operations
 pub mod error;
 pub mod storage;
 
 // Re-export core types and functions
 pub use crate::core::{
-    AnkiConfig, Card, Deck, DeckConfig, Error, Field, FieldDefaults, Model, ModelConfig, ModelIds,
-    ModelType, Note, Result, Template, guid_for,
+    AnkiConfig, Burial, Card, CardType, CollectDiagnostics, Deck, DeckConfig, DeckOptions,
+    DenyDiagnostics, Diagnostic, DiagnosticSink, Due, Error, Field, FieldDefaults, Flag, FsrsState,
+    GuidStrategy, IgnoreDiagnostics, LeechAction, Model, ModelConfig, ModelIds, ModelType, Note,
+    Rating, Result, ReviewKind, RevlogEntry, RevlogInterval, Scheduling, Severity,
+    StderrDiagnostics, Template, ValidationReport, guid_for,
 };
 
 // Re-export storage types
 pub use crate::storage::{
     AnkiSchema, COL_SQL, Collection, CollectionManager, DeckDbEntry, ModelDbEntry, SCHEMA_SQL,
+    SchemaVersion,
 };
 
 // Re-export builder types
 pub use crate::builder::{
-    BasicModels, DeckBuilder, FieldBuilder, ModelBuilder, NoteBuilder, TemplateBuilder,
+    BasicModels, DeckBuilder, FieldBuilder, ModelBuilder, NoteBuilder, TemplateBuilder, TemplateRef,
 };
 
 // Re-export export types
-pub use crate::export::{MediaFiles, Package, PackageWriter};
+pub use crate::export::{
+    Compression, CompressionOptions, MediaFiles, MediaScan, MediaSource, Package, PackageFormat,
+    PackageWriter, SiteWriter,
+};
+
+// Re-export import types
+pub use crate::import::PackageReader;
 
 // ===== BACKWARD COMPATIBILITY =====
 // Re-export old API for compatibility
@@ -49,6 +62,14 @@ pub mod constants {
     pub static MEDIA_MAPPING_FILENAME: &str = "collection.media";
 
     pub static DATABASE_FILENAME: &str = "collection.anki2";
+
+    pub static DATABASE_21_FILENAME: &str = "collection.anki21";
+
+    pub static DATABASE_21B_FILENAME: &str = "collection.anki21b";
+
+    pub static META_FILENAME: &str = "meta";
+
+    pub static MEDIA_ENTRIES_FILENAME: &str = "media";
 }
 
 /// Basic model (backward compatible)
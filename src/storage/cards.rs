@@ -1,51 +1,322 @@
 //! Card database operations
 
-use crate::core::{Card, Error};
-use rusqlite::{Transaction, params};
-use std::ops::RangeFrom;
+use crate::core::config::db;
+use crate::core::{Card, CardType, Due, Error, Flag, FsrsState, Scheduling};
+use crate::storage::ids::IdAllocator;
+use rusqlite::{Connection, Transaction, params};
+use serde::{Deserialize, Serialize};
+use std::time::SystemTime;
+use time::{Date, OffsetDateTime};
 
-/// Write a card to the database
+/// Write a card to the database, returning its ID
+///
+/// `deck_id` is the note's deck, used unless the card has its own. Day-based
+/// due dates are counted from the collection's [`creation_date`].
 pub fn write_card_to_db(
     card: &Card,
     transaction: &Transaction,
     timestamp: f64,
+    creation_date: Date,
     deck_id: i64,
     note_id: i64,
-    id_gen: &mut RangeFrom<usize>,
-) -> Result<(), Error> {
+    ids: &mut IdAllocator,
+) -> Result<i64, Error> {
     let queue = card.queue_value();
+    let card_id = card.id().unwrap_or_else(|| ids.next_id());
+    let scheduling = card.scheduling();
+    let due = match scheduling.due {
+        Due::Position(position) => position,
+        Due::Time(time) => time.duration_since(SystemTime::UNIX_EPOCH)?.as_secs() as i64,
+        Due::Day(date) => (date - creation_date).whole_days(),
+    };
+    let data = match card.fsrs() {
+        Some(state) => serde_json::to_string(&CardDataDbEntry::from(state))?,
+        None => String::new(),
+    };
     transaction.execute(
         "INSERT INTO cards VALUES(?,?,?,?,?,?,?,?,?,?,?,?,?,?,?,?,?,?);",
         params![
-            id_gen.next().expect("Range overflowed!") as i64, // id
-            note_id,                                          // nid
-            deck_id,                                          // did
-            card.ord(),                                       // ord
-            timestamp as i64,                                 // mod
-            -1_i64,                                           // usn
-            0_i64,                                            // type (=0 for non-Cloze)
-            queue,                                            // queue
-            0_i64,                                            // due
-            0_i64,                                            // ivl
-            0_i64,                                            // factor
-            0_i64,                                            // reps
-            0_i64,                                            // lapses
-            0_i64,                                            // left
-            0_i64,                                            // odue
-            0_i64,                                            // odid
-            0_i64,                                            // flags
-            "",                                               // data
+            card_id,                             // id
+            note_id,                             // nid
+            card.deck_id().unwrap_or(deck_id),   // did
+            card.ord(),                          // ord
+            timestamp as i64,                    // mod
+            -1_i64,                              // usn
+            scheduling.card_type.to_i64(),       // type
+            queue,                               // queue
+            due,                                 // due
+            scheduling.interval,                 // ivl
+            scheduling.ease_factor,              // factor
+            scheduling.reps,                     // reps
+            scheduling.lapses,                   // lapses
+            scheduling.remaining_steps,          // left
+            0_i64,                               // odue
+            0_i64,                               // odid
+            card.flag().map_or(0, Flag::to_i64), // flags
+            data,                                // data
         ],
     )?;
-    Ok(())
+    Ok(card_id)
 }
 
-/// Database entry for cards (for future use)
+/// Day the collection was created, which day-based due numbers count from
+pub fn creation_date(conn: &Connection) -> Result<Date, Error> {
+    let crt: i64 = conn.query_row("SELECT crt FROM col", [], |row| row.get(0))?;
+    Ok(OffsetDateTime::from_unix_timestamp(crt)
+        .map_err(|e| Error::Validation(format!("Invalid collection creation time: {e}")))?
+        .date())
+}
+
+/// Read all cards from the database, ordered by note and ordinal
+pub fn read_cards_from_db(conn: &Connection) -> Result<Vec<CardDbEntry>, Error> {
+    let mut stmt = conn.prepare(
+        "SELECT id, nid, did, ord, type, queue, due, ivl, factor, reps, lapses, left, odid, flags, \
+         data FROM cards ORDER BY nid, ord",
+    )?;
+    let cards = stmt
+        .query_map([], |row| {
+            Ok(CardDbEntry {
+                id: row.get(0)?,
+                nid: row.get(1)?,
+                did: row.get(2)?,
+                ord: row.get(3)?,
+                card_type: row.get(4)?,
+                queue: row.get(5)?,
+                due: row.get(6)?,
+                ivl: row.get(7)?,
+                factor: row.get(8)?,
+                reps: row.get(9)?,
+                lapses: row.get(10)?,
+                left: row.get(11)?,
+                odid: row.get(12)?,
+                flags: row.get(13)?,
+                data: row.get(14)?,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(cards)
+}
+
+/// Rebuild the scheduling state of a card, given the collection's
+/// [`creation_date`]
+pub fn db_entry_to_scheduling(entry: &CardDbEntry, creation_date: Date) -> Scheduling {
+    let card_type = CardType::from_i64(entry.card_type);
+    let at_time = || {
+        Due::Time(SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(entry.due.max(0) as u64))
+    };
+    let on_day = || Due::Day(creation_date + time::Duration::days(entry.due));
+    // The queue tells whether a learning card's due is a timestamp or a day
+    let due = match (card_type, entry.queue) {
+        (CardType::New, _) => Due::Position(entry.due),
+        (CardType::Review, _) => on_day(),
+        (_, db::queue::LEARNING) => at_time(),
+        (_, db::queue::DAY_LEARNING) => on_day(),
+        // Suspended and buried cards have lost their queue; timestamps are
+        // far larger than day numbers
+        _ if entry.due > 1_000_000_000 => at_time(),
+        _ => on_day(),
+    };
+    Scheduling {
+        card_type,
+        due,
+        interval: entry.ivl.max(0) as u32,
+        ease_factor: entry.factor.max(0) as u32,
+        reps: entry.reps.max(0) as u32,
+        lapses: entry.lapses.max(0) as u32,
+        remaining_steps: (entry.left.max(0) % 1000) as u32,
+    }
+}
+
+/// Rebuild the FSRS memory state stored in a card's `data` column
+pub fn db_entry_to_fsrs(entry: &CardDbEntry) -> Result<Option<FsrsState>, Error> {
+    if entry.data.is_empty() {
+        return Ok(None);
+    }
+    let data: CardDataDbEntry = serde_json::from_str(&entry.data)?;
+    Ok(match (data.stability, data.difficulty) {
+        (Some(stability), Some(difficulty)) => Some(FsrsState {
+            stability,
+            difficulty,
+            desired_retention: data.desired_retention,
+        }),
+        _ => None,
+    })
+}
+
+/// Contents of the card `data` column
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct CardDataDbEntry {
+    #[serde(rename = "s", default, skip_serializing_if = "Option::is_none")]
+    pub stability: Option<f32>,
+    #[serde(rename = "d", default, skip_serializing_if = "Option::is_none")]
+    pub difficulty: Option<f32>,
+    #[serde(rename = "dr", default, skip_serializing_if = "Option::is_none")]
+    pub desired_retention: Option<f32>,
+}
+
+impl From<&FsrsState> for CardDataDbEntry {
+    fn from(state: &FsrsState) -> Self {
+        Self {
+            stability: Some(state.stability),
+            difficulty: Some(state.difficulty),
+            desired_retention: state.desired_retention,
+        }
+    }
+}
+
+/// Database entry for cards
 #[derive(Debug, Clone)]
 pub struct CardDbEntry {
     pub id: i64,
     pub nid: i64,
     pub did: i64,
     pub ord: i64,
+    pub card_type: i64,
     pub queue: i64,
+    pub due: i64,
+    pub ivl: i64,
+    pub factor: i64,
+    pub reps: i64,
+    pub lapses: i64,
+    pub left: i64,
+    pub odid: i64,
+    pub flags: i64,
+    pub data: String,
+}
+
+impl CardDbEntry {
+    /// Deck the card belongs to, which for cards moved into a filtered deck
+    /// is their original deck
+    pub fn home_deck(&self) -> i64 {
+        if self.odid != 0 { self.odid } else { self.did }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::{CollectionManager, SchemaVersion};
+
+    #[test]
+    fn test_write_scheduled_card() {
+        let mut collection = CollectionManager::memory().unwrap();
+        collection.init_schema_version(SchemaVersion::V11).unwrap();
+        // The default collection was created on 2014-09-19
+        let due = Date::from_calendar_date(2014, time::Month::October, 1).unwrap();
+        let card = Card::new(0, false)
+            .with_id(10)
+            .with_scheduling(Scheduling::review(due, 12, 2500).with_reps(5));
+
+        let crt = creation_date(collection.connection()).unwrap();
+        let transaction = collection.connection_mut().transaction().unwrap();
+        write_card_to_db(
+            &card,
+            &transaction,
+            0.0,
+            crt,
+            1,
+            1,
+            &mut IdAllocator::new(0),
+        )
+        .unwrap();
+        transaction.commit().unwrap();
+
+        let row: (i64, i64, i64, i64, i64, i64) = collection
+            .connection()
+            .query_row(
+                "SELECT type, queue, due, ivl, factor, reps FROM cards WHERE id = 10",
+                [],
+                |row| {
+                    Ok((
+                        row.get(0)?,
+                        row.get(1)?,
+                        row.get(2)?,
+                        row.get(3)?,
+                        row.get(4)?,
+                        row.get(5)?,
+                    ))
+                },
+            )
+            .unwrap();
+        assert_eq!(row, (2, 2, 12, 12, 2500, 5));
+    }
+
+    #[test]
+    fn test_due_follows_queue() {
+        let crt = Date::from_calendar_date(2014, time::Month::September, 19).unwrap();
+        let entry = |card_type, queue, due| CardDbEntry {
+            id: 1,
+            nid: 1,
+            did: 1,
+            ord: 0,
+            card_type,
+            queue,
+            due,
+            ivl: 0,
+            factor: 0,
+            reps: 0,
+            lapses: 0,
+            left: 0,
+            odid: 0,
+            flags: 0,
+            data: String::new(),
+        };
+        let due =
+            |card_type, queue, due| db_entry_to_scheduling(&entry(card_type, queue, due), crt).due;
+
+        let timestamp = SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1_700_000_000);
+        assert_eq!(
+            due(1, db::queue::LEARNING, 1_700_000_000),
+            Due::Time(timestamp)
+        );
+        assert_eq!(
+            due(3, db::queue::DAY_LEARNING, 12),
+            Due::Day(crt + time::Duration::days(12))
+        );
+        assert_eq!(
+            due(2, db::queue::REVIEW, 30),
+            Due::Day(crt + time::Duration::days(30))
+        );
+        // Suspended cards fall back to the size of the due number
+        assert_eq!(
+            due(1, db::queue::SUSPENDED, 1_700_000_000),
+            Due::Time(timestamp)
+        );
+        assert_eq!(
+            due(3, db::queue::SUSPENDED, 12),
+            Due::Day(crt + time::Duration::days(12))
+        );
+    }
+
+    #[test]
+    fn test_write_fsrs_state() {
+        let mut collection = CollectionManager::memory().unwrap();
+        collection.init_schema_version(SchemaVersion::V18).unwrap();
+        let card = Card::new(0, false)
+            .with_id(10)
+            .with_fsrs(FsrsState::new(12.5, 4.25).with_desired_retention(0.9));
+
+        let crt = creation_date(collection.connection()).unwrap();
+        let transaction = collection.connection_mut().transaction().unwrap();
+        write_card_to_db(
+            &card,
+            &transaction,
+            0.0,
+            crt,
+            1,
+            1,
+            &mut IdAllocator::new(0),
+        )
+        .unwrap();
+        transaction.commit().unwrap();
+
+        let data: String = collection
+            .connection()
+            .query_row("SELECT data FROM cards WHERE id = 10", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(data, r#"{"s":12.5,"d":4.25,"dr":0.9}"#);
+
+        let entries = read_cards_from_db(collection.connection()).unwrap();
+        assert_eq!(db_entry_to_fsrs(&entries[0]).unwrap(), card.fsrs().copied());
+    }
 }
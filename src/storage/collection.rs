@@ -1,7 +1,8 @@
 //! Collection management
 
 use crate::core::Error;
-use rusqlite::{Connection, Result as SqlResult};
+use crate::storage::schema::SchemaVersion;
+use rusqlite::{Connection, MAIN_DB, Result as SqlResult};
 use std::path::Path;
 
 /// Anki collection manager
@@ -13,12 +14,14 @@ impl CollectionManager {
     /// Open a collection from a file
     pub fn open<P: AsRef<Path>>(path: P) -> SqlResult<Self> {
         let conn = Connection::open(path)?;
+        register_collations(&conn)?;
         Ok(Self { conn })
     }
 
     /// Create an in-memory collection
     pub fn memory() -> SqlResult<Self> {
         let conn = Connection::open_in_memory()?;
+        register_collations(&conn)?;
         Ok(Self { conn })
     }
 
@@ -28,6 +31,17 @@ impl CollectionManager {
         Ok(())
     }
 
+    /// Initialize with the given Anki schema version
+    pub fn init_schema_version(&mut self, version: SchemaVersion) -> Result<(), Error> {
+        crate::storage::schema::AnkiSchema::init_db_version(&mut self.conn, version)?;
+        Ok(())
+    }
+
+    /// Serialize the collection into the bytes of an SQLite database file
+    pub fn to_bytes(&self) -> SqlResult<Vec<u8>> {
+        Ok(self.conn.serialize(MAIN_DB)?.to_vec())
+    }
+
     /// Get the underlying connection
     pub fn connection(&self) -> &Connection {
         &self.conn
@@ -39,6 +53,11 @@ impl CollectionManager {
     }
 }
 
+/// Register the case-insensitive `unicase` collation used by schema 18 tables
+fn register_collations(conn: &Connection) -> SqlResult<()> {
+    conn.create_collation("unicase", |a, b| a.to_lowercase().cmp(&b.to_lowercase()))
+}
+
 /// Collection wrapper for type safety
 pub struct Collection(pub CollectionManager);
 
@@ -50,4 +69,20 @@ mod tests {
     fn test_collection_memory() {
         assert!(CollectionManager::memory().is_ok())
     }
+
+    #[test]
+    fn test_collection_v18_schema() {
+        let mut collection = CollectionManager::memory().unwrap();
+        collection.init_schema_version(SchemaVersion::V18).unwrap();
+        let version = crate::storage::schema::AnkiSchema::version(collection.connection()).unwrap();
+        assert_eq!(version, SchemaVersion::V18);
+    }
+
+    #[test]
+    fn test_collection_to_bytes() {
+        let mut collection = CollectionManager::memory().unwrap();
+        collection.init_schema().unwrap();
+        let bytes = collection.to_bytes().unwrap();
+        assert!(bytes.starts_with(b"SQLite format 3\0"));
+    }
 }
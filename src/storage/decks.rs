@@ -1,9 +1,10 @@
 //! Deck database operations
 
-use crate::core::{Deck, Error};
-use crate::storage::schema::DeckDbEntry;
-use rusqlite::{Transaction, params};
+use crate::core::{Deck, DeckOptions, Error, LeechAction};
+use crate::storage::schema::{DeckConfigDbEntry, DeckDbEntry};
+use rusqlite::{Connection, Transaction, params};
 use serde_json;
+use std::collections::HashMap;
 
 /// Convert a core Deck to a database entry
 pub fn deck_to_db_entry(deck: &Deck) -> DeckDbEntry {
@@ -11,10 +12,135 @@ pub fn deck_to_db_entry(deck: &Deck) -> DeckDbEntry {
         id: deck.id,
         name: deck.name.clone(),
         desc: deck.description.clone(),
+        conf: deck.options_id(),
         ..Default::default()
     }
 }
 
+/// Convert deck options to a `dconf` entry
+pub fn options_to_db_entry(options: &DeckOptions) -> DeckConfigDbEntry {
+    let mut entry = DeckConfigDbEntry {
+        id: options.id,
+        name: options.name.clone(),
+        usn: -1,
+        autoplay: options.autoplay,
+        replayq: options.replay_question,
+        bury_interday_learning: options.bury_interday_learning,
+        ..Default::default()
+    };
+    entry.new.bury = options.bury_new;
+    entry.new.delays = options.learning_steps.clone();
+    entry.new.initial_factor = (options.starting_ease * 1000.0).round() as i64;
+    entry.new.ints = vec![
+        options.graduating_interval as i64,
+        options.easy_interval as i64,
+        7,
+    ];
+    entry.new.per_day = options.new_per_day as i64;
+    entry.rev.bury = options.bury_reviews;
+    entry.rev.per_day = options.reviews_per_day as i64;
+    entry.lapse.delays = options.relearning_steps.clone();
+    entry.lapse.leech_action = options.leech_action.to_i64();
+    entry.lapse.leech_fails = options.leech_threshold as i64;
+    // Each FSRS version has its own key, told apart by the parameter count
+    let params = options.fsrs_params.clone();
+    match params.len() {
+        0 => {}
+        19 => entry.fsrs_params_5 = params,
+        21 => entry.fsrs_params_6 = params,
+        _ => entry.fsrs_weights = params,
+    }
+    entry.desired_retention = options.desired_retention;
+    entry
+}
+
+/// Convert a `dconf` entry back to deck options
+pub fn db_entry_to_options(entry: &DeckConfigDbEntry) -> DeckOptions {
+    let interval = |index: usize, default: u32| {
+        entry
+            .new
+            .ints
+            .get(index)
+            .map_or(default, |&days| days.max(0) as u32)
+    };
+    DeckOptions {
+        id: entry.id,
+        name: entry.name.clone(),
+        new_per_day: entry.new.per_day.max(0) as u32,
+        reviews_per_day: entry.rev.per_day.max(0) as u32,
+        learning_steps: entry.new.delays.clone(),
+        relearning_steps: entry.lapse.delays.clone(),
+        graduating_interval: interval(0, 1),
+        easy_interval: interval(1, 4),
+        starting_ease: entry.new.initial_factor as f32 / 1000.0,
+        leech_threshold: entry.lapse.leech_fails.max(0) as u32,
+        leech_action: LeechAction::from_i64(entry.lapse.leech_action),
+        bury_new: entry.new.bury,
+        bury_reviews: entry.rev.bury,
+        bury_interday_learning: entry.bury_interday_learning,
+        autoplay: entry.autoplay,
+        replay_question: entry.replayq,
+        fsrs_params: [
+            &entry.fsrs_params_6,
+            &entry.fsrs_params_5,
+            &entry.fsrs_weights,
+        ]
+        .into_iter()
+        .find(|params| !params.is_empty())
+        .cloned()
+        .unwrap_or_default(),
+        desired_retention: entry.desired_retention,
+    }
+}
+
+/// Convert a database entry back to an empty core Deck
+pub fn db_entry_to_deck(entry: &DeckDbEntry) -> Deck {
+    Deck::new(entry.id, &entry.name, &entry.desc)
+}
+
+/// Read all deck entries from the database
+pub fn read_decks_from_db(conn: &Connection) -> Result<Vec<DeckDbEntry>, Error> {
+    let decks_json: String = conn.query_row("SELECT decks FROM col", [], |row| row.get(0))?;
+    let decks: HashMap<String, DeckDbEntry> = serde_json::from_str(&decks_json)?;
+    let mut decks: Vec<DeckDbEntry> = decks.into_values().collect();
+    decks.sort_by_key(|d| d.id);
+    Ok(decks)
+}
+
+/// Read all deck options groups from the database
+pub fn read_deck_options_from_db(conn: &Connection) -> Result<Vec<DeckConfigDbEntry>, Error> {
+    let dconf_json: String = conn.query_row("SELECT dconf FROM col", [], |row| row.get(0))?;
+    let configs: HashMap<String, DeckConfigDbEntry> = serde_json::from_str(&dconf_json)?;
+    let mut configs: Vec<DeckConfigDbEntry> = configs.into_values().collect();
+    configs.sort_by_key(|c| c.id);
+    Ok(configs)
+}
+
+/// Write a deck options group to the database
+pub fn write_deck_options_to_db(
+    options: &DeckOptions,
+    transaction: &Transaction,
+    timestamp: f64,
+) -> Result<(), Error> {
+    let dconf_json: String =
+        transaction.query_row("SELECT dconf FROM col", [], |row| row.get(0))?;
+    let mut configs: serde_json::Map<String, serde_json::Value> =
+        serde_json::from_str(&dconf_json)?;
+
+    let entry = DeckConfigDbEntry {
+        deck_config_db_entry_mod: (timestamp / 1000.0) as i64,
+        ..options_to_db_entry(options)
+    };
+    configs.insert(options.id.to_string(), serde_json::to_value(&entry)?);
+
+    transaction.execute(
+        "UPDATE col SET dconf = ?",
+        params![serde_json::to_string(&configs)?],
+    )?;
+
+    Ok(())
+}
+
 /// Write deck to database
 pub fn write_deck_to_db(deck: &Deck, transaction: &Transaction) -> Result<(), Error> {
     let decks_json: String =
@@ -31,3 +157,36 @@ pub fn write_deck_to_db(deck: &Deck, transaction: &Transaction) -> Result<(), Er
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_options_db_entry_round_trip() {
+        let options = DeckOptions::new(42, "Custom")
+            .with_new_per_day(5)
+            .with_reviews_per_day(500)
+            .with_learning_steps(vec![0.5, 30.0])
+            .with_relearning_steps(vec![])
+            .with_intervals(2, 7)
+            .with_starting_ease(2.3)
+            .with_leech(4, LeechAction::Suspend)
+            .with_bury(false, true, true)
+            .with_autoplay(false)
+            .with_replay_question(false)
+            .with_fsrs_params(vec![0.5; 19])
+            .with_desired_retention(0.85);
+
+        let entry = options_to_db_entry(&options);
+        assert_eq!(entry.new.initial_factor, 2300);
+        assert_eq!(entry.fsrs_params_5.len(), 19);
+        assert!(entry.fsrs_weights.is_empty());
+        assert_eq!(entry.new.ints, vec![2, 7, 7]);
+        assert_eq!(db_entry_to_options(&entry), options);
+        assert_eq!(
+            db_entry_to_options(&DeckConfigDbEntry::default()),
+            DeckOptions::default()
+        );
+    }
+}
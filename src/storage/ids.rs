@@ -0,0 +1,104 @@
+//! ID allocation for notes and cards
+//!
+//! Anki uses the creation time in milliseconds since the epoch as the ID of
+//! notes and cards, bumping it by one whenever that ID is already taken.
+//! Allocating IDs the same way keeps packages generated at different times
+//! from colliding when they are imported into the same profile.
+
+use crate::core::Deck;
+use crate::error::Result;
+use std::collections::HashSet;
+use std::time::SystemTime;
+
+/// Allocator for millisecond-epoch based, monotonically increasing IDs
+#[derive(Debug, Clone)]
+pub struct IdAllocator {
+    next: i64,
+    reserved: HashSet<i64>,
+}
+
+impl IdAllocator {
+    /// Create an allocator whose first ID is `start_ms`
+    pub fn new(start_ms: i64) -> Self {
+        Self {
+            next: start_ms,
+            reserved: HashSet::new(),
+        }
+    }
+
+    /// Create an allocator starting at the current time
+    pub fn now() -> Result<Self> {
+        let now = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH)?;
+        Ok(Self::new(now.as_millis() as i64))
+    }
+
+    /// Mark an explicit ID as taken so it is never handed out
+    pub fn reserve(&mut self, id: i64) {
+        self.reserved.insert(id);
+    }
+
+    /// Reserve the explicit note and card IDs used in `decks`
+    pub fn reserve_explicit_ids<'a>(&mut self, decks: impl IntoIterator<Item = &'a Deck>) {
+        for note in decks.into_iter().flat_map(Deck::notes) {
+            self.reserved.extend(note.id());
+            self.reserved
+                .extend(note.cards().iter().filter_map(|card| card.id));
+        }
+    }
+
+    /// Claim `id`, or the first free ID after it when it is taken
+    ///
+    /// Used for IDs that record a time, such as review log entries.
+    pub fn claim(&mut self, id: i64) -> i64 {
+        let mut id = id;
+        while !self.reserved.insert(id) {
+            id += 1;
+        }
+        id
+    }
+
+    /// Allocate the next free ID
+    pub fn next_id(&mut self) -> i64 {
+        while self.reserved.contains(&self.next) {
+            self.next += 1;
+        }
+        let id = self.next;
+        self.next += 1;
+        id
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ids_are_monotonic() {
+        let mut ids = IdAllocator::new(1_700_000_000_000);
+        assert_eq!(ids.next_id(), 1_700_000_000_000);
+        assert_eq!(ids.next_id(), 1_700_000_000_001);
+    }
+
+    #[test]
+    fn test_reserved_ids_are_skipped() {
+        let mut ids = IdAllocator::new(100);
+        ids.reserve(100);
+        ids.reserve(101);
+        assert_eq!(ids.next_id(), 102);
+    }
+
+    #[test]
+    fn test_claim_bumps_taken_ids() {
+        let mut ids = IdAllocator::new(0);
+        assert_eq!(ids.claim(500), 500);
+        assert_eq!(ids.claim(500), 501);
+        assert_eq!(ids.claim(499), 499);
+    }
+
+    #[test]
+    fn test_now_uses_epoch_millis() {
+        let id = IdAllocator::now().unwrap().next_id();
+        // Later than 2020-01-01 in milliseconds
+        assert!(id > 1_577_836_800_000);
+    }
+}
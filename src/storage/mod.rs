@@ -5,15 +5,23 @@
 pub mod cards;
 pub mod collection;
 pub mod decks;
+pub mod ids;
 pub mod models;
 pub mod notes;
+pub(crate) mod protobuf;
+pub mod revlog;
 pub mod schema;
+pub mod schema18;
 
 // Re-exports from schema
 pub use schema::{
-    AnkiSchema, COL_SQL, DeckDbEntry, FieldDbEntry, ModelDbEntry, SCHEMA_SQL, TemplateDbEntry,
+    AnkiSchema, COL_SQL, COL_V18_SQL, DeckConfigDbEntry, DeckDbEntry, FieldDbEntry, ModelDbEntry,
+    SCHEMA_SQL, SCHEMA_V18_SQL, SchemaVersion, TemplateDbEntry,
 };
 
 // Re-exports from modules
-pub use cards::CardDbEntry;
+pub use cards::{CardDataDbEntry, CardDbEntry};
 pub use collection::{Collection, CollectionManager};
+pub use ids::IdAllocator;
+pub use notes::NoteDbEntry;
+pub use revlog::RevlogDbEntry;
@@ -1,7 +1,9 @@
 //! Model database operations
 
-use crate::core::{Model, ModelType};
+use crate::core::{Error, Field, Model, ModelType, Template};
 use crate::storage::schema::{FieldDbEntry, ModelDbEntry, TemplateDbEntry};
+use rusqlite::{Connection, Transaction};
+use std::collections::{BTreeMap, HashMap};
 
 /// Convert a core Model to a database entry
 pub fn model_to_db_entry(model: &mut Model, timestamp: f64, deck_id: i64) -> ModelDbEntry {
@@ -58,6 +60,86 @@ pub fn model_to_db_entry(model: &mut Model, timestamp: f64, deck_id: i64) -> Mod
     }
 }
 
+/// Write models into the `col.models` JSON
+pub fn write_models_to_db<'a>(
+    models: impl IntoIterator<Item = &'a Model>,
+    transaction: &Transaction,
+    timestamp: f64,
+    deck_id: i64,
+) -> Result<(), Error> {
+    let models_json_str: String =
+        transaction.query_row("SELECT models FROM col", [], |row| row.get(0))?;
+    let mut entries: BTreeMap<i64, ModelDbEntry> = serde_json::from_str(&models_json_str)?;
+
+    for model in models {
+        let db_entry = model_to_db_entry(&mut model.clone(), timestamp, deck_id);
+        entries.insert(model.id, db_entry);
+    }
+
+    transaction.execute(
+        "UPDATE col SET models = ?",
+        [serde_json::to_string(&entries)?],
+    )?;
+    Ok(())
+}
+
+/// Read all model entries from the `col.models` JSON
+pub fn read_models_from_db(conn: &Connection) -> Result<Vec<ModelDbEntry>, Error> {
+    let models_json: String = conn.query_row("SELECT models FROM col", [], |row| row.get(0))?;
+    let entries: HashMap<String, ModelDbEntry> = serde_json::from_str(&models_json)?;
+    let mut entries: Vec<ModelDbEntry> = entries.into_values().collect();
+    entries.sort_by_cached_key(|entry| entry.id.parse::<i64>().ok());
+    Ok(entries)
+}
+
+/// Convert a database entry back to a core Model
+pub fn db_entry_to_model(entry: &ModelDbEntry) -> Result<Model, Error> {
+    let id = entry
+        .id
+        .parse::<i64>()
+        .map_err(|_| Error::Validation(format!("Invalid model id: {}", entry.id)))?;
+    let model_type = match entry.model_db_entry_type {
+        1 => ModelType::Cloze,
+        _ => ModelType::Basic,
+    };
+
+    let mut flds = entry.flds.clone();
+    flds.sort_by_key(|f| f.ord);
+    let fields = flds
+        .into_iter()
+        .map(|f| Field {
+            name: f.name,
+            font: Some(f.font),
+            size: Some(f.size),
+            rtl: Some(f.rtl),
+            sticky: Some(f.sticky),
+        })
+        .collect();
+
+    let mut tmpls = entry.tmpls.clone();
+    tmpls.sort_by_key(|t| t.ord);
+    let templates = tmpls
+        .into_iter()
+        .map(|t| Template {
+            name: t.name,
+            qfmt: t.qfmt,
+            afmt: t.afmt,
+        })
+        .collect();
+
+    Ok(Model::with_options(
+        id,
+        &entry.name,
+        fields,
+        templates,
+        Some(&entry.css),
+        Some(model_type),
+        Some(&entry.latex_pre),
+        Some(&entry.latex_post),
+        Some(entry.sortf),
+    ))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -75,4 +157,19 @@ mod tests {
         assert_eq!(entry.id, "123");
         assert_eq!(entry.flds.len(), 2);
     }
+
+    #[test]
+    fn test_db_entry_to_model() {
+        let mut model = Model::new(
+            123,
+            "Test",
+            vec![Field::new("F1"), Field::new("F2")],
+            vec![Template::new("Card 1").qfmt("{{F1}}").afmt("{{F2}}")],
+        );
+        let entry = model_to_db_entry(&mut model, 0.0, 1);
+        let restored = db_entry_to_model(&entry).unwrap();
+        assert_eq!(restored.id, 123);
+        assert_eq!(restored.field_names(), vec!["F1", "F2"]);
+        assert_eq!(restored.templates[0].qfmt, "{{F1}}");
+    }
 }
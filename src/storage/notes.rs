@@ -1,8 +1,9 @@
 //! Note database operations
 
 use crate::core::{Error, Note};
-use rusqlite::{Transaction, params};
-use std::ops::RangeFrom;
+use crate::storage::ids::IdAllocator;
+use rusqlite::types::Value;
+use rusqlite::{Connection, Transaction, params};
 
 /// Write a note to the database
 pub fn write_note_to_db(
@@ -10,11 +11,16 @@ pub fn write_note_to_db(
     transaction: &Transaction,
     timestamp: f64,
     _deck_id: i64,
-    id_gen: &mut RangeFrom<usize>,
+    ids: &mut IdAllocator,
 ) -> Result<i64, Error> {
-    note.check_invalid_html();
+    let note_id = note.id().unwrap_or_else(|| ids.next_id());
 
-    let note_id = id_gen.next().expect("Range overflowed!") as i64;
+    // Anki stores numeric sort fields as integers so they sort numerically
+    let sort_field = note.sort_field();
+    let sfld = match sort_field.parse::<i64>() {
+        Ok(n) => Value::Integer(n),
+        Err(_) => Value::Text(sort_field),
+    };
 
     transaction.execute(
         "INSERT INTO notes VALUES(?,?,?,?,?,?,?,?,?,?,?);",
@@ -26,8 +32,8 @@ pub fn write_note_to_db(
             -1_i64,               // usn
             note.format_tags(),   // tags
             note.format_fields(), // flds
-            false,                // sfld
-            0_i64,                // csum
+            sfld,                 // sfld
+            note.checksum(),      // csum
             0_i64,                // flags
             "",                   // data
         ],
@@ -35,3 +41,30 @@ pub fn write_note_to_db(
 
     Ok(note_id)
 }
+
+/// Read all notes from the database, ordered by id
+pub fn read_notes_from_db(conn: &Connection) -> Result<Vec<NoteDbEntry>, Error> {
+    let mut stmt = conn.prepare("SELECT id, guid, mid, tags, flds FROM notes ORDER BY id")?;
+    let notes = stmt
+        .query_map([], |row| {
+            Ok(NoteDbEntry {
+                id: row.get(0)?,
+                guid: row.get(1)?,
+                mid: row.get(2)?,
+                tags: row.get(3)?,
+                flds: row.get(4)?,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(notes)
+}
+
+/// Database entry for notes
+#[derive(Debug, Clone)]
+pub struct NoteDbEntry {
+    pub id: i64,
+    pub guid: String,
+    pub mid: i64,
+    pub tags: String,
+    pub flds: String,
+}
@@ -0,0 +1,236 @@
+//! Minimal protobuf wire-format support
+//!
+//! Newer Anki files store package metadata, media maps and (in schema 18)
+//! notetype/deck configuration as protobuf messages. Only the handful of
+//! messages genanki-rs needs are encoded, so a small hand-written encoder
+//! is used instead of generated code.
+
+use crate::core::Error;
+
+const WIRE_VARINT: u64 = 0;
+const WIRE_FIXED64: u64 = 1;
+const WIRE_LEN: u64 = 2;
+const WIRE_FIXED32: u64 = 5;
+
+/// Encoder for a single protobuf message
+#[derive(Debug, Default, Clone)]
+pub(crate) struct ProtoWriter {
+    buf: Vec<u8>,
+}
+
+impl ProtoWriter {
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    fn raw_varint(&mut self, mut value: u64) {
+        while value >= 0x80 {
+            self.buf.push((value as u8) | 0x80);
+            value >>= 7;
+        }
+        self.buf.push(value as u8);
+    }
+
+    fn key(&mut self, field: u32, wire_type: u64) {
+        self.raw_varint(((field as u64) << 3) | wire_type);
+    }
+
+    fn len_delimited(&mut self, field: u32, value: &[u8]) {
+        self.key(field, WIRE_LEN);
+        self.raw_varint(value.len() as u64);
+        self.buf.extend_from_slice(value);
+    }
+
+    // Scalar fields follow proto3 semantics: default values are not written.
+
+    /// Write an unsigned varint field (uint32, uint64, enum)
+    pub(crate) fn uint(&mut self, field: u32, value: u64) -> &mut Self {
+        if value != 0 {
+            self.key(field, WIRE_VARINT);
+            self.raw_varint(value);
+        }
+        self
+    }
+
+    /// Write a signed varint field (int32, int64)
+    pub(crate) fn int(&mut self, field: u32, value: i64) -> &mut Self {
+        self.uint(field, value as u64)
+    }
+
+    /// Write a bool field
+    pub(crate) fn bool(&mut self, field: u32, value: bool) -> &mut Self {
+        self.uint(field, value as u64)
+    }
+
+    /// Write a float field
+    pub(crate) fn float(&mut self, field: u32, value: f32) -> &mut Self {
+        if value != 0.0 {
+            self.key(field, WIRE_FIXED32);
+            self.buf.extend_from_slice(&value.to_le_bytes());
+        }
+        self
+    }
+
+    /// Write a packed repeated float field
+    pub(crate) fn packed_floats(&mut self, field: u32, values: &[f32]) -> &mut Self {
+        let bytes: Vec<u8> = values.iter().flat_map(|v| v.to_le_bytes()).collect();
+        self.bytes(field, &bytes)
+    }
+
+    /// Write a packed repeated unsigned varint field
+    pub(crate) fn packed_uints(&mut self, field: u32, values: &[u64]) -> &mut Self {
+        let mut packed = ProtoWriter::new();
+        values.iter().for_each(|&v| packed.raw_varint(v));
+        self.bytes(field, &packed.buf)
+    }
+
+    /// Write a bytes field
+    pub(crate) fn bytes(&mut self, field: u32, value: &[u8]) -> &mut Self {
+        if !value.is_empty() {
+            self.len_delimited(field, value);
+        }
+        self
+    }
+
+    /// Write a string field
+    pub(crate) fn string(&mut self, field: u32, value: &str) -> &mut Self {
+        self.bytes(field, value.as_bytes())
+    }
+
+    /// Write a nested message field, even if it is empty
+    pub(crate) fn message(&mut self, field: u32, message: &ProtoWriter) -> &mut Self {
+        self.len_delimited(field, &message.buf);
+        self
+    }
+
+    /// Get the encoded message
+    pub(crate) fn into_bytes(self) -> Vec<u8> {
+        self.buf
+    }
+}
+
+/// A decoded protobuf field value
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum ProtoValue<'a> {
+    Varint(u64),
+    Fixed64(u64),
+    Bytes(&'a [u8]),
+    Fixed32(u32),
+}
+
+/// Decoder iterating over the fields of a protobuf message
+pub(crate) struct ProtoReader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> ProtoReader<'a> {
+    pub(crate) fn new(buf: &'a [u8]) -> Self {
+        Self { buf, pos: 0 }
+    }
+
+    fn raw_varint(&mut self) -> Result<u64, Error> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = *self.buf.get(self.pos).ok_or_else(truncated)?;
+            self.pos += 1;
+            value |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(Error::Validation("Invalid protobuf varint".to_string()))
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], Error> {
+        let end = self.pos.checked_add(len).ok_or_else(truncated)?;
+        let slice = self.buf.get(self.pos..end).ok_or_else(truncated)?;
+        self.pos = end;
+        Ok(slice)
+    }
+
+    /// Read the next field number and value
+    pub(crate) fn next_field(&mut self) -> Result<Option<(u32, ProtoValue<'a>)>, Error> {
+        if self.pos >= self.buf.len() {
+            return Ok(None);
+        }
+        let key = self.raw_varint()?;
+        let field = (key >> 3) as u32;
+        let value = match key & 0x7 {
+            WIRE_VARINT => ProtoValue::Varint(self.raw_varint()?),
+            WIRE_FIXED64 => {
+                ProtoValue::Fixed64(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+            }
+            WIRE_LEN => {
+                let len = self.raw_varint()? as usize;
+                ProtoValue::Bytes(self.take(len)?)
+            }
+            WIRE_FIXED32 => {
+                ProtoValue::Fixed32(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+            }
+            other => {
+                return Err(Error::Validation(format!(
+                    "Unsupported protobuf wire type {other}"
+                )));
+            }
+        };
+        Ok(Some((field, value)))
+    }
+}
+
+fn truncated() -> Error {
+    Error::Validation("Truncated protobuf message".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let mut nested = ProtoWriter::new();
+        nested.string(1, "name");
+        let mut writer = ProtoWriter::new();
+        writer.uint(1, 300).message(2, &nested);
+        let bytes = writer.into_bytes();
+
+        let mut reader = ProtoReader::new(&bytes);
+        assert_eq!(
+            reader.next_field().unwrap(),
+            Some((1, ProtoValue::Varint(300)))
+        );
+        let Some((2, ProtoValue::Bytes(inner))) = reader.next_field().unwrap() else {
+            panic!("expected nested message");
+        };
+        assert_eq!(
+            ProtoReader::new(inner).next_field().unwrap(),
+            Some((1, ProtoValue::Bytes(b"name")))
+        );
+        assert_eq!(reader.next_field().unwrap(), None);
+    }
+
+    #[test]
+    fn test_defaults_are_skipped() {
+        let mut writer = ProtoWriter::new();
+        writer
+            .uint(1, 0)
+            .bool(2, false)
+            .float(3, 0.0)
+            .string(4, "")
+            .message(5, &ProtoWriter::new());
+        assert_eq!(writer.into_bytes(), vec![0x2a, 0x00]);
+    }
+
+    #[test]
+    fn test_packed_floats() {
+        let mut writer = ProtoWriter::new();
+        writer.packed_floats(1, &[1.0, 10.0]);
+        let bytes = writer.into_bytes();
+        let Some((1, ProtoValue::Bytes(packed))) = ProtoReader::new(&bytes).next_field().unwrap()
+        else {
+            panic!("expected packed field");
+        };
+        assert_eq!(packed.len(), 8);
+        assert_eq!(&packed[4..], &10.0f32.to_le_bytes());
+    }
+}
@@ -0,0 +1,85 @@
+//! Review log database operations
+
+use crate::core::{Error, Rating, ReviewKind, RevlogEntry, RevlogInterval};
+use crate::storage::ids::IdAllocator;
+use rusqlite::{Connection, Transaction, params};
+use std::time::{Duration, SystemTime};
+
+/// Write a card's past review to the database
+///
+/// The entry ID is the review time in milliseconds, bumped by one when
+/// another review already uses it.
+pub fn write_revlog_to_db(
+    entry: &RevlogEntry,
+    transaction: &Transaction,
+    card_id: i64,
+    ids: &mut IdAllocator,
+) -> Result<(), Error> {
+    let millis = entry
+        .time
+        .duration_since(SystemTime::UNIX_EPOCH)?
+        .as_millis() as i64;
+    transaction.execute(
+        "INSERT INTO revlog VALUES(?,?,?,?,?,?,?,?,?);",
+        params![
+            ids.claim(millis),                 // id
+            card_id,                           // cid
+            -1_i64,                            // usn
+            entry.rating.to_i64(),             // ease
+            entry.interval.to_i64(),           // ivl
+            entry.last_interval.to_i64(),      // lastIvl
+            entry.ease_factor,                 // factor
+            entry.duration.as_millis() as i64, // time
+            entry.kind.to_i64(),               // type
+        ],
+    )?;
+    Ok(())
+}
+
+/// Read all review log entries from the database, ordered by card and time
+pub fn read_revlog_from_db(conn: &Connection) -> Result<Vec<RevlogDbEntry>, Error> {
+    let mut stmt = conn.prepare(
+        "SELECT id, cid, ease, ivl, lastIvl, factor, time, type FROM revlog ORDER BY cid, id",
+    )?;
+    let entries = stmt
+        .query_map([], |row| {
+            Ok(RevlogDbEntry {
+                id: row.get(0)?,
+                cid: row.get(1)?,
+                ease: row.get(2)?,
+                ivl: row.get(3)?,
+                last_ivl: row.get(4)?,
+                factor: row.get(5)?,
+                time: row.get(6)?,
+                revlog_type: row.get(7)?,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(entries)
+}
+
+/// Convert a database entry back to a review
+pub fn db_entry_to_revlog(entry: &RevlogDbEntry) -> RevlogEntry {
+    RevlogEntry {
+        time: SystemTime::UNIX_EPOCH + Duration::from_millis(entry.id.max(0) as u64),
+        rating: Rating::from_i64(entry.ease),
+        kind: ReviewKind::from_i64(entry.revlog_type),
+        interval: RevlogInterval::from_i64(entry.ivl),
+        last_interval: RevlogInterval::from_i64(entry.last_ivl),
+        ease_factor: entry.factor.max(0) as u32,
+        duration: Duration::from_millis(entry.time.max(0) as u64),
+    }
+}
+
+/// Database entry for the review log
+#[derive(Debug, Clone)]
+pub struct RevlogDbEntry {
+    pub id: i64,
+    pub cid: i64,
+    pub ease: i64,
+    pub ivl: i64,
+    pub last_ivl: i64,
+    pub factor: i64,
+    pub time: i64,
+    pub revlog_type: i64,
+}
@@ -4,7 +4,7 @@
 //! and the default collection data.
 
 use crate::core::Error;
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
 
 /// Anki database schema
 pub static SCHEMA_SQL: &str = include_str!("../../sql/schema.sql");
@@ -12,6 +12,43 @@ pub static SCHEMA_SQL: &str = include_str!("../../sql/schema.sql");
 /// Default collection data SQL
 pub static COL_SQL: &str = include_str!("../../sql/default_inject.sql");
 
+/// Anki database schema 18, with normalized notetypes, decks and config
+pub static SCHEMA_V18_SQL: &str = include_str!("../../sql/schema_v18.sql");
+
+/// Default collection data SQL for schema 18
+pub static COL_V18_SQL: &str = include_str!("../../sql/default_inject_v18.sql");
+
+/// Version of the collection schema
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SchemaVersion {
+    /// Models, decks and options stored as JSON in the `col` row
+    #[default]
+    V11,
+    /// Notetypes, decks and options stored in dedicated tables, as current Anki writes them
+    V18,
+}
+
+impl SchemaVersion {
+    /// The `col.ver` value for this schema
+    pub fn ver(self) -> i64 {
+        match self {
+            SchemaVersion::V11 => 11,
+            SchemaVersion::V18 => 18,
+        }
+    }
+
+    /// Layout used by a collection with the given `col.ver`
+    ///
+    /// Notetypes and decks moved out of the `col` row in schema 15.
+    pub fn from_ver(ver: i64) -> Self {
+        if ver >= 15 {
+            SchemaVersion::V18
+        } else {
+            SchemaVersion::V11
+        }
+    }
+}
+
 /// Database schema representation
 #[derive(Debug, Clone)]
 pub struct AnkiSchema;
@@ -29,14 +66,38 @@ impl AnkiSchema {
 
     /// Initialize a database with the Anki schema
     pub fn init_db(conn: &mut rusqlite::Connection) -> Result<(), Error> {
-        conn.execute_batch(SCHEMA_SQL)?;
-        conn.execute_batch(COL_SQL)?;
+        Self::init_db_version(conn, SchemaVersion::V11)
+    }
+
+    /// Initialize a database with the given Anki schema version
+    pub fn init_db_version(
+        conn: &mut rusqlite::Connection,
+        version: SchemaVersion,
+    ) -> Result<(), Error> {
+        match version {
+            SchemaVersion::V11 => {
+                conn.execute_batch(SCHEMA_SQL)?;
+                conn.execute_batch(COL_SQL)?;
+            }
+            SchemaVersion::V18 => {
+                conn.execute_batch(SCHEMA_V18_SQL)?;
+                conn.execute_batch(COL_V18_SQL)?;
+                crate::storage::schema18::write_defaults(conn)?;
+            }
+        }
         Ok(())
     }
+
+    /// Read the schema version of an existing database
+    pub fn version(conn: &rusqlite::Connection) -> Result<SchemaVersion, Error> {
+        let ver: i64 = conn.query_row("SELECT ver FROM col", [], |row| row.get(0))?;
+        Ok(SchemaVersion::from_ver(ver))
+    }
 }
 
 /// Database entry for decks
 #[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
 pub struct DeckDbEntry {
     pub collapsed: bool,
     pub conf: i64,
@@ -83,27 +144,179 @@ impl Default for DeckDbEntry {
     }
 }
 
+/// Database entry for deck options groups (`dconf`)
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct DeckConfigDbEntry {
+    pub id: i64,
+    pub name: String,
+    #[serde(rename = "mod")]
+    pub deck_config_db_entry_mod: i64,
+    pub usn: i64,
+    pub autoplay: bool,
+    pub replayq: bool,
+    pub timer: i64,
+    #[serde(rename = "maxTaken")]
+    pub max_taken: i64,
+    #[serde(rename = "buryInterdayLearning")]
+    pub bury_interday_learning: bool,
+    /// FSRS-4.5 parameters
+    #[serde(rename = "fsrsWeights")]
+    pub fsrs_weights: Vec<f32>,
+    #[serde(rename = "fsrsParams5")]
+    pub fsrs_params_5: Vec<f32>,
+    #[serde(rename = "fsrsParams6")]
+    pub fsrs_params_6: Vec<f32>,
+    #[serde(rename = "desiredRetention")]
+    pub desired_retention: f32,
+    pub new: NewConfigDbEntry,
+    pub rev: RevConfigDbEntry,
+    pub lapse: LapseConfigDbEntry,
+}
+
+impl Default for DeckConfigDbEntry {
+    fn default() -> Self {
+        Self {
+            id: 1,
+            name: "Default".to_string(),
+            deck_config_db_entry_mod: 0,
+            usn: 0,
+            autoplay: true,
+            replayq: true,
+            timer: 0,
+            max_taken: 60,
+            bury_interday_learning: false,
+            fsrs_weights: vec![],
+            fsrs_params_5: vec![],
+            fsrs_params_6: vec![],
+            desired_retention: 0.9,
+            new: NewConfigDbEntry::default(),
+            rev: RevConfigDbEntry::default(),
+            lapse: LapseConfigDbEntry::default(),
+        }
+    }
+}
+
+/// New card settings of a deck options group
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct NewConfigDbEntry {
+    pub bury: bool,
+    pub delays: Vec<f32>,
+    #[serde(rename = "initialFactor")]
+    pub initial_factor: i64,
+    /// Graduating, easy and (unused) third interval, in days
+    pub ints: Vec<i64>,
+    pub order: i64,
+    #[serde(rename = "perDay")]
+    pub per_day: i64,
+    pub separate: bool,
+}
+
+impl Default for NewConfigDbEntry {
+    fn default() -> Self {
+        Self {
+            bury: true,
+            delays: vec![1.0, 10.0],
+            initial_factor: 2500,
+            ints: vec![1, 4, 7],
+            order: 1,
+            per_day: 20,
+            separate: true,
+        }
+    }
+}
+
+/// Review settings of a deck options group
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct RevConfigDbEntry {
+    pub bury: bool,
+    pub ease4: f32,
+    #[serde(rename = "hardFactor")]
+    pub hard_factor: f32,
+    pub fuzz: f32,
+    #[serde(rename = "ivlFct")]
+    pub ivl_fct: f32,
+    #[serde(rename = "maxIvl")]
+    pub max_ivl: i64,
+    #[serde(rename = "minSpace")]
+    pub min_space: i64,
+    #[serde(rename = "perDay")]
+    pub per_day: i64,
+}
+
+impl Default for RevConfigDbEntry {
+    fn default() -> Self {
+        Self {
+            bury: true,
+            ease4: 1.3,
+            hard_factor: 1.2,
+            fuzz: 0.05,
+            ivl_fct: 1.0,
+            max_ivl: 36500,
+            min_space: 1,
+            per_day: 100,
+        }
+    }
+}
+
+/// Lapse settings of a deck options group
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct LapseConfigDbEntry {
+    pub delays: Vec<f32>,
+    #[serde(rename = "leechAction")]
+    pub leech_action: i64,
+    #[serde(rename = "leechFails")]
+    pub leech_fails: i64,
+    #[serde(rename = "minInt")]
+    pub min_int: i64,
+    pub mult: f32,
+}
+
+impl Default for LapseConfigDbEntry {
+    fn default() -> Self {
+        Self {
+            delays: vec![10.0],
+            leech_action: 1,
+            leech_fails: 8,
+            min_int: 1,
+            mult: 0.0,
+        }
+    }
+}
+
 /// Database entry for models
 #[derive(Serialize, Deserialize, Clone)]
 pub struct ModelDbEntry {
+    #[serde(default)]
     pub vers: Vec<Option<serde_json::Value>>,
     pub name: String,
+    #[serde(default)]
     pub tags: Vec<Option<serde_json::Value>>,
+    #[serde(default, deserialize_with = "null_as_default")]
     pub did: i64,
+    #[serde(default)]
     pub usn: i64,
+    #[serde(default)]
     pub req: Vec<(usize, String, Vec<usize>)>,
     pub flds: Vec<FieldDbEntry>,
+    #[serde(default)]
     pub sortf: i64,
     pub tmpls: Vec<TemplateDbEntry>,
-    #[serde(rename = "mod")]
+    #[serde(rename = "mod", default)]
     pub model_db_entry_mod: i64,
-    #[serde(rename = "latexPost")]
+    #[serde(rename = "latexPost", default)]
     pub latex_post: String,
-    #[serde(rename = "type")]
+    #[serde(rename = "type", default)]
     pub model_db_entry_type: i64,
+    /// Anki writes the id as a number, genanki-rs historically as a string
+    #[serde(deserialize_with = "string_or_number")]
     pub id: String,
+    #[serde(default)]
     pub css: String,
-    #[serde(rename = "latexPre")]
+    #[serde(rename = "latexPre", default)]
     pub latex_pre: String,
 }
 
@@ -111,11 +324,16 @@ pub struct ModelDbEntry {
 #[derive(Serialize, Deserialize, Clone)]
 pub struct FieldDbEntry {
     pub name: String,
+    #[serde(default)]
     pub media: Vec<Option<serde_json::Value>>,
+    #[serde(default)]
     pub sticky: bool,
+    #[serde(default)]
     pub rtl: bool,
     pub ord: i64,
+    #[serde(default)]
     pub font: String,
+    #[serde(default)]
     pub size: i64,
 }
 
@@ -124,13 +342,39 @@ pub struct FieldDbEntry {
 pub struct TemplateDbEntry {
     pub name: String,
     pub qfmt: String,
+    #[serde(default)]
     pub did: Option<usize>,
+    #[serde(default)]
     pub bafmt: String,
     pub afmt: String,
     pub ord: i64,
+    #[serde(default)]
     pub bqfmt: String,
 }
 
+/// Deserialize a value that may be `null` into its default
+fn null_as_default<'de, D, T>(deserializer: D) -> std::result::Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Default + Deserialize<'de>,
+{
+    Ok(Option::<T>::deserialize(deserializer)?.unwrap_or_default())
+}
+
+/// Deserialize an id that may be stored either as a JSON string or a number
+fn string_or_number<'de, D>(deserializer: D) -> std::result::Result<String, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    match serde_json::Value::deserialize(deserializer)? {
+        serde_json::Value::String(s) => Ok(s),
+        serde_json::Value::Number(n) => Ok(n.to_string()),
+        other => Err(serde::de::Error::custom(format!(
+            "expected string or number, got {other}"
+        ))),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -139,6 +383,14 @@ mod tests {
     fn test_schema_not_empty() {
         assert!(!SCHEMA_SQL.is_empty());
         assert!(!COL_SQL.is_empty());
+        assert!(!SCHEMA_V18_SQL.is_empty());
+        assert!(!COL_V18_SQL.is_empty());
+    }
+
+    #[test]
+    fn test_schema_version_from_ver() {
+        assert_eq!(SchemaVersion::from_ver(11), SchemaVersion::V11);
+        assert_eq!(SchemaVersion::from_ver(18), SchemaVersion::V18);
     }
 
     #[test]
@@ -147,4 +399,22 @@ mod tests {
         assert_eq!(entry.conf, 1);
         assert_eq!(entry.usn, -1);
     }
+
+    #[test]
+    fn test_deck_config_db_entry_partial() {
+        let json = r#"{"id":5,"name":"Quick","new":{"perDay":50},"lapse":{"leechAction":0}}"#;
+        let entry: DeckConfigDbEntry = serde_json::from_str(json).unwrap();
+        assert_eq!(entry.new.per_day, 50);
+        assert_eq!(entry.new.delays, vec![1.0, 10.0]);
+        assert_eq!(entry.lapse.leech_action, 0);
+        assert_eq!(entry.rev.per_day, 100);
+    }
+
+    #[test]
+    fn test_model_db_entry_numeric_id() {
+        let json = r#"{"name":"Basic","id":1342697561419,"did":null,"flds":[],"tmpls":[]}"#;
+        let entry: ModelDbEntry = serde_json::from_str(json).unwrap();
+        assert_eq!(entry.id, "1342697561419");
+        assert_eq!(entry.did, 0);
+    }
 }
@@ -0,0 +1,719 @@
+//! Schema 18 storage backend
+//!
+//! Current Anki versions keep notetypes, fields, templates, decks and deck
+//! options in dedicated tables, with their configuration encoded as protobuf
+//! blobs, instead of JSON in the `col` row. This module writes and reads that
+//! layout from the same `ModelDbEntry`/`DeckDbEntry` values the schema 11
+//! backend uses.
+
+use crate::core::{Deck, DeckOptions, Error, Model};
+use crate::storage::protobuf::{ProtoReader, ProtoValue, ProtoWriter};
+use crate::storage::schema::{
+    DeckConfigDbEntry, DeckDbEntry, FieldDbEntry, ModelDbEntry, TemplateDbEntry,
+};
+use crate::storage::{decks, models};
+use rusqlite::{Connection, Transaction, params};
+
+/// Separator between deck name components in schema 18
+const DECK_NAME_SEPARATOR: char = '\x1f';
+
+/// Id of the default deck and options group
+const DEFAULT_ID: i64 = 1;
+
+/// Write the default deck and options group every collection contains
+pub fn write_defaults(conn: &Connection) -> Result<(), Error> {
+    let default_deck = DeckDbEntry {
+        id: DEFAULT_ID,
+        name: "Default".to_string(),
+        conf: DEFAULT_ID,
+        usn: 0,
+        ..Default::default()
+    };
+    write_deck_entry(&default_deck, conn, 0)?;
+    write_deck_config_entry(&DeckConfigDbEntry::default(), conn, 0)
+}
+
+/// Write a deck to the `decks` table
+pub fn write_deck_to_db(
+    deck: &Deck,
+    transaction: &Transaction,
+    timestamp: f64,
+) -> Result<(), Error> {
+    write_deck_entry(
+        &decks::deck_to_db_entry(deck),
+        transaction,
+        mtime_secs(timestamp),
+    )
+}
+
+/// Write a deck options group to the `deck_config` table
+pub fn write_deck_options_to_db(
+    options: &DeckOptions,
+    transaction: &Transaction,
+    timestamp: f64,
+) -> Result<(), Error> {
+    write_deck_config_entry(
+        &decks::options_to_db_entry(options),
+        transaction,
+        mtime_secs(timestamp),
+    )
+}
+
+/// Write a model to the `notetypes`, `fields` and `templates` tables
+pub fn write_model_to_db(
+    model: &Model,
+    transaction: &Transaction,
+    timestamp: f64,
+    deck_id: i64,
+) -> Result<(), Error> {
+    let entry = models::model_to_db_entry(&mut model.clone(), timestamp, deck_id);
+    let mtime = mtime_secs(timestamp);
+
+    transaction.execute("DELETE FROM notetypes WHERE id = ?", params![model.id])?;
+    let name = unique_name(transaction, "notetypes", &entry.name)?;
+    transaction.execute(
+        "INSERT INTO notetypes VALUES(?,?,?,?,?);",
+        params![
+            model.id,
+            name,
+            mtime,
+            entry.usn,
+            notetype_config(&entry).into_bytes()
+        ],
+    )?;
+
+    transaction.execute("DELETE FROM fields WHERE ntid = ?", params![model.id])?;
+    for field in &entry.flds {
+        transaction.execute(
+            "INSERT INTO fields VALUES(?,?,?,?);",
+            params![
+                model.id,
+                field.ord,
+                field.name,
+                field_config(field).into_bytes()
+            ],
+        )?;
+    }
+
+    transaction.execute("DELETE FROM templates WHERE ntid = ?", params![model.id])?;
+    for template in &entry.tmpls {
+        transaction.execute(
+            "INSERT INTO templates VALUES(?,?,?,?,?,?);",
+            params![
+                model.id,
+                template.ord,
+                template.name,
+                mtime,
+                entry.usn,
+                template_config(template).into_bytes()
+            ],
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Register tags in the `tags` table
+pub fn write_tags_to_db(tags: &[String], transaction: &Transaction) -> Result<(), Error> {
+    for tag in tags {
+        transaction.execute(
+            "INSERT OR IGNORE INTO tags VALUES(?,?,?,NULL);",
+            params![tag, -1_i64, false],
+        )?;
+    }
+    Ok(())
+}
+
+/// Read all notetypes as model database entries
+pub fn read_models_from_db(conn: &Connection) -> Result<Vec<ModelDbEntry>, Error> {
+    let mut fields_stmt =
+        conn.prepare("SELECT ord, name, config FROM fields WHERE ntid = ? ORDER BY ord")?;
+    let mut templates_stmt =
+        conn.prepare("SELECT ord, name, config FROM templates WHERE ntid = ? ORDER BY ord")?;
+    let mut stmt =
+        conn.prepare("SELECT id, name, mtime_secs, usn, config FROM notetypes ORDER BY id")?;
+    let rows = stmt
+        .query_map([], |row| {
+            Ok((
+                row.get::<_, i64>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, i64>(2)?,
+                row.get::<_, i64>(3)?,
+                row.get::<_, Vec<u8>>(4)?,
+            ))
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut entries = Vec::with_capacity(rows.len());
+    for (id, name, mtime, usn, config) in rows {
+        let flds = fields_stmt
+            .query_map(params![id], |row| {
+                Ok((
+                    row.get::<_, i64>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, Vec<u8>>(2)?,
+                ))
+            })?
+            .collect::<Result<Vec<_>, _>>()?
+            .into_iter()
+            .map(|(ord, name, config)| read_field_config(ord, name, &config))
+            .collect::<Result<Vec<_>, _>>()?;
+        let tmpls = templates_stmt
+            .query_map(params![id], |row| {
+                Ok((
+                    row.get::<_, i64>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, Vec<u8>>(2)?,
+                ))
+            })?
+            .collect::<Result<Vec<_>, _>>()?
+            .into_iter()
+            .map(|(ord, name, config)| read_template_config(ord, name, &config))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut entry = ModelDbEntry {
+            vers: vec![],
+            name,
+            tags: vec![],
+            did: 0,
+            usn,
+            req: vec![],
+            flds,
+            sortf: 0,
+            tmpls,
+            model_db_entry_mod: mtime,
+            latex_post: String::new(),
+            model_db_entry_type: 0,
+            id: id.to_string(),
+            css: String::new(),
+            latex_pre: String::new(),
+        };
+        read_notetype_config(&mut entry, &config)?;
+        entries.push(entry);
+    }
+    Ok(entries)
+}
+
+/// Read all decks as deck database entries
+pub fn read_decks_from_db(conn: &Connection) -> Result<Vec<DeckDbEntry>, Error> {
+    let mut stmt =
+        conn.prepare("SELECT id, name, mtime_secs, usn, common, kind FROM decks ORDER BY id")?;
+    let rows = stmt
+        .query_map([], |row| {
+            Ok((
+                row.get::<_, i64>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, i64>(2)?,
+                row.get::<_, i64>(3)?,
+                row.get::<_, Vec<u8>>(4)?,
+                row.get::<_, Vec<u8>>(5)?,
+            ))
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut entries = Vec::with_capacity(rows.len());
+    for (id, name, mtime, usn, common, kind) in rows {
+        let mut entry = DeckDbEntry {
+            id,
+            name: name.replace(DECK_NAME_SEPARATOR, "::"),
+            deck_db_entry_mod: mtime,
+            usn,
+            ..Default::default()
+        };
+        read_deck_common(&mut entry, &common)?;
+        read_deck_kind(&mut entry, &kind)?;
+        entries.push(entry);
+    }
+    Ok(entries)
+}
+
+/// Read all deck options groups as `dconf` entries
+pub fn read_deck_options_from_db(conn: &Connection) -> Result<Vec<DeckConfigDbEntry>, Error> {
+    let mut stmt =
+        conn.prepare("SELECT id, name, mtime_secs, usn, config FROM deck_config ORDER BY id")?;
+    let rows = stmt
+        .query_map([], |row| {
+            Ok((
+                row.get::<_, i64>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, i64>(2)?,
+                row.get::<_, i64>(3)?,
+                row.get::<_, Vec<u8>>(4)?,
+            ))
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut entries = Vec::with_capacity(rows.len());
+    for (id, name, mtime, usn, config) in rows {
+        let mut entry = DeckConfigDbEntry {
+            id,
+            name,
+            deck_config_db_entry_mod: mtime,
+            usn,
+            ..Default::default()
+        };
+        read_deck_config(&mut entry, &config)?;
+        entries.push(entry);
+    }
+    Ok(entries)
+}
+
+fn mtime_secs(timestamp: f64) -> i64 {
+    (timestamp / 1000.0) as i64
+}
+
+/// Append "+" to `name` until no row of `table` uses it, as Anki does
+///
+/// Notetype and deck names are unique in schema 18, so a plain insert of a
+/// second entry with the same name would fail.
+fn unique_name(conn: &Connection, table: &str, name: &str) -> Result<String, Error> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT EXISTS(SELECT 1 FROM {table} WHERE name = ?)"
+    ))?;
+    let mut name = name.to_string();
+    while stmt.query_row(params![name], |row| row.get::<_, bool>(0))? {
+        name.push('+');
+    }
+    Ok(name)
+}
+
+fn write_deck_entry(entry: &DeckDbEntry, conn: &Connection, mtime: i64) -> Result<(), Error> {
+    let mut common = ProtoWriter::new();
+    common.bool(1, entry.collapsed);
+
+    let mut normal = ProtoWriter::new();
+    normal
+        .int(1, entry.conf)
+        .uint(2, entry.extend_new as u64)
+        .uint(3, entry.extend_rev as u64)
+        .string(4, &entry.desc);
+    let mut kind = ProtoWriter::new();
+    kind.message(1, &normal);
+
+    conn.execute("DELETE FROM decks WHERE id = ?", params![entry.id])?;
+    let name = unique_name(
+        conn,
+        "decks",
+        &entry.name.replace("::", &DECK_NAME_SEPARATOR.to_string()),
+    )?;
+    conn.execute(
+        "INSERT INTO decks VALUES(?,?,?,?,?,?);",
+        params![
+            entry.id,
+            name,
+            mtime,
+            entry.usn,
+            common.into_bytes(),
+            kind.into_bytes()
+        ],
+    )?;
+    Ok(())
+}
+
+fn notetype_config(entry: &ModelDbEntry) -> ProtoWriter {
+    let mut config = ProtoWriter::new();
+    config
+        .uint(1, entry.model_db_entry_type as u64)
+        .uint(2, entry.sortf as u64)
+        .string(3, &entry.css)
+        .string(5, &entry.latex_pre)
+        .string(6, &entry.latex_post);
+    for (card_ord, kind, field_ords) in &entry.req {
+        let kind = match kind.as_str() {
+            "any" => 1,
+            "all" => 2,
+            _ => 0,
+        };
+        let field_ords: Vec<u64> = field_ords.iter().map(|&ord| ord as u64).collect();
+        let mut requirement = ProtoWriter::new();
+        requirement
+            .uint(1, *card_ord as u64)
+            .uint(2, kind)
+            .packed_uints(3, &field_ords);
+        config.message(8, &requirement);
+    }
+    config
+}
+
+fn field_config(field: &FieldDbEntry) -> ProtoWriter {
+    let mut config = ProtoWriter::new();
+    config
+        .bool(1, field.sticky)
+        .bool(2, field.rtl)
+        .string(3, &field.font)
+        .uint(4, field.size as u64);
+    config
+}
+
+fn template_config(template: &TemplateDbEntry) -> ProtoWriter {
+    let mut config = ProtoWriter::new();
+    config
+        .string(1, &template.qfmt)
+        .string(2, &template.afmt)
+        .string(3, &template.bqfmt)
+        .string(4, &template.bafmt)
+        .uint(5, template.did.unwrap_or_default() as u64);
+    config
+}
+
+fn write_deck_config_entry(
+    entry: &DeckConfigDbEntry,
+    conn: &Connection,
+    mtime: i64,
+) -> Result<(), Error> {
+    conn.execute(
+        "INSERT OR REPLACE INTO deck_config VALUES(?,?,?,?,?);",
+        params![
+            entry.id,
+            entry.name,
+            mtime,
+            entry.usn,
+            deck_config(entry).into_bytes()
+        ],
+    )?;
+    Ok(())
+}
+
+/// `DeckConfig.Config` message for a `dconf` entry
+fn deck_config(entry: &DeckConfigDbEntry) -> ProtoWriter {
+    let interval = |index: usize| entry.new.ints.get(index).copied().unwrap_or_default();
+    let mut config = ProtoWriter::new();
+    config
+        .packed_floats(1, &entry.new.delays)
+        .packed_floats(2, &entry.lapse.delays)
+        .packed_floats(3, &entry.fsrs_weights)
+        .packed_floats(5, &entry.fsrs_params_5)
+        .packed_floats(6, &entry.fsrs_params_6)
+        .uint(9, entry.new.per_day as u64)
+        .uint(10, entry.rev.per_day as u64)
+        .float(11, entry.new.initial_factor as f32 / 1000.0)
+        .float(12, entry.rev.ease4)
+        .float(13, entry.rev.hard_factor)
+        .float(14, entry.lapse.mult)
+        .float(15, entry.rev.ivl_fct)
+        .uint(16, entry.rev.max_ivl as u64)
+        .uint(17, entry.lapse.min_int as u64)
+        .uint(18, interval(0) as u64)
+        .uint(19, interval(1) as u64)
+        .uint(21, entry.lapse.leech_action as u64)
+        .uint(22, entry.lapse.leech_fails as u64)
+        .bool(23, !entry.autoplay)
+        .uint(24, entry.max_taken as u64)
+        .bool(25, entry.timer != 0)
+        .bool(26, !entry.replayq)
+        .bool(27, entry.new.bury)
+        .bool(28, entry.rev.bury)
+        .bool(29, entry.bury_interday_learning)
+        .float(37, entry.desired_retention);
+    config
+}
+
+fn string(bytes: &[u8]) -> String {
+    String::from_utf8_lossy(bytes).into_owned()
+}
+
+fn read_notetype_config(entry: &mut ModelDbEntry, config: &[u8]) -> Result<(), Error> {
+    let mut reader = ProtoReader::new(config);
+    while let Some((field, value)) = reader.next_field()? {
+        match (field, value) {
+            (1, ProtoValue::Varint(kind)) => entry.model_db_entry_type = kind as i64,
+            (2, ProtoValue::Varint(sortf)) => entry.sortf = sortf as i64,
+            (3, ProtoValue::Bytes(css)) => entry.css = string(css),
+            (5, ProtoValue::Bytes(latex_pre)) => entry.latex_pre = string(latex_pre),
+            (6, ProtoValue::Bytes(latex_post)) => entry.latex_post = string(latex_post),
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+fn read_field_config(ord: i64, name: String, config: &[u8]) -> Result<FieldDbEntry, Error> {
+    let mut field = FieldDbEntry {
+        name,
+        media: vec![],
+        sticky: false,
+        rtl: false,
+        ord,
+        font: String::new(),
+        size: 0,
+    };
+    let mut reader = ProtoReader::new(config);
+    while let Some((number, value)) = reader.next_field()? {
+        match (number, value) {
+            (1, ProtoValue::Varint(sticky)) => field.sticky = sticky != 0,
+            (2, ProtoValue::Varint(rtl)) => field.rtl = rtl != 0,
+            (3, ProtoValue::Bytes(font)) => field.font = string(font),
+            (4, ProtoValue::Varint(size)) => field.size = size as i64,
+            _ => {}
+        }
+    }
+    Ok(field)
+}
+
+fn read_template_config(ord: i64, name: String, config: &[u8]) -> Result<TemplateDbEntry, Error> {
+    let mut template = TemplateDbEntry {
+        name,
+        qfmt: String::new(),
+        did: None,
+        bafmt: String::new(),
+        afmt: String::new(),
+        ord,
+        bqfmt: String::new(),
+    };
+    let mut reader = ProtoReader::new(config);
+    while let Some((field, value)) = reader.next_field()? {
+        match (field, value) {
+            (1, ProtoValue::Bytes(qfmt)) => template.qfmt = string(qfmt),
+            (2, ProtoValue::Bytes(afmt)) => template.afmt = string(afmt),
+            (3, ProtoValue::Bytes(bqfmt)) => template.bqfmt = string(bqfmt),
+            (4, ProtoValue::Bytes(bafmt)) => template.bafmt = string(bafmt),
+            (5, ProtoValue::Varint(did)) if did != 0 => template.did = Some(did as usize),
+            _ => {}
+        }
+    }
+    Ok(template)
+}
+
+fn packed_floats(bytes: &[u8]) -> Vec<f32> {
+    bytes
+        .chunks_exact(4)
+        .map(|chunk| f32::from_le_bytes(chunk.try_into().unwrap()))
+        .collect()
+}
+
+fn read_deck_config(entry: &mut DeckConfigDbEntry, config: &[u8]) -> Result<(), Error> {
+    // Start from proto3 defaults, since default values are not written
+    entry.new.delays.clear();
+    entry.lapse.delays.clear();
+    entry.new.ints = vec![0, 0, 7];
+    entry.autoplay = true;
+    entry.replayq = true;
+    entry.new.bury = false;
+    entry.rev.bury = false;
+    entry.lapse.min_int = 0;
+    entry.lapse.leech_action = 0;
+    entry.lapse.leech_fails = 0;
+
+    let mut reader = ProtoReader::new(config);
+    while let Some((field, value)) = reader.next_field()? {
+        match (field, value) {
+            (1, ProtoValue::Bytes(steps)) => entry.new.delays = packed_floats(steps),
+            (2, ProtoValue::Bytes(steps)) => entry.lapse.delays = packed_floats(steps),
+            (9, ProtoValue::Varint(limit)) => entry.new.per_day = limit as i64,
+            (10, ProtoValue::Varint(limit)) => entry.rev.per_day = limit as i64,
+            (11, ProtoValue::Fixed32(ease)) => {
+                entry.new.initial_factor = (f32::from_bits(ease) * 1000.0).round() as i64;
+            }
+            (12, ProtoValue::Fixed32(bonus)) => entry.rev.ease4 = f32::from_bits(bonus),
+            (13, ProtoValue::Fixed32(hard)) => entry.rev.hard_factor = f32::from_bits(hard),
+            (14, ProtoValue::Fixed32(mult)) => entry.lapse.mult = f32::from_bits(mult),
+            (15, ProtoValue::Fixed32(factor)) => entry.rev.ivl_fct = f32::from_bits(factor),
+            (16, ProtoValue::Varint(days)) => entry.rev.max_ivl = days as i64,
+            (17, ProtoValue::Varint(days)) => entry.lapse.min_int = days as i64,
+            (18, ProtoValue::Varint(days)) => entry.new.ints[0] = days as i64,
+            (19, ProtoValue::Varint(days)) => entry.new.ints[1] = days as i64,
+            (21, ProtoValue::Varint(action)) => entry.lapse.leech_action = action as i64,
+            (22, ProtoValue::Varint(lapses)) => entry.lapse.leech_fails = lapses as i64,
+            (23, ProtoValue::Varint(disable)) => entry.autoplay = disable == 0,
+            (24, ProtoValue::Varint(secs)) => entry.max_taken = secs as i64,
+            (25, ProtoValue::Varint(timer)) => entry.timer = timer as i64,
+            (26, ProtoValue::Varint(skip)) => entry.replayq = skip == 0,
+            (27, ProtoValue::Varint(bury)) => entry.new.bury = bury != 0,
+            (28, ProtoValue::Varint(bury)) => entry.rev.bury = bury != 0,
+            (29, ProtoValue::Varint(bury)) => entry.bury_interday_learning = bury != 0,
+            (3, ProtoValue::Bytes(params)) => entry.fsrs_weights = packed_floats(params),
+            (5, ProtoValue::Bytes(params)) => entry.fsrs_params_5 = packed_floats(params),
+            (6, ProtoValue::Bytes(params)) => entry.fsrs_params_6 = packed_floats(params),
+            (37, ProtoValue::Fixed32(retention)) => {
+                entry.desired_retention = f32::from_bits(retention);
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+fn read_deck_common(entry: &mut DeckDbEntry, common: &[u8]) -> Result<(), Error> {
+    let mut reader = ProtoReader::new(common);
+    while let Some((field, value)) = reader.next_field()? {
+        if let (1, ProtoValue::Varint(collapsed)) = (field, value) {
+            entry.collapsed = collapsed != 0;
+        }
+    }
+    Ok(())
+}
+
+fn read_deck_kind(entry: &mut DeckDbEntry, kind: &[u8]) -> Result<(), Error> {
+    let mut reader = ProtoReader::new(kind);
+    while let Some((field, value)) = reader.next_field()? {
+        match (field, value) {
+            (1, ProtoValue::Bytes(normal)) => {
+                entry.deck_db_entry_dyn = 0;
+                let mut reader = ProtoReader::new(normal);
+                while let Some((field, value)) = reader.next_field()? {
+                    match (field, value) {
+                        (1, ProtoValue::Varint(conf)) => entry.conf = conf as i64,
+                        (2, ProtoValue::Varint(extend_new)) => entry.extend_new = extend_new as i64,
+                        (3, ProtoValue::Varint(extend_rev)) => entry.extend_rev = extend_rev as i64,
+                        (4, ProtoValue::Bytes(desc)) => entry.desc = string(desc),
+                        _ => {}
+                    }
+                }
+            }
+            (2, ProtoValue::Bytes(_)) => entry.deck_db_entry_dyn = 1,
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::{Field, Template};
+    use crate::storage::CollectionManager;
+    use crate::storage::schema::SchemaVersion;
+
+    #[test]
+    fn test_model_round_trip() {
+        let mut collection = CollectionManager::memory().unwrap();
+        collection.init_schema_version(SchemaVersion::V18).unwrap();
+        let model = Model::new(
+            123,
+            "Test",
+            vec![Field::new("Front"), Field::new("Back").sticky(true)],
+            vec![Template::new("Card 1").qfmt("{{Front}}").afmt("{{Back}}")],
+        )
+        .css(".card {}");
+
+        let transaction = collection.connection_mut().transaction().unwrap();
+        write_model_to_db(&model, &transaction, 0.0, 1).unwrap();
+        transaction.commit().unwrap();
+
+        let entries = read_models_from_db(collection.connection()).unwrap();
+        assert_eq!(entries.len(), 1);
+        let restored = models::db_entry_to_model(&entries[0]).unwrap();
+        assert_eq!(restored.name, "Test");
+        assert_eq!(restored.css, ".card {}");
+        assert_eq!(restored.field_names(), vec!["Front", "Back"]);
+        assert_eq!(restored.fields[1].sticky, Some(true));
+        assert_eq!(restored.templates[0].afmt, "{{Back}}");
+    }
+
+    #[test]
+    fn test_deck_options_round_trip() {
+        let mut collection = CollectionManager::memory().unwrap();
+        collection.init_schema_version(SchemaVersion::V18).unwrap();
+        let options = DeckOptions::new(99, "Custom")
+            .with_learning_steps(vec![0.5, 5.0])
+            .with_starting_ease(2.1)
+            .with_bury(false, false, true)
+            .with_autoplay(false)
+            .with_fsrs_params(vec![0.25; 21])
+            .with_desired_retention(0.8);
+
+        let transaction = collection.connection_mut().transaction().unwrap();
+        write_deck_options_to_db(&options, &transaction, 0.0).unwrap();
+        transaction.commit().unwrap();
+
+        let entries = read_deck_options_from_db(collection.connection()).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(
+            decks::db_entry_to_options(&entries[0]),
+            DeckOptions::default()
+        );
+        assert_eq!(decks::db_entry_to_options(&entries[1]), options);
+    }
+
+    #[test]
+    fn test_deck_config_fsrs_fields() {
+        let entry = DeckConfigDbEntry {
+            fsrs_weights: vec![0.5],
+            fsrs_params_5: vec![1.0],
+            fsrs_params_6: vec![2.0],
+            desired_retention: 0.875,
+            ..DeckConfigDbEntry::default()
+        };
+        let bytes = deck_config(&entry).into_bytes();
+
+        // fsrs_params_4 = 3, fsrs_params_5 = 5 and fsrs_params_6 = 6 as packed floats
+        let params = [
+            0x1a, 4, 0x00, 0x00, 0x00, 0x3f, //
+            0x2a, 4, 0x00, 0x00, 0x80, 0x3f, //
+            0x32, 4, 0x00, 0x00, 0x00, 0x40,
+        ];
+        assert!(bytes.windows(params.len()).any(|window| window == params));
+        // desired_retention = 37 as a fixed32 float
+        let retention = [0xad, 0x02, 0x00, 0x00, 0x60, 0x3f];
+        assert!(
+            bytes
+                .windows(retention.len())
+                .any(|window| window == retention)
+        );
+
+        let mut reader = ProtoReader::new(&bytes);
+        while let Some((field, _)) = reader.next_field().unwrap() {
+            assert!(![39, 40, 48].contains(&field), "unexpected field {field}");
+        }
+    }
+
+    #[test]
+    fn test_deck_round_trip() {
+        let mut collection = CollectionManager::memory().unwrap();
+        collection.init_schema_version(SchemaVersion::V18).unwrap();
+        let deck = Deck::new(1234, "Parent::Child", "Description");
+
+        let transaction = collection.connection_mut().transaction().unwrap();
+        write_deck_to_db(&deck, &transaction, 0.0).unwrap();
+        transaction.commit().unwrap();
+
+        let stored_name: String = collection
+            .connection()
+            .query_row("SELECT name FROM decks WHERE id = 1234", [], |row| {
+                row.get(0)
+            })
+            .unwrap();
+        assert_eq!(stored_name, "Parent\x1fChild");
+
+        let entries = read_decks_from_db(collection.connection()).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].name, "Default");
+        assert_eq!(entries[1].name, "Parent::Child");
+        assert_eq!(entries[1].desc, "Description");
+        assert_eq!(entries[1].conf, 1);
+    }
+
+    #[test]
+    fn test_same_named_entries_are_renamed() {
+        let mut collection = CollectionManager::memory().unwrap();
+        collection.init_schema_version(SchemaVersion::V18).unwrap();
+        let model = |id| {
+            Model::new(
+                id,
+                "Basic",
+                vec![Field::new("Front")],
+                vec![Template::new("Card 1").qfmt("{{Front}}")],
+            )
+        };
+
+        let transaction = collection.connection_mut().transaction().unwrap();
+        write_deck_to_db(&Deck::new(10, "Same", ""), &transaction, 0.0).unwrap();
+        write_deck_to_db(&Deck::new(20, "Same", ""), &transaction, 0.0).unwrap();
+        write_model_to_db(&model(100), &transaction, 0.0, 10).unwrap();
+        write_model_to_db(&model(200), &transaction, 0.0, 20).unwrap();
+        // Writing an entry again replaces it by ID and keeps its name
+        write_model_to_db(&model(200), &transaction, 0.0, 20).unwrap();
+        transaction.commit().unwrap();
+
+        let decks = read_decks_from_db(collection.connection()).unwrap();
+        let names: Vec<_> = decks.iter().map(|d| (d.id, d.name.as_str())).collect();
+        assert_eq!(names, vec![(1, "Default"), (10, "Same"), (20, "Same+")]);
+
+        let models = read_models_from_db(collection.connection()).unwrap();
+        let names: Vec<_> = models
+            .iter()
+            .map(|m| (m.id.as_str(), m.name.as_str()))
+            .collect();
+        assert_eq!(names, vec![("100", "Basic"), ("200", "Basic+")]);
+    }
+}
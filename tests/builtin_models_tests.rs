@@ -117,3 +117,16 @@ fn test_all_builtin_models_clone() {
         assert_eq!(cloned.name, model.name);
     }
 }
+
+#[test]
+fn test_builtin_models_validate_cleanly() {
+    for model in [
+        basic_model(),
+        basic_and_reversed_card_model(),
+        basic_optional_reversed_card_model(),
+        basic_type_in_the_answer_model(),
+        cloze_model(),
+    ] {
+        assert_eq!(model.validate(), vec![], "{}", model.name);
+    }
+}
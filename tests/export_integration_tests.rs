@@ -246,3 +246,316 @@ fn test_package_with_cloze_notes() {
 
     assert!(output_path.exists());
 }
+
+#[test]
+fn test_package_media_uses_numbered_entries() {
+    let temp_dir = TempDir::new().unwrap();
+    let output_path = temp_dir.path().join("numbered_media.apkg");
+
+    let deck = Deck::new(1234, "Media Deck", "Deck with media files");
+    let mut media = std::collections::HashMap::new();
+    media.insert("sound.mp3".to_string(), vec![1, 2, 3]);
+    media.insert("image.png".to_string(), vec![4, 5, 6]);
+
+    let package = genanki_rs_rev::Package::new(vec![deck], media).unwrap();
+    package.write_to_file(&output_path).unwrap();
+
+    let file = File::open(&output_path).unwrap();
+    let mut archive = zip::ZipArchive::new(std::io::BufReader::new(file)).unwrap();
+
+    let mut mapping_json = String::new();
+    archive
+        .by_name("collection.media")
+        .unwrap()
+        .read_to_string(&mut mapping_json)
+        .unwrap();
+    let mapping: std::collections::HashMap<String, String> =
+        serde_json::from_str(&mapping_json).unwrap();
+    assert_eq!(mapping.len(), 2);
+    assert_eq!(mapping["0"], "image.png");
+    assert_eq!(mapping["1"], "sound.mp3");
+
+    let mut data = Vec::new();
+    archive.by_name("0").unwrap().read_to_end(&mut data).unwrap();
+    assert_eq!(data, vec![4, 5, 6]);
+    data.clear();
+    archive.by_name("1").unwrap().read_to_end(&mut data).unwrap();
+    assert_eq!(data, vec![1, 2, 3]);
+
+    let names: Vec<&str> = archive.file_names().collect();
+    assert!(!names.iter().any(|name| name.starts_with("media/")));
+}
+
+#[test]
+fn test_package_anki21_layout() {
+    let temp_dir = TempDir::new().unwrap();
+    let output_path = temp_dir.path().join("anki21.apkg");
+
+    let mut deck = Deck::new(1234, "Anki21", "");
+    deck.add_note(Note::new(basic_model(), vec!["Q", "A"]).unwrap());
+    let package = create_package_result(deck)
+        .unwrap()
+        .with_format(genanki_rs_rev::PackageFormat::Anki21);
+    package.write_to_file(&output_path).unwrap();
+
+    let file = File::open(&output_path).unwrap();
+    let mut archive = zip::ZipArchive::new(std::io::BufReader::new(file)).unwrap();
+
+    let mut meta = Vec::new();
+    archive.by_name("meta").unwrap().read_to_end(&mut meta).unwrap();
+    assert_eq!(meta, vec![0x08, 2]);
+
+    let mut header = vec![0u8; 16];
+    archive
+        .by_name("collection.anki21")
+        .unwrap()
+        .read_exact(&mut header)
+        .unwrap();
+    assert_eq!(&header[0..6], b"SQLite");
+
+    // Older Anki versions get a placeholder collection
+    assert!(archive.by_name("collection.anki2").is_ok());
+    assert!(archive.by_name("collection.media").is_ok());
+}
+
+#[test]
+fn test_package_anki21b_layout() {
+    let temp_dir = TempDir::new().unwrap();
+    let output_path = temp_dir.path().join("anki21b.apkg");
+
+    let mut deck = Deck::new(1234, "Anki21b", "");
+    deck.add_note(Note::new(basic_model(), vec!["Q", "A"]).unwrap());
+    let mut media = std::collections::HashMap::new();
+    media.insert("sound.mp3".to_string(), vec![1, 2, 3]);
+    let package = genanki_rs_rev::Package::new(vec![deck], media)
+        .unwrap()
+        .with_format(genanki_rs_rev::PackageFormat::Anki21b);
+    package.write_to_file(&output_path).unwrap();
+
+    let file = File::open(&output_path).unwrap();
+    let mut archive = zip::ZipArchive::new(std::io::BufReader::new(file)).unwrap();
+
+    let mut meta = Vec::new();
+    archive.by_name("meta").unwrap().read_to_end(&mut meta).unwrap();
+    assert_eq!(meta, vec![0x08, 3]);
+
+    let mut compressed = Vec::new();
+    archive
+        .by_name("collection.anki21b")
+        .unwrap()
+        .read_to_end(&mut compressed)
+        .unwrap();
+    let collection = zstd::decode_all(compressed.as_slice()).unwrap();
+    assert_eq!(&collection[0..6], b"SQLite");
+
+    let mut media_data = Vec::new();
+    archive.by_name("0").unwrap().read_to_end(&mut media_data).unwrap();
+    assert_eq!(zstd::decode_all(media_data.as_slice()).unwrap(), vec![1, 2, 3]);
+
+    assert!(archive.by_name("media").is_ok());
+    assert!(archive.by_name("collection.media").is_err());
+}
+
+#[test]
+fn test_package_anki21b_uses_schema_v18() {
+    let temp_dir = TempDir::new().unwrap();
+    let output_path = temp_dir.path().join("schema18.apkg");
+
+    let mut deck = Deck::new(1234, "Languages::Rust", "");
+    let note = Note::with_options(basic_model(), vec!["Q", "A"], None, Some(vec!["rust"]), None)
+        .unwrap();
+    deck.add_note(note);
+    create_package_result(deck)
+        .unwrap()
+        .with_format(genanki_rs_rev::PackageFormat::Anki21b)
+        .write_to_file(&output_path)
+        .unwrap();
+
+    let file = File::open(&output_path).unwrap();
+    let mut archive = zip::ZipArchive::new(std::io::BufReader::new(file)).unwrap();
+    let mut compressed = Vec::new();
+    archive
+        .by_name("collection.anki21b")
+        .unwrap()
+        .read_to_end(&mut compressed)
+        .unwrap();
+    let db_path = temp_dir.path().join("collection.anki21b");
+    std::fs::write(&db_path, zstd::decode_all(compressed.as_slice()).unwrap()).unwrap();
+
+    let collection = genanki_rs_rev::CollectionManager::open(&db_path).unwrap();
+    let conn = collection.connection();
+    let count = |sql: &str| -> i64 { conn.query_row(sql, [], |row| row.get(0)).unwrap() };
+
+    assert_eq!(count("SELECT ver FROM col"), 18);
+    assert_eq!(count("SELECT count() FROM notetypes"), 1);
+    assert_eq!(count("SELECT count() FROM fields"), 2);
+    assert_eq!(count("SELECT count() FROM templates"), 1);
+    assert_eq!(count("SELECT count() FROM decks"), 2);
+    assert_eq!(count("SELECT count() FROM deck_config"), 1);
+    assert_eq!(count("SELECT count() FROM tags WHERE tag = 'rust'"), 1);
+    assert_eq!(count("SELECT count() FROM notes"), 1);
+    let name: String = conn
+        .query_row("SELECT name FROM decks WHERE id = 1234", [], |row| row.get(0))
+        .unwrap();
+    assert_eq!(name, "Languages\x1fRust");
+}
+
+#[test]
+fn test_package_writes_sort_field_and_checksum() {
+    let temp_dir = TempDir::new().unwrap();
+    let output_path = temp_dir.path().join("sfld.apkg");
+
+    let mut deck = Deck::new(1234, "Sort Field", "");
+    deck.add_note(Note::new(basic_model(), vec!["<b>hello</b>", "A"]).unwrap());
+    deck.add_note(Note::new(basic_model(), vec!["42", "A"]).unwrap());
+    create_package_result(deck)
+        .unwrap()
+        .write_to_file(&output_path)
+        .unwrap();
+
+    let file = File::open(&output_path).unwrap();
+    let mut archive = zip::ZipArchive::new(std::io::BufReader::new(file)).unwrap();
+    let db_path = temp_dir.path().join("collection.anki2");
+    let mut db = Vec::new();
+    archive
+        .by_name("collection.anki2")
+        .unwrap()
+        .read_to_end(&mut db)
+        .unwrap();
+    std::fs::write(&db_path, db).unwrap();
+
+    let collection = genanki_rs_rev::CollectionManager::open(&db_path).unwrap();
+    let conn = collection.connection();
+    let rows: Vec<(String, String, i64)> = conn
+        .prepare("SELECT typeof(sfld), sfld, csum FROM notes ORDER BY id")
+        .unwrap()
+        .query_map([], |row| {
+            Ok((
+                row.get(0)?,
+                row.get::<_, rusqlite::types::Value>(1).map(|v| match v {
+                    rusqlite::types::Value::Integer(n) => n.to_string(),
+                    rusqlite::types::Value::Text(s) => s,
+                    other => format!("{other:?}"),
+                })?,
+                row.get(2)?,
+            ))
+        })
+        .unwrap()
+        .collect::<Result<_, _>>()
+        .unwrap();
+
+    assert_eq!(rows[0], ("text".to_string(), "hello".to_string(), 0xaaf4c61d));
+    assert_eq!(rows[1].0, "integer");
+    assert_eq!(rows[1].1, "42");
+}
+
+#[test]
+fn test_package_write_is_reproducible() {
+    let temp_dir = TempDir::new().unwrap();
+    let timestamp = std::time::UNIX_EPOCH + std::time::Duration::from_secs(1_700_000_000);
+
+    let build = |format: genanki_rs_rev::PackageFormat, name: &str| -> Vec<u8> {
+        let mut deck = Deck::new(1234, "Reproducible", "");
+        deck.add_note(Note::new(basic_model(), vec!["Q", "A"]).unwrap());
+        // Several clozes, so card IDs depend on the order cards are generated in
+        deck.add_note(
+            Note::new(cloze_model(), vec!["{{c1::One}} {{c2::Two}} {{c3::Three}}"]).unwrap(),
+        );
+        let mut media = std::collections::HashMap::new();
+        for i in 0..10 {
+            media.insert(format!("file{i}.txt"), vec![i as u8; 16]);
+        }
+        let path = temp_dir.path().join(name);
+        genanki_rs_rev::Package::new(vec![deck], media)
+            .unwrap()
+            .with_format(format)
+            .with_timestamp(timestamp)
+            .write_to_file(&path)
+            .unwrap();
+        std::fs::read(path).unwrap()
+    };
+
+    for format in [
+        genanki_rs_rev::PackageFormat::Legacy,
+        genanki_rs_rev::PackageFormat::Anki21,
+        genanki_rs_rev::PackageFormat::Anki21b,
+    ] {
+        assert_eq!(build(format, "first.apkg"), build(format, "second.apkg"));
+    }
+}
+
+#[test]
+fn test_package_write_site() {
+    let temp_dir = TempDir::new().unwrap();
+    let site_dir = temp_dir.path().join("site");
+
+    let mut deck = Deck::new(1234, "Review <Deck>", "For proofreading");
+    deck.add_note(
+        Note::new(basic_model(), vec!["Flag <img src=\"fr.png\">", "France"])
+            .unwrap()
+            .with_tag("geo"),
+    );
+    deck.add_note(Note::new(cloze_model(), vec!["{{c1::Paris}} and {{c2::Lyon}}"]).unwrap());
+    let mut media = std::collections::HashMap::new();
+    media.insert("fr.png".to_string(), vec![1, 2, 3]);
+    genanki_rs_rev::Package::new(vec![deck], media)
+        .unwrap()
+        .write_site(&site_dir)
+        .unwrap();
+
+    let read = |name: &str| std::fs::read_to_string(site_dir.join(name)).unwrap();
+    assert!(read("index.html").contains("<a href=\"deck-1234.html\">Review &lt;Deck&gt;</a>"));
+    let deck_index = read("deck-1234.html");
+    assert!(deck_index.contains("deck-1234-note-1-card-1.html"));
+    assert!(deck_index.contains("deck-1234-note-2-card-2.html"));
+
+    let card = read("deck-1234-note-1-card-1.html");
+    assert!(card.contains("Flag <img src=\"fr.png\">"));
+    assert!(card.contains(&basic_model().css));
+    assert!(card.contains("Tags: geo"));
+    assert!(card.contains("href=\"deck-1234-note-2-card-1.html\">Next"));
+    assert_eq!(std::fs::read(site_dir.join("fr.png")).unwrap(), vec![1, 2, 3]);
+
+    // Media named like a page would overwrite it
+    let clash_dir = temp_dir.path().join("clash");
+    let mut media = std::collections::HashMap::new();
+    media.insert("index.html".to_string(), b"<p>not a page</p>".to_vec());
+    let clash = genanki_rs_rev::Package::new(vec![Deck::new(1234, "Empty", "")], media)
+        .unwrap()
+        .write_site(&clash_dir);
+    assert!(matches!(clash, Err(genanki_rs_rev::Error::Validation(_))));
+    assert!(!clash_dir.exists());
+}
+
+#[test]
+fn test_package_reports_html_warnings_to_sink() {
+    use genanki_rs_rev::{CollectDiagnostics, DenyDiagnostics, Error, Severity};
+    use std::sync::Arc;
+
+    let mut deck = Deck::new(1234, "Warnings", "");
+    deck.add_note(Note::new(basic_model(), vec!["Fine <b>bold</b>", "1 < 2 > 0"]).unwrap());
+    deck.add_note(Note::new(basic_model(), vec!["Also fine", "<br>"]).unwrap());
+
+    let sink = Arc::new(CollectDiagnostics::new());
+    genanki_rs_rev::Package::new(vec![deck.clone()], std::collections::HashMap::new())
+        .unwrap()
+        .with_diagnostics(sink.clone())
+        .to_bytes()
+        .unwrap();
+    let diagnostics = sink.take();
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(diagnostics[0].severity, Severity::Warning);
+    assert_eq!(
+        diagnostics[0].to_string(),
+        format!(
+            "warning: deck 'Warnings', note 0 (guid '{}'), field 'Back': invalid HTML tags: < 2 >",
+            deck.notes()[0].guid()
+        )
+    );
+
+    let result = genanki_rs_rev::Package::new(vec![deck], std::collections::HashMap::new())
+        .unwrap()
+        .with_diagnostics(Arc::new(DenyDiagnostics))
+        .to_bytes();
+    assert!(matches!(result, Err(Error::Validation(_))));
+}
@@ -0,0 +1,609 @@
+//! Package import integration tests
+
+use genanki_rs_rev::{
+    Burial, Compression, CompressionOptions, Deck, DeckOptions, Due, Error, Flag, FsrsState,
+    GuidStrategy, LeechAction, Note, NoteBuilder, Package, PackageFormat, PackageReader,
+    PackageWriter, Rating, ReviewKind, RevlogEntry, RevlogInterval, Scheduling,
+    basic_and_reversed_card_model, basic_model, cloze_model,
+};
+use std::collections::HashMap;
+use tempfile::TempDir;
+
+fn sample_package() -> Result<Package, Error> {
+    let mut deck = Deck::new(2059400110, "Country Capitals", "Capitals of the world");
+    deck.add_note(
+        Note::with_options(
+            basic_model(),
+            vec!["Capital of France", "Paris"],
+            None,
+            Some(vec!["geography", "europe"]),
+            None,
+        )?
+        .with_guid("custom-guid"),
+    );
+    deck.add_note(Note::new(
+        basic_and_reversed_card_model(),
+        vec!["Capital of Japan", "<b>Tokyo</b>"],
+    )?);
+
+    let mut cloze_deck = Deck::new(2059400111, "Cloze", "");
+    cloze_deck.add_note(Note::new(
+        cloze_model(),
+        vec!["{{c1::Berlin}} is in {{c2::Germany}}"],
+    )?);
+
+    let mut media = HashMap::new();
+    media.insert("flag.png".to_string(), vec![0x89, 0x50, 0x4E, 0x47]);
+    Package::new(vec![deck, cloze_deck], media)
+}
+
+#[test]
+fn test_read_package_restores_decks() -> Result<(), Error> {
+    let temp_dir = TempDir::new()?;
+    let path = temp_dir.path().join("import.apkg");
+    sample_package()?.write_to_file(&path)?;
+
+    let package = Package::read_from_file(&path)?;
+    let decks = package.decks();
+    assert_eq!(decks.len(), 2);
+    assert_eq!(decks[0].id, 2059400110);
+    assert_eq!(decks[0].name, "Country Capitals");
+    assert_eq!(decks[0].description, "Capitals of the world");
+    assert_eq!(decks[0].num_notes(), 2);
+    assert_eq!(decks[0].num_models(), 2);
+    assert_eq!(decks[1].num_notes(), 1);
+
+    let note = &decks[0].notes()[0];
+    assert_eq!(note.guid(), "custom-guid");
+    assert_eq!(note.fields(), &["Capital of France", "Paris"]);
+    assert_eq!(note.tags(), &["geography", "europe"]);
+    assert_eq!(note.model().name, basic_model().name);
+
+    assert_eq!(decks[0].notes()[1].cards().len(), 2);
+    assert_eq!(decks[1].notes()[0].cards().len(), 2);
+
+    assert_eq!(
+        package.media_files().get("flag.png"),
+        Some(&vec![0x89, 0x50, 0x4E, 0x47])
+    );
+    Ok(())
+}
+
+#[test]
+fn test_read_package_round_trip() -> Result<(), Error> {
+    let temp_dir = TempDir::new()?;
+    let first = temp_dir.path().join("first.apkg");
+    let second = temp_dir.path().join("second.apkg");
+
+    sample_package()?.write_to_file(&first)?;
+    let read_first = Package::read_from_file(&first)?;
+    let snapshot_first = snapshot(&read_first);
+    read_first.write_to_file(&second)?;
+    let read_second = PackageReader::open(&second)?.read()?;
+
+    assert_eq!(snapshot_first, snapshot(&read_second));
+    assert_eq!(read_second.media_files().len(), 1);
+    Ok(())
+}
+
+#[test]
+fn test_read_package_detects_guid_strategies() -> Result<(), Error> {
+    let mut deck = Deck::new(2059400112, "Guids", "");
+    for strategy in [
+        GuidStrategy::Blake3,
+        GuidStrategy::Genanki,
+        GuidStrategy::AnkiRandom,
+    ] {
+        let question = format!("{strategy:?}");
+        deck.add_note(Note::new_with_strategy(
+            basic_model(),
+            vec![&question, "answer"],
+            strategy,
+        )?);
+    }
+    let bytes = Package::new(vec![deck], HashMap::new())?.to_bytes()?;
+    let read = PackageReader::new(std::io::Cursor::new(bytes))?.read()?;
+
+    let notes = read.decks()[0].notes();
+    assert!(notes.iter().all(Note::has_valid_guid));
+    assert_eq!(notes[0].guid_strategy(), GuidStrategy::Blake3);
+    assert_eq!(notes[1].guid_strategy(), GuidStrategy::Genanki);
+    assert_eq!(notes[2].guid_strategy(), GuidStrategy::AnkiRandom);
+    assert!(read.validate().is_empty());
+    Ok(())
+}
+
+#[test]
+fn test_read_missing_collection_error() -> Result<(), Error> {
+    let temp_dir = TempDir::new()?;
+    let path = temp_dir.path().join("empty.apkg");
+    let mut zip = zip::ZipWriter::new(std::fs::File::create(&path)?);
+    zip.start_file("collection.media", zip::write::SimpleFileOptions::default())?;
+    zip.finish()?;
+
+    assert!(Package::read_from_file(&path).is_err());
+    Ok(())
+}
+
+/// Comparable view of a note: guid, fields, tags and card count
+type NoteSnapshot = (String, Vec<String>, Vec<String>, usize);
+
+/// Comparable view of a deck: id, name and notes
+type DeckSnapshot = (i64, String, Vec<NoteSnapshot>);
+
+/// Flatten a package into comparable values
+fn snapshot(package: &Package) -> Vec<DeckSnapshot> {
+    package
+        .decks()
+        .iter()
+        .map(|deck| {
+            (
+                deck.id,
+                deck.name.clone(),
+                deck.notes()
+                    .iter()
+                    .map(|note| {
+                        (
+                            note.guid().to_string(),
+                            note.fields().to_vec(),
+                            note.tags().to_vec(),
+                            note.cards().len(),
+                        )
+                    })
+                    .collect(),
+            )
+        })
+        .collect()
+}
+
+#[test]
+fn test_read_package_modern_formats() -> Result<(), Error> {
+    let temp_dir = TempDir::new()?;
+    for format in [PackageFormat::Anki21, PackageFormat::Anki21b] {
+        let path = temp_dir.path().join(format!("{format:?}.apkg"));
+        sample_package()?.with_format(format).write_to_file(&path)?;
+
+        let package = Package::read_from_file(&path)?;
+        assert_eq!(package.format(), format);
+        assert_eq!(snapshot(&package), snapshot(&sample_package()?));
+        assert_eq!(
+            package.media_files().get("flag.png"),
+            Some(&vec![0x89, 0x50, 0x4E, 0x47])
+        );
+    }
+    Ok(())
+}
+
+#[test]
+fn test_note_and_card_ids() -> Result<(), Error> {
+    let temp_dir = TempDir::new()?;
+    let path = temp_dir.path().join("ids.apkg");
+
+    let mut explicit = Note::new(basic_model(), vec!["Explicit", "ID"])?.with_id(1_000);
+    explicit.cards_mut()[0].id = Some(2_000);
+    let mut deck = Deck::new(2059400112, "IDs", "");
+    deck.add_note(explicit);
+    deck.add_note(Note::new(basic_model(), vec!["Generated", "ID"])?);
+    Package::new(vec![deck], HashMap::new())?.write_to_file(&path)?;
+
+    let package = Package::read_from_file(&path)?;
+    let notes = package.decks()[0].notes();
+    assert_eq!(notes[0].id(), Some(1_000));
+    assert_eq!(notes[0].cards()[0].id(), Some(2_000));
+
+    // Generated IDs are milliseconds since the epoch, later than 2020-01-01
+    let note_id = notes[1].id().unwrap();
+    let card_id = notes[1].cards()[0].id().unwrap();
+    assert!(note_id > 1_577_836_800_000);
+    assert!(card_id > note_id);
+    Ok(())
+}
+
+#[test]
+fn test_write_package_in_memory() -> Result<(), Error> {
+    let temp_dir = TempDir::new()?;
+    let path = temp_dir.path().join("memory.apkg");
+    let timestamp = std::time::UNIX_EPOCH + std::time::Duration::from_secs(1_700_000_000);
+
+    let bytes = sample_package()?.with_timestamp(timestamp).to_bytes()?;
+    sample_package()?
+        .with_timestamp(timestamp)
+        .write_to_file(&path)?;
+    assert_eq!(bytes, std::fs::read(&path)?);
+
+    let package = PackageReader::new(std::io::Cursor::new(bytes))?.read()?;
+    assert_eq!(package.decks().len(), 2);
+    assert_eq!(package.media_files().len(), 1);
+    Ok(())
+}
+
+#[test]
+fn test_streamed_media_round_trip() -> Result<(), Error> {
+    let temp_dir = TempDir::new()?;
+    let audio = temp_dir.path().join("word.mp3");
+    let audio_data: Vec<u8> = (0..100_000u32).map(|i| (i % 251) as u8).collect();
+    std::fs::write(&audio, &audio_data)?;
+
+    for format in [PackageFormat::Legacy, PackageFormat::Anki21b] {
+        let mut writer = PackageWriter::new().format(format);
+        writer.add_media("word.mp3", &audio)?;
+        writer.add_media_reader("notes.txt", std::io::Cursor::new(b"streamed".to_vec()));
+        let package = writer.build(vec![Deck::new(2059400113, "Audio", "")])?;
+        let bytes = package.to_bytes()?;
+
+        // Already compressed formats are stored as-is in legacy packages
+        if format == PackageFormat::Legacy {
+            let mut zip = zip::ZipArchive::new(std::io::Cursor::new(bytes.clone()))?;
+            assert_eq!(
+                zip.by_name("1")?.compression(),
+                zip::CompressionMethod::Stored
+            );
+            assert_eq!(
+                zip.by_name("0")?.compression(),
+                zip::CompressionMethod::Deflated
+            );
+        }
+
+        let read = PackageReader::new(std::io::Cursor::new(bytes))?.read()?;
+        assert_eq!(read.media_files().get("word.mp3"), Some(&audio_data));
+        assert_eq!(
+            read.media_files().get("notes.txt"),
+            Some(&b"streamed".to_vec())
+        );
+
+        // The reader was consumed by the first write
+        assert!(matches!(package.to_bytes(), Err(Error::Validation(_))));
+        let site = temp_dir.path().join(format!("{format:?}-site"));
+        let error = package.write_site(&site).unwrap_err();
+        assert!(
+            error
+                .to_string()
+                .contains("media source 'notes.txt' was already consumed")
+        );
+        assert!(!site.exists());
+        let path = temp_dir.path().join(format!("{format:?}.apkg"));
+        assert!(package.write_to_file(&path).is_err());
+        assert!(!path.exists());
+    }
+    Ok(())
+}
+
+#[test]
+fn test_compression_options_round_trip() -> Result<(), Error> {
+    let mut deck = Deck::new(2059400114, "Compressed", "");
+    deck.add_note(Note::new(basic_model(), vec!["Q", "A"])?);
+    let media = HashMap::from([("notes.txt".to_string(), b"compress me".repeat(100))]);
+
+    let stored = Package::new(vec![deck.clone()], media.clone())?
+        .with_compression(CompressionOptions::all(Compression::Stored))
+        .to_bytes()?;
+    let mut zip = zip::ZipArchive::new(std::io::Cursor::new(stored.clone()))?;
+    assert_eq!(
+        zip.by_name("collection.anki2")?.compression(),
+        zip::CompressionMethod::Stored
+    );
+    assert_eq!(
+        zip.by_name("0")?.compression(),
+        zip::CompressionMethod::Stored
+    );
+
+    let deflated = Package::new(vec![deck.clone()], media.clone())?
+        .with_compression(
+            CompressionOptions::new()
+                .with_database(Compression::Deflate(Some(9)))
+                .with_media(Compression::Deflate(Some(1))),
+        )
+        .to_bytes()?;
+    assert!(deflated.len() < stored.len());
+
+    let zstd = Package::new(vec![deck.clone()], media.clone())?
+        .with_format(PackageFormat::Anki21b)
+        .with_compression(CompressionOptions::all(Compression::Zstd(19)))
+        .to_bytes()?;
+    for bytes in [stored, deflated, zstd] {
+        let read = PackageReader::new(std::io::Cursor::new(bytes))?.read()?;
+        assert_eq!(read.decks()[0].notes()[0].fields(), ["Q", "A"]);
+        assert_eq!(read.media_files(), &media);
+    }
+
+    // Older formats cannot hold zstd entries, and Anki21b needs them
+    let package = Package::new(vec![deck], media)?
+        .with_compression(CompressionOptions::new().with_media(Compression::Zstd(3)));
+    assert!(matches!(package.to_bytes(), Err(Error::Config(_))));
+    let package = package
+        .with_format(PackageFormat::Anki21b)
+        .with_compression(CompressionOptions::new().with_database(Compression::Stored));
+    assert!(matches!(package.to_bytes(), Err(Error::Config(_))));
+    Ok(())
+}
+
+#[test]
+fn test_deck_options_round_trip() -> Result<(), Error> {
+    let options = DeckOptions::new(1700000000001, "Intensive")
+        .with_new_per_day(60)
+        .with_reviews_per_day(999)
+        .with_learning_steps(vec![1.0, 5.0, 30.0])
+        .with_relearning_steps(vec![5.0])
+        .with_intervals(3, 6)
+        .with_starting_ease(2.2)
+        .with_leech(5, LeechAction::Suspend)
+        .with_bury(false, true, true)
+        .with_autoplay(false)
+        .with_replay_question(false);
+    let mut first = Deck::new(2059400115, "Intensive 1", "").with_options(options.clone());
+    first.add_note(Note::new(basic_model(), vec!["Q1", "A1"])?);
+    let mut second = Deck::new(2059400116, "Intensive 2", "").with_options(options.clone());
+    second.add_note(Note::new(basic_model(), vec!["Q2", "A2"])?);
+    let mut plain = Deck::new(2059400117, "Plain", "");
+    plain.add_note(Note::new(basic_model(), vec!["Q3", "A3"])?);
+
+    for format in [
+        PackageFormat::Legacy,
+        PackageFormat::Anki21,
+        PackageFormat::Anki21b,
+    ] {
+        let bytes = Package::new(
+            vec![first.clone(), second.clone(), plain.clone()],
+            HashMap::new(),
+        )?
+        .with_format(format)
+        .to_bytes()?;
+        let read = PackageReader::new(std::io::Cursor::new(bytes))?.read()?;
+
+        let decks = read.decks();
+        assert_eq!(decks[0].options(), Some(&options), "{format:?}");
+        assert_eq!(decks[1].options_id(), options.id);
+        assert_eq!(decks[2].options(), None);
+    }
+    Ok(())
+}
+
+#[test]
+fn test_card_scheduling_round_trip() -> Result<(), Error> {
+    let due_day = time::Date::from_calendar_date(2030, time::Month::January, 15).unwrap();
+    let step_end = std::time::UNIX_EPOCH + std::time::Duration::from_secs(1_900_000_000);
+    let states = [
+        Scheduling::new_card(7),
+        Scheduling::learning(Due::Time(step_end), 2).with_reps(1),
+        Scheduling::review(due_day, 21, 2350)
+            .with_reps(9)
+            .with_lapses(1),
+        Scheduling::relearning(Due::Day(due_day), 1, 4, 2150)
+            .with_reps(12)
+            .with_lapses(2),
+    ];
+
+    let mut deck = Deck::new(2059400118, "Migrated", "");
+    for (i, state) in states.iter().enumerate() {
+        let mut note = Note::new(basic_model(), vec![&format!("Q{i}"), "A"])?;
+        note.cards_mut()[0] = note.cards()[0].clone().with_scheduling(*state);
+        deck.add_note(note);
+    }
+
+    for format in [PackageFormat::Legacy, PackageFormat::Anki21b] {
+        let bytes = Package::new(vec![deck.clone()], HashMap::new())?
+            .with_format(format)
+            .to_bytes()?;
+        let read = PackageReader::new(std::io::Cursor::new(bytes))?.read()?;
+        let restored: Vec<Scheduling> = read.decks()[0]
+            .notes()
+            .iter()
+            .map(|note| *note.cards()[0].scheduling())
+            .collect();
+        assert_eq!(restored, states, "{format:?}");
+    }
+    Ok(())
+}
+
+#[test]
+fn test_revlog_round_trip() -> Result<(), Error> {
+    let at = |secs: u64| std::time::UNIX_EPOCH + std::time::Duration::from_secs(secs);
+    let history = vec![
+        RevlogEntry::new(at(1_700_000_000), Rating::Good, ReviewKind::Learning)
+            .with_intervals(RevlogInterval::Seconds(600), RevlogInterval::Seconds(60))
+            .with_duration(std::time::Duration::from_millis(4200)),
+        RevlogEntry::new(at(1_700_086_400), Rating::Easy, ReviewKind::Review)
+            .with_intervals(RevlogInterval::Days(4), RevlogInterval::Days(1))
+            .with_ease_factor(2650)
+            .with_duration(std::time::Duration::from_millis(3100)),
+    ];
+
+    let mut note = Note::new(basic_and_reversed_card_model(), vec!["Q", "A"])?;
+    let first = note.cards()[0].clone().with_revlog(history.clone());
+    // Answered at the same moment as the first card's first review
+    let second = note.cards()[1]
+        .clone()
+        .with_review(history[0].clone().with_ease_factor(1));
+    note.cards_mut()[0] = first;
+    note.cards_mut()[1] = second;
+    let mut deck = Deck::new(2059400119, "History", "");
+    deck.add_note(note);
+
+    for format in [PackageFormat::Legacy, PackageFormat::Anki21b] {
+        let bytes = Package::new(vec![deck.clone()], HashMap::new())?
+            .with_format(format)
+            .to_bytes()?;
+        let read = PackageReader::new(std::io::Cursor::new(bytes))?.read()?;
+        let cards = read.decks()[0].notes()[0].cards();
+        assert_eq!(cards[0].revlog(), history.as_slice(), "{format:?}");
+
+        // Review log IDs are unique, so the clashing review moves by a millisecond
+        let bumped = &cards[1].revlog()[0];
+        assert_eq!(bumped.ease_factor, 1);
+        assert_eq!(
+            bumped.time,
+            history[0].time + std::time::Duration::from_millis(1)
+        );
+    }
+    Ok(())
+}
+
+#[test]
+fn test_fsrs_state_round_trip() -> Result<(), Error> {
+    let params: Vec<f32> = (0..19).map(|i| i as f32 / 10.0).collect();
+    let options = DeckOptions::new(1700000000002, "FSRS")
+        .with_fsrs_params(params.clone())
+        .with_desired_retention(0.87);
+    let state = FsrsState::new(23.5, 6.125).with_desired_retention(0.87);
+
+    let mut note = Note::new(basic_model(), vec!["Q", "A"])?;
+    note.cards_mut()[0] = note.cards()[0].clone().with_fsrs(state);
+    let mut deck = Deck::new(2059400120, "FSRS", "").with_options(options.clone());
+    deck.add_note(note);
+    assert!(deck.validate().is_empty());
+
+    for format in [PackageFormat::Legacy, PackageFormat::Anki21b] {
+        let bytes = Package::new(vec![deck.clone()], HashMap::new())?
+            .with_format(format)
+            .to_bytes()?;
+        let read = PackageReader::new(std::io::Cursor::new(bytes))?.read()?;
+        let deck = &read.decks()[0];
+        assert_eq!(deck.options(), Some(&options), "{format:?}");
+        assert_eq!(deck.notes()[0].cards()[0].fsrs(), Some(&state));
+    }
+
+    let mut invalid = Note::new(basic_model(), vec!["Q", "A"])?;
+    invalid.cards_mut()[0] = invalid.cards()[0]
+        .clone()
+        .with_fsrs(FsrsState::new(2.0, 11.0));
+    let mut deck = Deck::new(2059400121, "Invalid", "");
+    deck.add_note(invalid);
+    assert!(deck.validate().has_errors());
+    Ok(())
+}
+
+#[test]
+fn test_card_flags_burial_and_deck_round_trip() -> Result<(), Error> {
+    let note = NoteBuilder::new()
+        .model(basic_and_reversed_card_model())
+        .fields(vec!["perro", "dog"])
+        .flag("Card 1", Flag::Purple)
+        .bury(0, Burial::Sibling)
+        .card_deck("Card 2", 2059400131)
+        .build()
+        .unwrap();
+    let mut deck = Deck::new(2059400130, "Spanish", "");
+    deck.add_note(note);
+    let reverse = Deck::new(2059400131, "Spanish::Reverse", "");
+    assert!(
+        !Package::new(vec![deck.clone()], HashMap::new())?
+            .validate()
+            .is_empty()
+    );
+
+    for format in [PackageFormat::Legacy, PackageFormat::Anki21b] {
+        let bytes = Package::new(vec![deck.clone(), reverse.clone()], HashMap::new())?
+            .with_format(format)
+            .to_bytes()?;
+        let read = PackageReader::new(std::io::Cursor::new(bytes))?.read()?;
+        let spanish = read
+            .decks()
+            .iter()
+            .find(|deck| deck.name == "Spanish")
+            .unwrap();
+        let cards = spanish.notes()[0].cards();
+        assert_eq!(cards[0].flag(), Some(Flag::Purple), "{format:?}");
+        assert_eq!(cards[0].buried(), Some(Burial::Sibling));
+        assert_eq!(cards[0].deck_id(), None);
+        assert_eq!(cards[1].flag(), None);
+        assert_eq!(cards[1].deck_id(), Some(2059400131));
+    }
+    Ok(())
+}
+
+#[test]
+fn test_same_named_decks_round_trip() -> Result<(), Error> {
+    let mut first = Deck::new(2059400140, "Vocabulary", "");
+    first.add_note(Note::new(basic_model(), vec!["uno", "one"])?);
+    let mut second = Deck::new(2059400141, "Vocabulary", "");
+    second.add_note(Note::new(basic_model(), vec!["dos", "two"])?);
+    let package = Package::new(vec![first, second], HashMap::new())?;
+    assert_eq!(package.validate().warnings().count(), 1);
+
+    let bytes = package.with_format(PackageFormat::Anki21b).to_bytes()?;
+    let read = PackageReader::new(std::io::Cursor::new(bytes))?.read()?;
+    let decks: Vec<_> = read
+        .decks()
+        .iter()
+        .map(|deck| (deck.id, deck.name.as_str(), deck.num_notes()))
+        .collect();
+    assert_eq!(
+        decks,
+        vec![
+            (2059400140, "Vocabulary", 1),
+            (2059400141, "Vocabulary+", 1)
+        ]
+    );
+    Ok(())
+}
+
+/// Write `package` as a legacy `.apkg` with `sql` run on its collection
+fn package_with_sql(package: &Package, sql: &str) -> Result<Vec<u8>, Error> {
+    let mut archive = zip::ZipArchive::new(std::io::Cursor::new(package.to_bytes()?))?;
+    let mut collection = Vec::new();
+    std::io::Read::read_to_end(&mut archive.by_name("collection.anki2")?, &mut collection)?;
+    let file = tempfile::NamedTempFile::new()?;
+    std::fs::write(file.path(), collection)?;
+    rusqlite::Connection::open(file.path())?.execute_batch(sql)?;
+
+    let mut zip = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+    zip.start_file("collection.anki2", zip::write::SimpleFileOptions::default())?;
+    std::io::Write::write_all(&mut zip, &std::fs::read(file.path())?)?;
+    Ok(zip.finish()?.into_inner())
+}
+
+#[test]
+fn test_read_cards_in_filtered_and_unknown_decks() -> Result<(), Error> {
+    let mut deck = Deck::new(2059400150, "Home", "");
+    deck.add_note(Note::new(
+        basic_and_reversed_card_model(),
+        vec!["perro", "dog"],
+    )?);
+    let package = Package::new(
+        vec![deck, Deck::new(2059400151, "Filtered", "")],
+        HashMap::new(),
+    )?;
+
+    // Cards moved into a filtered deck belong to their original deck
+    let filtered = package_with_sql(&package, "UPDATE cards SET odid = did, did = 2059400151")?;
+    let read = PackageReader::new(std::io::Cursor::new(filtered))?.read()?;
+    let home = read
+        .decks()
+        .iter()
+        .find(|deck| deck.name == "Home")
+        .unwrap();
+    assert_eq!(home.num_notes(), 1);
+    assert!(
+        home.notes()[0]
+            .cards()
+            .iter()
+            .all(|card| card.deck_id().is_none())
+    );
+
+    let unknown = package_with_sql(&package, "UPDATE cards SET did = 42 WHERE ord = 1")?;
+    assert!(
+        PackageReader::new(std::io::Cursor::new(unknown))?
+            .read()
+            .is_err()
+    );
+    Ok(())
+}
+
+#[test]
+fn test_failed_write_keeps_existing_file() -> Result<(), Error> {
+    let temp_dir = TempDir::new()?;
+    let path = temp_dir.path().join("existing.apkg");
+    std::fs::write(&path, b"previous package")?;
+
+    let audio = temp_dir.path().join("word.mp3");
+    std::fs::write(&audio, [1, 2, 3])?;
+    let mut writer = PackageWriter::new();
+    writer.add_media("word.mp3", &audio)?;
+    let package = writer.build(vec![Deck::new(2059400114, "Audio", "")])?;
+    std::fs::remove_file(&audio)?;
+
+    assert!(package.write_to_file(&path).is_err());
+    assert_eq!(std::fs::read(&path)?, b"previous package");
+    assert_eq!(std::fs::read_dir(temp_dir.path())?.count(), 1);
+    Ok(())
+}
@@ -5,6 +5,7 @@
 
 mod builtin_models_tests;
 mod deck_tests;
+mod import_tests;
 mod model_tests;
 mod note_tests;
 mod package_tests;
@@ -1,7 +1,7 @@
 //! Model integration tests
 
-use genanki_rs_rev::core::ModelType;
-use genanki_rs_rev::{Error, Field, Model, Template};
+use genanki_rs_rev::core::{Location, ModelType, TemplateSide, TextPosition};
+use genanki_rs_rev::{Error, Field, Model, Severity, Template};
 
 #[test]
 fn test_model_creation() {
@@ -245,3 +245,73 @@ fn test_template_name() {
     let template = Template::new("My Template");
     assert_eq!(template.name, "My Template");
 }
+
+#[test]
+fn test_model_req_understands_anki_syntax() -> Result<(), Error> {
+    let model = Model::new(
+        1,
+        "Anki Syntax",
+        vec![Field::new("Front"), Field::new("Back"), Field::new("Hint")],
+        vec![
+            Template::new("Hinted").qfmt("{{#Hint}}{{hint:Hint}}{{/Hint}}{{text:Front}}"),
+            Template::new("Reverse").qfmt("{{#Back}}{{type:Back}}{{/Back}}{{Tags}}"),
+            Template::new("Static").qfmt("{{FrontSide}}{{Deck}}{{Card}}"),
+        ],
+    );
+    assert_eq!(
+        model.req()?,
+        vec![
+            (0, "any".to_string(), vec![0, 2]),
+            (1, "any".to_string(), vec![1]),
+            (2, "none".to_string(), vec![]),
+        ]
+    );
+    Ok(())
+}
+
+#[test]
+fn test_model_req_parse_error() {
+    let model = Model::new(
+        1,
+        "Broken",
+        vec![Field::new("Front")],
+        vec![Template::new("Card 1").qfmt("{{#Front}}{{Front}}{{/Back}}")],
+    );
+    let err = model.req().unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "Invalid template format: template 'Card 1': line 1, column 20: \
+         '{{/Back}}' does not match the open section 'Front'"
+    );
+}
+
+#[test]
+fn test_model_validate_unknown_field() {
+    let model = Model::new(
+        1,
+        "Typo",
+        vec![Field::new("Front"), Field::new("Back")],
+        vec![
+            Template::new("Card 1")
+                .qfmt("{{Front}}")
+                .afmt("{{FrontSide}}<hr id=answer>{{Bakc}}"),
+        ],
+    );
+    let diagnostics = model.validate();
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(diagnostics[0].severity, Severity::Error);
+    assert_eq!(
+        diagnostics[0].location,
+        Location::Template {
+            model: "Typo".to_string(),
+            index: 0,
+            name: "Card 1".to_string(),
+            side: Some(TemplateSide::Answer),
+            position: Some(TextPosition {
+                line: 1,
+                column: 28
+            }),
+        }
+    );
+    assert_eq!(diagnostics[0].message, "no field called 'Bakc'");
+}
@@ -1,6 +1,9 @@
 //! Note integration tests
 
-use genanki_rs_rev::{Error, Field, Model, Note, Template, basic_model, cloze_model};
+use genanki_rs_rev::{
+    Burial, Error, Field, Flag, GuidStrategy, Model, Note, NoteBuilder, Template,
+    basic_and_reversed_card_model, basic_model, basic_optional_reversed_card_model, cloze_model,
+};
 
 #[test]
 fn test_note_creation_with_basic_model() -> Result<(), Error> {
@@ -195,3 +198,103 @@ fn test_note_format_tags() -> Result<(), Error> {
     assert!(formatted.contains("tag2"));
     Ok(())
 }
+
+#[test]
+fn test_note_builder_guid_strategy() {
+    let note = NoteBuilder::new()
+        .model(basic_model())
+        .fields(vec!["Question", "Answer"])
+        .guid_strategy(GuidStrategy::Genanki)
+        .build()
+        .unwrap();
+    assert_eq!(note.guid(), "l5<zIi`lY5");
+    assert!(GuidStrategy::Genanki.is_valid(note.guid()));
+    assert_eq!(note.guid_strategy(), GuidStrategy::Genanki);
+    assert!(note.has_valid_guid());
+
+    let note = NoteBuilder::new()
+        .model(basic_model())
+        .fields(vec!["Question", "Answer"])
+        .guid_strategy(GuidStrategy::AnkiRandom)
+        .guid("fixed")
+        .build()
+        .unwrap();
+    assert_eq!(note.guid(), "fixed");
+}
+
+#[test]
+fn test_note_builder_sort_field_value() {
+    let note = NoteBuilder::new()
+        .model(basic_model())
+        .fields(vec!["Question", "Answer"])
+        .sort_field_value("<b>custom</b>")
+        .build()
+        .unwrap();
+    assert_eq!(note.sort_field(), "custom");
+}
+
+#[test]
+fn test_note_render_card() -> Result<(), Error> {
+    let note = Note::new(basic_and_reversed_card_model(), vec!["Front", "Back"])?;
+    let reverse = note.render_card(1, "Languages")?;
+    assert_eq!(reverse.question, "Back");
+    assert!(reverse.answer.starts_with("Back"));
+    assert!(reverse.answer.ends_with("Front"));
+    assert!(
+        reverse
+            .answer_html()
+            .contains(&basic_and_reversed_card_model().css)
+    );
+    Ok(())
+}
+
+#[test]
+fn test_note_builder_card_settings() {
+    let note = NoteBuilder::new()
+        .model(basic_and_reversed_card_model())
+        .fields(vec!["Front", "Back"])
+        .flag(0, Flag::Red)
+        .bury("Card 2", Burial::Manual)
+        .card_deck("Card 2", 2059400130)
+        .build()
+        .unwrap();
+    let cards = note.cards();
+    assert_eq!(cards[0].flag(), Some(Flag::Red));
+    assert_eq!((cards[0].buried(), cards[0].deck_id()), (None, None));
+    assert_eq!(cards[1].flag(), None);
+    assert_eq!(cards[1].buried(), Some(Burial::Manual));
+    assert_eq!(cards[1].deck_id(), Some(2059400130));
+
+    let unknown = NoteBuilder::new()
+        .model(basic_model())
+        .fields(vec!["Front", "Back"])
+        .flag("Reverse", Flag::Blue)
+        .build();
+    assert!(unknown.is_err());
+
+    let out_of_range = NoteBuilder::new()
+        .model(basic_and_reversed_card_model())
+        .fields(vec!["Front", "Back"])
+        .flag(5, Flag::Red)
+        .build();
+    assert!(out_of_range.is_err());
+
+    // The reverse template exists but produces no card without "Add Reverse"
+    let no_card = NoteBuilder::new()
+        .model(basic_optional_reversed_card_model())
+        .fields(vec!["Front", "Back", ""])
+        .flag(1, Flag::Red)
+        .build()
+        .unwrap();
+    assert_eq!(no_card.cards().len(), 1);
+    assert_eq!(no_card.cards()[0].flag(), None);
+
+    let cloze = NoteBuilder::new()
+        .model(cloze_model())
+        .fields(vec!["{{c1::a}} {{c2::b}}"])
+        .flag(1, Flag::Green)
+        .build()
+        .unwrap();
+    assert_eq!(cloze.cards()[0].flag(), None);
+    assert_eq!(cloze.cards()[1].flag(), Some(Flag::Green));
+}
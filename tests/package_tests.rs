@@ -1,6 +1,7 @@
 //! Package integration tests
 
-use genanki_rs_rev::{Deck, MediaFiles, Package};
+use genanki_rs_rev::core::Location;
+use genanki_rs_rev::{Deck, MediaFiles, Note, Package, PackageWriter, Severity, basic_model};
 
 #[test]
 fn test_package_new_with_deck() {
@@ -90,3 +91,151 @@ fn test_media_files_files() {
     let files = media.files();
     assert!(files.contains_key("test.mp3"));
 }
+
+#[test]
+fn test_package_validate_reports_across_decks() {
+    let note = Note::new(
+        basic_model(),
+        vec!["<img src=\"flag.png\">", "[sound:anthem.mp3]"],
+    )
+    .unwrap();
+    let guid = note.guid().to_string();
+    let mut first = Deck::new(1234, "First", "");
+    first.add_note(note.clone());
+    let mut second = Deck::new(1234, "Second", "");
+    second.add_note(note);
+
+    let mut media = std::collections::HashMap::new();
+    media.insert("flag.png".to_string(), vec![0]);
+    let report = Package::new(vec![first, second], media).unwrap().validate();
+
+    let found: Vec<(Severity, Location)> = report
+        .diagnostics()
+        .iter()
+        .map(|d| (d.severity, d.location.clone()))
+        .collect();
+    let note_location = |deck: &str, field: Option<&str>| Location::Note {
+        deck: deck.to_string(),
+        index: 0,
+        guid: guid.clone(),
+        field: field.map(str::to_string),
+    };
+    assert_eq!(
+        found,
+        vec![
+            (
+                Severity::Error,
+                Location::Deck {
+                    id: 1234,
+                    name: "Second".to_string()
+                }
+            ),
+            (Severity::Error, note_location("Second", None)),
+            (Severity::Error, note_location("First", Some("Back"))),
+            (Severity::Error, note_location("Second", Some("Back"))),
+        ]
+    );
+    assert_eq!(
+        report.diagnostics()[1].message,
+        "duplicate GUID, also used by note 0 of deck 'First'"
+    );
+    assert_eq!(
+        report.diagnostics()[2].message,
+        "references missing media file 'anthem.mp3'"
+    );
+}
+
+#[test]
+fn test_package_validate_clean() {
+    let mut deck = Deck::new(1234, "Deck", "");
+    deck.add_note(
+        Note::new(basic_model(), vec!["Q", "A"])
+            .unwrap()
+            .with_tag("geo"),
+    );
+    let report = Package::new(vec![deck], std::collections::HashMap::new())
+        .unwrap()
+        .validate();
+    assert!(report.is_empty(), "{report}");
+}
+
+#[test]
+fn test_package_writer_media_dir() {
+    let dir = tempfile::TempDir::new().unwrap();
+    std::fs::write(dir.path().join("fr.png"), [1, 2]).unwrap();
+    std::fs::write(dir.path().join("_font.ttf"), [3]).unwrap();
+    std::fs::write(dir.path().join("unused.png"), [4]).unwrap();
+
+    let model = basic_model().css("@font-face { font-family: f; src: url(_font.ttf); }");
+    let mut deck = Deck::new(1234, "Flags", "");
+    deck.add_note(Note::new(model.clone(), vec!["<img src=\"fr.png\">", "France"]).unwrap());
+    deck.add_note(Note::new(model, vec!["[sound:de.mp3]", "Germany"]).unwrap());
+
+    let package = PackageWriter::new()
+        .media_dir(dir.path())
+        .build(vec![deck])
+        .unwrap();
+    assert_eq!(package.media().names(), vec!["_font.ttf", "fr.png"]);
+    assert_eq!(
+        package.media().sources()["fr.png"].as_path(),
+        Some(dir.path().join("fr.png").as_path())
+    );
+    assert!(package.media_files().is_empty());
+
+    let report = package.validate();
+    let errors: Vec<&str> = report.errors().map(|d| d.message.as_str()).collect();
+    assert_eq!(errors, vec!["references missing media file 'de.mp3'"]);
+}
+
+#[test]
+fn test_package_validate_unused_media() {
+    let mut deck = Deck::new(1234, "Deck", "");
+    deck.add_note(Note::new(basic_model(), vec!["Q", "A"]).unwrap());
+    let mut media = std::collections::HashMap::new();
+    media.insert("old.png".to_string(), vec![0]);
+    media.insert("_shared.js".to_string(), vec![0]);
+    let report = Package::new(vec![deck], media).unwrap().validate();
+
+    assert!(!report.has_errors());
+    let warnings: Vec<String> = report.warnings().map(ToString::to_string).collect();
+    assert_eq!(
+        warnings,
+        vec!["warning: media file 'old.png': not referenced by any note, template or CSS"]
+    );
+}
+
+#[test]
+fn test_package_writer_normalize_media() {
+    let dir = tempfile::TempDir::new().unwrap();
+    std::fs::write(dir.path().join("fr.png"), [1, 2]).unwrap();
+    std::fs::write(dir.path().join("france.png"), [1, 2]).unwrap();
+
+    let mut deck = Deck::new(1234, "Flags", "");
+    deck.add_note(
+        Note::new(
+            basic_model(),
+            vec!["<img src=\"france.png\"> <img src=fr.png>", "France"],
+        )
+        .unwrap(),
+    );
+    deck.add_note(Note::new(basic_model(), vec!["[sound:de:1.mp3]", "Germany"]).unwrap());
+
+    let mut writer = PackageWriter::new().normalize_media(true);
+    writer
+        .add_media("france.png", dir.path().join("france.png"))
+        .unwrap();
+    writer
+        .add_media("fr.png", dir.path().join("fr.png"))
+        .unwrap();
+    writer.add_media_reader("de:1.mp3", &[3u8][..]);
+    let package = writer.build(vec![deck]).unwrap();
+
+    assert_eq!(package.media().names(), vec!["de1.mp3", "fr.png"]);
+    let notes = package.decks()[0].notes();
+    assert_eq!(
+        notes[0].fields()[0],
+        "<img src=\"fr.png\"> <img src=fr.png>"
+    );
+    assert_eq!(notes[1].fields()[0], "[sound:de1.mp3]");
+    assert!(package.validate().is_empty());
+}